@@ -0,0 +1,197 @@
+//! The Shoemake Arcball camera used for 3D scenes, brought in from the arcball crate
+//! so its orientation can be saved and restored.
+
+use cgmath::prelude::*;
+use cgmath::{self, Deg, Matrix4, Quaternion, Vector2, Vector3};
+
+use point;
+
+/// The axis aligned views the camera can be snapped to, named by the side of the
+/// scene they look at it from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AxisView {
+    Front,
+    Back,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl AxisView {
+    /// Get the rotation applied to the scene to view it from this side, for a camera
+    /// looking down -z with y up
+    pub fn rotation(&self) -> Quaternion<f32> {
+        match *self {
+            AxisView::Front => Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            AxisView::Back => Quaternion::from_angle_y(Deg(180.0)),
+            AxisView::Top => Quaternion::from_angle_x(Deg(90.0)),
+            AxisView::Bottom => Quaternion::from_angle_x(Deg(-90.0)),
+            AxisView::Left => Quaternion::from_angle_y(Deg(90.0)),
+            AxisView::Right => Quaternion::from_angle_y(Deg(-90.0)),
+        }
+    }
+}
+
+/// The Shoemake Arcball camera.
+pub struct ArcballCamera {
+    look_at: Matrix4<f32>,
+    translation: Matrix4<f32>,
+    rotation: Quaternion<f32>,
+    camera: Matrix4<f32>,
+    motion_speed: f32,
+    zoom_speed: f32,
+    inv_screen: [f32; 2],
+}
+
+impl ArcballCamera {
+    /// Create a new Arcball camera starting from the look at matrix `look_at`. The `motion_speed`
+    /// sets the speed for panning and `zoom_speed` the speed for zooming the camera. `screen` should be
+    /// `[screen_width, screen_height]`.
+    pub fn new(look_at: &Matrix4<f32>, motion_speed: f32, zoom_speed: f32, screen: [f32; 2]) -> ArcballCamera {
+        ArcballCamera::from_state(look_at, &Transform::one(), &Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                                  motion_speed, zoom_speed, screen)
+    }
+    /// Create an Arcball camera with the `translation` and `rotation` applied to `look_at`,
+    /// e.g. to restore a camera from its `state`.
+    pub fn from_state(look_at: &Matrix4<f32>, translation: &Matrix4<f32>, rotation: &Quaternion<f32>,
+                      motion_speed: f32, zoom_speed: f32, screen: [f32; 2]) -> ArcballCamera {
+        let mut camera = ArcballCamera {
+            look_at: *look_at,
+            translation: *translation,
+            rotation: *rotation,
+            camera: *look_at,
+            motion_speed: motion_speed,
+            zoom_speed: zoom_speed,
+            inv_screen: [1.0 / screen[0], 1.0 / screen[1]],
+        };
+        camera.update_camera();
+        camera
+    }
+    /// Reset the camera to start from `look_at` again, undoing any rotation, panning and zooming
+    pub fn reset(&mut self, look_at: &Matrix4<f32>) {
+        self.look_at = *look_at;
+        self.translation = Transform::one();
+        self.rotation = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        self.update_camera();
+    }
+    /// Get the translation and rotation of the camera from its look at matrix
+    #[allow(dead_code)]
+    pub fn state(&self) -> (Matrix4<f32>, Quaternion<f32>) {
+        (self.translation, self.rotation)
+    }
+    /// Snap the camera to view the scene from the `view` side, keeping the current zoom and pan
+    pub fn snap_to(&mut self, view: AxisView) {
+        self.rotation = view.rotation();
+        self.update_camera();
+    }
+    /// Get the view matrix computed by the camera.
+    pub fn get_mat4(&self) -> Matrix4<f32> {
+        self.camera
+    }
+    /// Rotate the camera, mouse positions should be in pixel coordinates.
+    ///
+    /// Rotates from the orientation at the previous mouse position specified by `mouse_prev`
+    /// to the orientation at the current mouse position, `mouse_cur`.
+    pub fn rotate(&mut self, mouse_prev: Vector2<f32>, mouse_cur: Vector2<f32>) {
+        let m_cur = Vector2::new(point::clamp(mouse_cur.x * 2.0 * self.inv_screen[0] - 1.0, -1.0, 1.0),
+                                 point::clamp(1.0 - 2.0 * mouse_cur.y * self.inv_screen[1], -1.0, 1.0));
+        let m_prev = Vector2::new(point::clamp(mouse_prev.x * 2.0 * self.inv_screen[0] - 1.0, -1.0, 1.0),
+                                  point::clamp(1.0 - 2.0 * mouse_prev.y * self.inv_screen[1], -1.0, 1.0));
+        let mouse_cur_ball = ArcballCamera::screen_to_arcball(m_cur);
+        let mouse_prev_ball = ArcballCamera::screen_to_arcball(m_prev);
+        self.rotation = mouse_cur_ball * mouse_prev_ball * self.rotation;
+        self.update_camera();
+    }
+    /// Zoom the camera in by some amount. Positive values zoom in, negative zoom out.
+    pub fn zoom(&mut self, amount: f32, elapsed: f32) {
+        let motion = Vector3::new(0.0, 0.0, amount);
+        self.translation = Matrix4::from_translation(motion * self.zoom_speed * elapsed) * self.translation;
+        self.update_camera();
+    }
+    /// Pan the camera following the motion of the mouse. The mouse delta should be in pixels.
+    pub fn pan(&mut self, mouse_delta: Vector2<f32>, elapsed: f32) {
+        let motion = Vector3::new(mouse_delta.x, mouse_delta.y, 0.0) * self.motion_speed * elapsed;
+        self.translation = Matrix4::from_translation(motion) * self.translation;
+        self.update_camera();
+    }
+    /// Update the screen dimensions, e.g. if the window has resized.
+    pub fn update_screen(&mut self, width: f32, height: f32) {
+        self.inv_screen[0] = 1.0 / width;
+        self.inv_screen[1] = 1.0 / height;
+    }
+    /// Recompute the view matrix from the translation, look at and rotation
+    fn update_camera(&mut self) {
+        self.camera = self.translation * self.look_at * Matrix4::from(self.rotation);
+    }
+    fn screen_to_arcball(p: Vector2<f32>) -> Quaternion<f32> {
+        let dist = cgmath::dot(p, p);
+        // If we're on/in the sphere return the point on it
+        if dist <= 1.0 {
+            Quaternion::new(0.0, p.x, p.y, f32::sqrt(1.0 - dist))
+        } else {
+            let unit_p = p.normalize();
+            Quaternion::new(0.0, unit_p.x, unit_p.y, 0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cgmath::Point3;
+
+    #[test]
+    fn from_state_restores_camera() {
+        let look_at = Matrix4::look_at(Point3::new(0.0, 0.0, 6.0), Point3::new(0.0, 0.0, 0.0),
+                                       Vector3::new(0.0, 1.0, 0.0));
+        let mut camera = ArcballCamera::new(&look_at, 1.0, 5.0, [640.0, 480.0]);
+        camera.rotate(Vector2::new(320.0, 240.0), Vector2::new(400.0, 200.0));
+        camera.pan(Vector2::new(12.0, -4.0), 0.16);
+        camera.zoom(-1.5, 0.16);
+
+        let (translation, rotation) = camera.state();
+        let restored = ArcballCamera::from_state(&look_at, &translation, &rotation, 1.0, 5.0, [640.0, 480.0]);
+        assert_eq!(restored.get_mat4(), camera.get_mat4());
+        // A fresh camera is just the look at matrix
+        assert_eq!(ArcballCamera::new(&look_at, 1.0, 5.0, [640.0, 480.0]).get_mat4(), look_at);
+    }
+
+    #[test]
+    fn reset_restores_initial_camera() {
+        let look_at = Matrix4::look_at(Point3::new(0.0, 0.0, 6.0), Point3::new(0.0, 0.0, 0.0),
+                                       Vector3::new(0.0, 1.0, 0.0));
+        let mut camera = ArcballCamera::new(&look_at, 1.0, 5.0, [640.0, 480.0]);
+        camera.rotate(Vector2::new(320.0, 240.0), Vector2::new(400.0, 200.0));
+        camera.pan(Vector2::new(12.0, -4.0), 0.16);
+        camera.zoom(-1.5, 0.16);
+        assert!(camera.get_mat4() != look_at);
+
+        camera.reset(&look_at);
+        let fresh = ArcballCamera::new(&look_at, 1.0, 5.0, [640.0, 480.0]);
+        assert_eq!(camera.get_mat4(), fresh.get_mat4());
+        assert_eq!(camera.state(), fresh.state());
+    }
+
+    #[test]
+    fn snap_to_axis_views() {
+        let look_at = Matrix4::look_at(Point3::new(0.0, 0.0, 6.0), Point3::new(0.0, 0.0, 0.0),
+                                       Vector3::new(0.0, 1.0, 0.0));
+        let mut camera = ArcballCamera::new(&look_at, 1.0, 5.0, [640.0, 480.0]);
+        camera.rotate(Vector2::new(320.0, 240.0), Vector2::new(400.0, 200.0));
+        camera.pan(Vector2::new(12.0, -4.0), 0.16);
+        let translation = camera.state().0;
+        let views = [(AxisView::Front, Vector3::new(0.0, 0.0, -1.0)), (AxisView::Back, Vector3::new(0.0, 0.0, 1.0)),
+                     (AxisView::Top, Vector3::new(0.0, -1.0, 0.0)), (AxisView::Bottom, Vector3::new(0.0, 1.0, 0.0)),
+                     (AxisView::Left, Vector3::new(1.0, 0.0, 0.0)), (AxisView::Right, Vector3::new(-1.0, 0.0, 0.0))];
+        for &(view, dir) in views.iter() {
+            camera.snap_to(view);
+            // The view direction in world space is the camera's -z axis taken back through the view matrix
+            let inv_view = camera.get_mat4().invert().unwrap();
+            let view_dir = inv_view.transform_vector(Vector3::new(0.0, 0.0, -1.0));
+            assert!((view_dir - dir).magnitude() < 1e-5, "{:?} looks along {:?}", view, view_dir);
+            // The zoom and pan are kept
+            assert_eq!(camera.state().0, translation);
+        }
+    }
+}
@@ -7,6 +7,8 @@ use std::fmt::Debug;
 use std::slice::Iter;
 use std::f32;
 
+use point::{Point, HomogeneousPoint};
+
 /// The interpolate trait is used to linearly interpolate between two types (or in the
 /// case of Quaternions, spherically linearly interpolate). The B-spline curve uses this
 /// trait to compute points on the curve for the given parameter value.
@@ -99,6 +101,51 @@ impl<T: Interpolate + ProjectToSegment + Copy + Debug> Bezier<T> {
             nearest.0 + 1
         }
     }
+    /// Split the curve at `t` into two curves which together trace out the same shape,
+    /// the first covers `[0, t]` and the second `[t, 1]`. The control points of the halves
+    /// are the outer edges of the de Casteljau triangle computed to evaluate the point at `t`.
+    pub fn subdivide(&self, t: f32) -> (Bezier<T>, Bezier<T>) {
+        debug_assert!(t >= 0.0 && t <= 1.0);
+        let r = self.control_points.len() - 1;
+        let mut tmp = self.control_points.clone();
+        let mut left = Vec::with_capacity(r + 1);
+        let mut right = Vec::with_capacity(r + 1);
+        left.push(tmp[0]);
+        right.push(tmp[r]);
+        for lvl in 0..r {
+            for i in 0..r - lvl {
+                tmp[i] = tmp[i].interpolate(&tmp[i + 1], t);
+            }
+            left.push(tmp[0]);
+            right.push(tmp[r - lvl - 1]);
+        }
+        right.reverse();
+        (Bezier::new(left), Bezier::new(right))
+    }
+    /// Raise the degree of the curve by one without changing its shape, this adds a
+    /// control point with the new points interpolated along the old control polygon.
+    pub fn elevate_degree(&mut self) {
+        let n = self.control_points.len() - 1;
+        let mut control_points = Vec::with_capacity(n + 2);
+        control_points.push(self.control_points[0]);
+        for i in 1..n + 1 {
+            let alpha = i as f32 / (n + 1) as f32;
+            control_points.push(self.control_points[i].interpolate(&self.control_points[i - 1], alpha));
+        }
+        control_points.push(self.control_points[n]);
+        self.control_points = control_points;
+    }
+    /// Compute each level of de Casteljau's algorithm for the point at `t`, starting with
+    /// the control points and ending with a level holding just the point on the curve.
+    pub fn de_casteljau_steps(&self, t: f32) -> Vec<Vec<T>> {
+        debug_assert!(t >= 0.0 && t <= 1.0);
+        let mut levels = vec![self.control_points.clone()];
+        for lvl in 0..self.control_points.len() - 1 {
+            let next = levels[lvl].windows(2).map(|x| x[0].interpolate(&x[1], t)).collect();
+            levels.push(next);
+        }
+        levels
+    }
     /// Iteratively use de Casteljau's algorithm to compute the desired point
     fn de_casteljau(&self, t: f32, r: usize) -> T {
         let mut tmp = self.control_points.clone();
@@ -111,3 +158,88 @@ impl<T: Interpolate + ProjectToSegment + Copy + Debug> Bezier<T> {
     }
 }
 
+
+impl Bezier<HomogeneousPoint> {
+    /// Create a rational Bezier curve from the control points and their weights, the
+    /// control points are stored as homogeneous points pre-multiplied by the weight.
+    pub fn rational(points: &[Point], weights: &[f32]) -> Bezier<HomogeneousPoint> {
+        assert_eq!(points.len(), weights.len());
+        Bezier::new(points.iter().zip(weights.iter()).map(|(p, w)| HomogeneousPoint::new(*p, *w)).collect())
+    }
+    /// Compute a point on the rational curve at `t`, de Casteljau's algorithm is run on
+    /// the homogeneous control points and the result projected back to 3D.
+    pub fn rational_point(&self, t: f32) -> Point {
+        self.point(t).project()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cubic() -> Bezier<Point> {
+        Bezier::new(vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.5),
+                         Point::new(3.0, 2.0, -0.5), Point::new(4.0, 0.0, 1.0)])
+    }
+
+    fn assert_near(a: Point, b: Point) {
+        assert!((a - b).length() < 1e-5, "expected {:?}, got {:?}", b, a);
+    }
+
+    #[test]
+    fn subdivide_halves_trace_curve() {
+        let curve = cubic();
+        let split = 0.3;
+        let (left, right) = curve.subdivide(split);
+        assert_eq!(left.control_points().count(), 4);
+        assert_eq!(right.control_points().count(), 4);
+        for i in 0..11 {
+            let u = i as f32 / 10.0;
+            assert_near(left.point(u), curve.point(u * split));
+            assert_near(right.point(u), curve.point(split + u * (1.0 - split)));
+        }
+    }
+
+    #[test]
+    fn elevate_degree_keeps_shape() {
+        let curve = cubic();
+        let mut elevated = curve.clone();
+        elevated.elevate_degree();
+        elevated.elevate_degree();
+        assert_eq!(elevated.control_points().count(), 6);
+        assert_near(*elevated.control_points().next().unwrap(), curve.control_points[0]);
+        assert_near(*elevated.control_points().last().unwrap(), curve.control_points[3]);
+        for i in 0..11 {
+            let t = i as f32 / 10.0;
+            assert_near(elevated.point(t), curve.point(t));
+        }
+    }
+
+    #[test]
+    fn de_casteljau_steps_end_at_point() {
+        let curve = cubic();
+        for i in 0..11 {
+            let t = i as f32 / 10.0;
+            let steps = curve.de_casteljau_steps(t);
+            assert_eq!(steps.len(), 4);
+            assert_eq!(steps[0].len(), 4);
+            let last = steps.last().unwrap();
+            assert_eq!(last.len(), 1);
+            assert_near(last[0], curve.point(t));
+        }
+    }
+
+    #[test]
+    fn rational_quadratic_circular_arc() {
+        // A quarter of the unit circle, the middle control point is weighted by cos(45)
+        let points = [Point::new(1.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0), Point::new(0.0, 1.0, 0.0)];
+        let curve = Bezier::rational(&points, &[1.0, f32::consts::FRAC_1_SQRT_2, 1.0]);
+        for i in 0..11 {
+            let t = i as f32 / 10.0;
+            let p = curve.rational_point(t);
+            assert!((p.length() - 1.0).abs() < 1e-5, "{:?} at t = {} is not on the circle", p, t);
+        }
+        let mid = curve.rational_point(0.5);
+        assert!((mid.pos[0] - mid.pos[1]).abs() < 1e-5);
+    }
+}
@@ -2,9 +2,17 @@ use std::fmt::Debug;
 use std::slice::Iter;
 use std::f32;
 use std::iter;
+use std::cmp;
+use std::mem;
 use std::slice;
+use std::ops::{Mul, Sub};
 
-use bezier::{Interpolate, ProjectToSegment};
+use rulinalg::matrix::Matrix;
+use rulinalg::vector::Vector;
+
+use bezier::{Bezier, Interpolate, ProjectToSegment};
+use bspline_basis::BSplineBasis;
+use point::{self, Point};
 
 /// Represents a B-spline curve that will use polynomials of the specified degree
 /// to interpolate between the control points given the knots.
@@ -16,13 +24,17 @@ pub struct BSpline<T> {
     pub control_points: Vec<T>,
     /// The knot vector
     knots: Vec<f32>,
+    /// If the knot vector should be regenerated when adding or removing points, otherwise
+    /// a single knot is added or removed keeping the existing non-uniform knots.
+    regenerate_knots: bool,
 }
 
 impl<T: Interpolate + Copy + Debug> BSpline<T> {
     /// Create a new B-spline curve of the desired `degree` that will interpolate
     /// the `control_points` using the `knots`. The knots should be sorted in non-decreasing
     /// order otherwise they will be sorted for you, which may lead to undesired knots
-    /// for control points. Note that here we use the interpolating polynomial degree,
+    /// for control points. Use `bspline_basis::unsorted_knots` to check for out of order
+    /// knots first. Note that here we use the interpolating polynomial degree,
     /// if you're familiar with the convention of "B-spline curve order" the degree is `curve_order - 1`.
     ///
     /// Your curve must have a valid number of control points and knots or the function will panic. A B-spline
@@ -37,7 +49,8 @@ impl<T: Interpolate + Copy + Debug> BSpline<T> {
                 control_points.len() + degree + 1));
         }
         knots.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        let mut spline = BSpline { degree: degree, control_points: control_points, knots: knots };
+        let mut spline = BSpline { degree: degree, control_points: control_points, knots: knots,
+                                   regenerate_knots: false };
         if spline.knots.is_empty() {
             spline.fill_knot_vector(true, true);
         }
@@ -45,23 +58,83 @@ impl<T: Interpolate + Copy + Debug> BSpline<T> {
     }
     /// Create a new empty BSpline.
     pub fn empty() -> BSpline<T> {
-        BSpline { degree: 0, control_points: Vec::new(), knots: Vec::new() }
+        BSpline { degree: 0, control_points: Vec::new(), knots: Vec::new(), regenerate_knots: false }
+    }
+    /// Check that the degree, control points and knots of the curve are consistent, returning
+    /// a message describing the problem if not. Empty curves are valid.
+    pub fn validate(&self) -> Result<(), String> {
+        let n = self.control_points.len();
+        if n == 0 {
+            return Ok(());
+        }
+        if n <= self.degree {
+            return Err(format!("Too few control points for a degree {} curve, got {} but need at least {}",
+                               self.degree, n, self.degree + 1));
+        }
+        if self.knots.len() != n + self.degree + 1 {
+            return Err(format!("Invalid number of knots, got {}, expected {}",
+                               self.knots.len(), n + self.degree + 1));
+        }
+        if self.knots.iter().any(|k| !k.is_finite()) || self.knots.windows(2).any(|k| k[1] < k[0]) {
+            return Err(String::from("Knots must be finite and non-decreasing"));
+        }
+        let domain = self.knot_domain();
+        if domain.1 <= domain.0 {
+            return Err(format!("The knot domain [{}, {}] is empty", domain.0, domain.1));
+        }
+        Ok(())
     }
-    /// Compute a point on the curve at `t`, the parameter **must** be in the inclusive range
-    /// of values returned by `knot_domain`. If `t` is out of bounds this function will assert
-    /// on debug builds and on release builds you'll likely get an out of bounds crash.
+    /// Compute a point on the curve at `t`, the parameter should be in the inclusive range
+    /// of values returned by `knot_domain`. Values of `t` outside the domain are clamped to it,
+    /// so small overshoots from accumulating float error when sampling the curve are safe.
     pub fn point(&self, t: f32) -> T {
-        debug_assert!(t >= self.knot_domain().0 && t <= self.knot_domain().1);
-        // Find the first index with a knot value greater than the t we're searching for. We want
-        // to find i such that: knot[i] <= t < knot[i + 1]
-        let i = match upper_bounds(&self.knots[..], t) {
-            Some(x) if x == 0 => self.degree,
-            Some(x) if x >= self.knots.len() - self.degree - 1 =>
-                self.knots.len() - self.degree - 1,
-            Some(x) => x,
-            None => self.knots.len() - self.degree - 1,
-        };
-        self.de_boor_iterative(t, i)
+        let (t, i) = self.find_span(t);
+        // Step and polyline curves are common for imported data, so they skip de Boor's algorithm
+        match self.degree {
+            0 => self.control_points[i - 1],
+            1 => {
+                let alpha = knot_alpha(t, self.knots[i - 1], self.knots[i]);
+                self.control_points[i - 2].interpolate(&self.control_points[i - 1], alpha)
+            },
+            _ => self.de_boor_iterative(t, i),
+        }
+    }
+    /// Evaluate the curve at the Greville abscissa of its knots, giving a point on the curve
+    /// for each control point. Abscissa outside the knot domain of unclamped curves are
+    /// skipped, and degree 0 curves have no abscissa.
+    pub fn greville_points(&self) -> Vec<T> {
+        if self.degree == 0 {
+            return Vec::new();
+        }
+        BSplineBasis::new(self.degree, self.knots.clone()).greville_abscissa().iter()
+            .map(|t| self.point(*t)).collect()
+    }
+    /// Get the curve as a function of `t` evaluating `point`, to pass the curve to
+    /// generic numerical routines taking a function of one parameter.
+    pub fn as_fn<'a>(&'a self) -> impl Fn(f32) -> T + 'a {
+        move |t| self.point(t)
+    }
+    /// Compute each level of de Boor's algorithm for the point at `t`, starting with the
+    /// control points influencing the point and ending with a level holding just the
+    /// point on the curve. `t` is clamped to the knot domain as in `point`.
+    pub fn de_boor_steps(&self, t: f32) -> Vec<Vec<T>> {
+        let (t, i_start) = self.find_span(t);
+        let mut levels = Vec::with_capacity(self.degree + 1);
+        levels.push((0..self.degree + 1).map(|j| self.control_points[j + i_start - self.degree - 1])
+                    .collect::<Vec<T>>());
+        for lvl in 0..self.degree {
+            let k = lvl + 1;
+            let next = {
+                let prev = &levels[lvl];
+                (0..self.degree - lvl).map(|j| {
+                    let i = j + k + i_start - self.degree;
+                    let alpha = knot_alpha(t, self.knots[i - 1], self.knots[i + self.degree - k]);
+                    prev[j].interpolate(&prev[j + 1], alpha)
+                }).collect()
+            };
+            levels.push(next);
+        }
+        levels
     }
     /// Get an iterator over the control points.
     pub fn control_points(&self) -> Iter<T> {
@@ -77,8 +150,7 @@ impl<T: Interpolate + Copy + Debug> BSpline<T> {
     }
     /// Get the min and max knot domain values for finding the `t` range to compute
     /// the curve over. The curve is only defined over the inclusive range `[min, max]`,
-    /// passing a `t` value outside of this range will result in an assert on debug builds
-    /// and likely a crash on release builds.
+    /// `t` values passed to `point` outside of this range are clamped to it.
     pub fn knot_domain(&self) -> (f32, f32) {
         (self.knots[self.degree], self.knots[self.knots.len() - 1 - self.degree])
     }
@@ -86,6 +158,29 @@ impl<T: Interpolate + Copy + Debug> BSpline<T> {
     pub fn knot_domain_iter(&self) -> iter::Take<iter::Skip<slice::Iter<f32>>> {
         self.knots.iter().skip(self.degree).take(self.knots.len() - 2 * self.degree)
     }
+    /// Reverse the direction of the curve so it's traced from its end to its start, the
+    /// knots are mirrored within the range of the knot vector.
+    pub fn reverse(&mut self) {
+        self.control_points.reverse();
+        if let (Some(&a), Some(&b)) = (self.knots.first(), self.knots.last()) {
+            self.knots = self.knots.iter().rev().map(|k| a + b - k).collect();
+        }
+    }
+    /// Affinely remap the knot vector so the knot domain becomes `[0, 1]`, the control
+    /// points are untouched so the curve's shape doesn't change, just its parameterization.
+    pub fn normalize_domain(&mut self) {
+        if self.control_points.is_empty() {
+            return;
+        }
+        let (a, b) = self.knot_domain();
+        if b <= a {
+            return;
+        }
+        // The ends of the domain are set exactly so they don't pick up rounding error
+        for k in self.knots.iter_mut() {
+            *k = if *k == a { 0.0 } else if *k == b { 1.0 } else { (*k - a) / (b - a) };
+        }
+    }
     /// Get the max degree of curve that this set of control points can support
     pub fn max_possible_degree(&self) -> usize {
         if self.control_points.is_empty() {
@@ -97,29 +192,180 @@ impl<T: Interpolate + Copy + Debug> BSpline<T> {
     /// Change the degree of the curve
     pub fn set_degree(&mut self, degree: usize) {
         assert!(degree <= self.max_possible_degree());
-        let was_clamped = self.is_clamped();
+        let (left_clamped, right_clamped) = self.end_conditions();
         self.degree = degree;
-        self.fill_knot_vector(was_clamped, was_clamped);
+        self.fill_knot_vector(left_clamped, right_clamped);
+    }
+    /// Insert a point into the curve at `index` in the control points, shifting the
+    /// points after it up. A knot is inserted for the new point keeping the existing knots,
+    /// unless the knots are uniform or set to be regenerated on edit.
+    pub fn insert_point_at(&mut self, t: T, index: usize) {
+        assert!(index <= self.control_points.len());
+        self.control_points.insert(index, t);
+        self.update_knots_for_insert(index);
+    }
+    /// Add a point to the end of the curve, returns the index it was added at
+    pub fn append_point(&mut self, t: T) -> usize {
+        let index = self.control_points.len();
+        self.insert_point_at(t, index);
+        index
+    }
+    /// Set whether the knot vector is regenerated as a uniform one when adding or removing
+    /// points. By default the existing knots are kept, preserving the curve's parameterization.
+    pub fn set_regenerate_knots(&mut self, regenerate: bool) {
+        self.regenerate_knots = regenerate;
+    }
+    pub fn regenerates_knots(&self) -> bool {
+        self.regenerate_knots
     }
     /// Remove a point from the curve
     pub fn remove_point(&mut self, i: usize) {
         self.control_points.remove(i);
+        let p = self.degree;
         if self.control_points.len() <= self.degree {
             self.degree -= 1;
         }
-        self.generate_knot_vector();
+        // Remove the interior knot nearest the removed point's influence, if the degree
+        // changed we have to regenerate the knots.
+        let n = self.control_points.len();
+        if self.regenerate_knots || self.degree != p || self.knots.len() != n + p + 2
+            || self.has_uniform_knots()
+        {
+            self.generate_knot_vector();
+            return;
+        }
+        let j = cmp::min(cmp::max(i + (p + 1) / 2, p + 1), self.knots.len() - p - 2);
+        self.knots.remove(j);
     }
     /// Toggle whether the curve should be open/clamped (Elaine: floating/open)
     pub fn set_clamped(&mut self, clamped: bool) {
         self.fill_knot_vector(clamped, clamped);
     }
     pub fn is_clamped(&self) -> bool {
-        let left_clamped = self.knots.iter().take(self.degree + 1)
-            .fold(true, |acc, x| *x == self.knots[0] && acc);
-        let right_clamped = self.knots.iter().rev().take(self.degree + 1)
-            .fold(true, |acc, x| *x == self.knots[self.knots.len() - 1] && acc);
+        let (left_clamped, right_clamped) = self.end_conditions();
         left_clamped && right_clamped
     }
+    /// Set whether the start and end of the curve should be clamped independently,
+    /// regenerating the knot vector for the new end conditions.
+    pub fn set_end_conditions(&mut self, left_clamped: bool, right_clamped: bool) {
+        self.fill_knot_vector(left_clamped, right_clamped);
+    }
+    /// Check if the curve is clamped at its start and end, returns `(left_clamped, right_clamped)`
+    pub fn end_conditions(&self) -> (bool, bool) {
+        let distinct = self.distinct_knots();
+        let left_clamped = distinct.first().map_or(true, |k| k.1 > self.degree);
+        let right_clamped = distinct.last().map_or(true, |k| k.1 > self.degree);
+        (left_clamped, right_clamped)
+    }
+    /// Get each distinct knot value along with its multiplicity, in increasing order. Knots
+    /// within a small epsilon of each other are treated as the same knot to be robust to
+    /// floating point error in knots computed by the editing operations.
+    pub fn distinct_knots(&self) -> Vec<(f32, usize)> {
+        let mut distinct: Vec<(f32, usize)> = Vec::new();
+        if self.knots.is_empty() {
+            return distinct;
+        }
+        let eps = 1e-6 * f32::max(1.0, self.knots[self.knots.len() - 1] - self.knots[0]);
+        for k in self.knots.iter() {
+            if let Some(last) = distinct.last_mut() {
+                if (*k - last.0).abs() <= eps {
+                    last.1 += 1;
+                    continue;
+                }
+            }
+            distinct.push((*k, 1));
+        }
+        distinct
+    }
+    /// Insert the knot `u` into the curve using Boehm's algorithm, this adds a control point
+    /// without changing the shape of the curve. The knot **must** be within the knot domain.
+    /// If the knot already has a multiplicity greater than the degree nothing is inserted.
+    pub fn insert_knot(&mut self, u: f32) {
+        debug_assert!(u >= self.knot_domain().0 && u <= self.knot_domain().1);
+        // Find the span k such that knots[k] <= u < knots[k + 1]
+        let k = match upper_bounds(&self.knots[..], u) {
+            Some(x) => x - 1,
+            None => self.knots.len() - 1,
+        };
+        let p = self.degree;
+        let s = self.knots.iter().filter(|x| **x == u).count();
+        if s > p {
+            return;
+        }
+        // Only the control points P_{k - p + 1}, ..., P_{k - s} are affected by the insertion,
+        // these are replaced by points interpolated between their neighbors
+        let mut control_points = Vec::with_capacity(self.control_points.len() + 1);
+        control_points.extend_from_slice(&self.control_points[..k - p + 1]);
+        for i in k - p + 1..k - s + 1 {
+            let alpha = (u - self.knots[i]) / (self.knots[i + p] - self.knots[i]);
+            control_points.push(self.control_points[i - 1].interpolate(&self.control_points[i], alpha));
+        }
+        control_points.extend_from_slice(&self.control_points[k - s..]);
+        self.control_points = control_points;
+        self.knots.insert(k + 1, u);
+    }
+    /// Get the clamped curve with the same shape as this one over the knot domain. Floating
+    /// ends are clamped by inserting the end knot until the curve passes through a control
+    /// point there, after which the knots and control points outside the domain are dropped.
+    pub fn to_clamped(&self) -> BSpline<T> {
+        let mut curve = self.clone();
+        let p = curve.degree;
+        let (a, b) = curve.knot_domain();
+        if curve.knots.iter().filter(|x| **x == a).count() <= p {
+            while curve.knots.iter().filter(|x| **x == a).count() < p {
+                curve.insert_knot(a);
+            }
+            let l = curve.knots.iter().take_while(|x| **x < a).count();
+            curve.knots.drain(..l - 1);
+            curve.knots[0] = a;
+            curve.control_points.drain(..l - 1);
+        }
+        if curve.knots.iter().filter(|x| **x == b).count() <= p {
+            while curve.knots.iter().filter(|x| **x == b).count() < p {
+                curve.insert_knot(b);
+            }
+            let r = curve.knots.iter().rev().take_while(|x| **x > b).count();
+            let n = curve.knots.len();
+            curve.knots.truncate(n - r + 1);
+            curve.knots[n - r] = b;
+            let m = curve.control_points.len();
+            curve.control_points.truncate(m - r + 1);
+        }
+        curve
+    }
+    /// Update the knots after inserting the point at `i`, inserting a knot midway through the
+    /// non-empty knot span nearest the new point's influence so the existing knots are kept.
+    /// Uniform knot vectors are just regenerated to stay uniform.
+    fn update_knots_for_insert(&mut self, i: usize) {
+        let p = self.degree;
+        let n = self.control_points.len();
+        if self.regenerate_knots || self.knots.len() != n + p || self.knots[p] >= self.knots[n - 1]
+            || self.has_uniform_knots()
+        {
+            self.generate_knot_vector();
+            return;
+        }
+        // Spans in the domain are [knots[j - 1], knots[j]] for j in [p + 1, len - p - 1]
+        let (first, last) = (p + 1, self.knots.len() - p - 1);
+        let start = cmp::min(cmp::max(i + (p + 1) / 2, first), last);
+        let j = (0..last - first + 1)
+            .flat_map(|d| vec![start + d, start.wrapping_sub(d)])
+            .find(|&j| j >= first && j <= last && self.knots[j - 1] < self.knots[j])
+            .expect("Curve domain has no non-empty knot span");
+        let knot = 0.5 * (self.knots[j - 1] + self.knots[j]);
+        self.knots.insert(j, knot);
+    }
+    /// Check if the knots are uniformly spaced with only the ends repeated, regenerating a
+    /// uniform knot vector when editing keeps its parameterization.
+    fn has_uniform_knots(&self) -> bool {
+        let distinct = self.distinct_knots();
+        if distinct.len() < 3 {
+            return true;
+        }
+        let spacing = distinct[1].0 - distinct[0].0;
+        distinct[1..distinct.len() - 1].iter().all(|k| k.1 == 1)
+            && distinct.windows(2).all(|x| ((x[1].0 - x[0].0) - spacing).abs() <= 1e-4 * spacing)
+    }
     /// Compute the number of knots required for this curve
     fn knots_required(&self) -> usize {
         self.control_points.len() + self.degree + 1
@@ -128,10 +374,7 @@ impl<T: Interpolate + Copy + Debug> BSpline<T> {
     /// whether it was open/clamped before (Elaine: terms floating/open)
     fn generate_knot_vector(&mut self) {
         // Check if we're clamped on the left/right (Elaine calls this end condition open)
-        let left_clamped = self.knots.iter().take(self.degree + 1)
-            .fold(true, |acc, x| *x == self.knots[0] && acc);
-        let right_clamped = self.knots.iter().rev().take(self.degree + 1)
-            .fold(true, |acc, x| *x == self.knots[self.knots.len() - 1] && acc);
+        let (left_clamped, right_clamped) = self.end_conditions();
         self.fill_knot_vector(left_clamped, right_clamped);
     }
     /// Fill the knot vector for this curve for the new number of points/degree
@@ -146,6 +389,33 @@ impl<T: Interpolate + Copy + Debug> BSpline<T> {
                 }
         }
     }
+    /// Clamp `t` to the knot domain and find the knot span to evaluate it in. Returns the
+    /// clamped `t` and the first knot index `i` such that: knot[i - 1] <= t < knot[i]
+    ///
+    /// At the end of the domain there's no such knot within the domain, so `domain.1` is
+    /// evaluated at the end of the last non-empty span instead. Since the curve is continuous
+    /// this gives the correct end point for both clamped and floating knot vectors, `domain.0`
+    /// always falls in the first non-empty span and needs no special handling.
+    fn find_span(&self, t: f32) -> (f32, usize) {
+        let domain = self.knot_domain();
+        let t = point::clamp(t, domain.0, domain.1);
+        // The last non-empty knot span in the domain, the end of the domain is evaluated in
+        // this span. The last span can be empty if the end knot is repeated, e.g. after inserting it
+        let mut last_span = self.knots.len() - self.degree - 1;
+        while last_span > self.degree + 1 && self.knots[last_span - 1] == self.knots[last_span] {
+            last_span -= 1;
+        }
+        // Find the first index with a knot value greater than the t we're searching for. We want
+        // to find i such that: knot[i - 1] <= t < knot[i]
+        let i = match upper_bounds(&self.knots[..], t) {
+            // Can't happen since t >= knots[degree], but keep us from reading before the control points
+            Some(x) if x <= self.degree => self.degree + 1,
+            Some(x) if x >= last_span => last_span,
+            Some(x) => x,
+            None => last_span,
+        };
+        (t, i)
+    }
     /// Iteratively compute de Boor's B-spline algorithm, this computes the recursive
     /// de Boor algorithm tree from the bottom up. At each level we use the results
     /// from the previous one to compute this level and store the results in the
@@ -161,8 +431,7 @@ impl<T: Interpolate + Copy + Debug> BSpline<T> {
             let k = lvl + 1;
             for j in 0..self.degree - lvl {
                 let i = j + k + i_start - self.degree;
-                let alpha = (t - self.knots[i - 1]) / (self.knots[i + self.degree - k] - self.knots[i - 1]);
-                debug_assert!(!alpha.is_nan());
+                let alpha = knot_alpha(t, self.knots[i - 1], self.knots[i + self.degree - k]);
                 tmp[j] = tmp[j].interpolate(&tmp[j + 1], alpha);
             }
         }
@@ -179,10 +448,16 @@ impl<T: Interpolate + ProjectToSegment + Copy + Debug> BSpline<T> {
             self.control_points.push(t);
             return 1;
         }
+        // Segments between coincident control points have no direction to project on to, so
+        // they're skipped and the stacked points are treated as a single point
+        let segments: Vec<_> = self.control_points.windows(2).enumerate()
+            .filter(|&(_, x)| x[1].project(&x[0], &x[0]).0 >= 1e-6)
+            .map(|(i, _)| i)
+            .collect();
         // Go through all segments of the control polygon and find the nearest one
-        let nearest = self.control_points.windows(2).enumerate()
-            .map(|(i, x)| {
-                let proj = t.project(&x[0], &x[1]);
+        let nearest = segments.iter()
+            .map(|&i| {
+                let proj = t.project(&self.control_points[i], &self.control_points[i + 1]);
                 (i, proj.0, proj.1)
             })
             .fold((0, f32::MAX, 0.0), |acc, (i, d, l)| {
@@ -192,22 +467,655 @@ impl<T: Interpolate + ProjectToSegment + Copy + Debug> BSpline<T> {
                     acc
                 }
             });
-        // Check if we're appending or prepending the point
-        let idx = if nearest.0 == 0 && nearest.2 == 0.0 {
+        // Check if we're appending or prepending the point, if all the points are stacked
+        // on top of each other we just append it
+        let idx = if segments.is_empty() {
+            self.control_points.push(t);
+            self.control_points.len() - 1
+        } else if nearest.0 == segments[0] && nearest.2 == 0.0 {
             self.control_points.insert(0, t);
             0
-        } else if nearest.0 == self.control_points.len() - 2 && nearest.2 == 1.0 {
+        } else if nearest.0 == segments[segments.len() - 1] && nearest.2 == 1.0 {
             self.control_points.push(t);
             self.control_points.len() - 1
         } else {
             self.control_points.insert(nearest.0 + 1, t);
             nearest.0 + 1
         };
-        self.generate_knot_vector();
+        self.update_knots_for_insert(idx);
         idx
     }
 }
 
+impl<T: Interpolate + ProjectToSegment + Copy + Debug> BSpline<T> {
+    /// Split the curve into its Bezier segments. Knots are inserted until every distinct knot
+    /// in the domain has multiplicity equal to the degree, after which each non-empty knot
+    /// span is a Bezier curve. The segments are returned in order along the curve.
+    pub fn to_beziers(&self) -> Vec<Bezier<T>> {
+        let mut curve = self.clone();
+        let domain = self.knot_domain();
+        for (u, multiplicity) in self.distinct_knots() {
+            if u < domain.0 || u > domain.1 {
+                continue;
+            }
+            for _ in multiplicity..curve.degree {
+                curve.insert_knot(u);
+            }
+        }
+        let p = curve.degree;
+        let mut beziers = Vec::new();
+        for i in p..curve.knots.len() - p - 1 {
+            if curve.knots[i] < curve.knots[i + 1] {
+                beziers.push(Bezier::new(curve.control_points[i - p..i + 1].to_vec()));
+            }
+        }
+        beziers
+    }
+}
+
+impl<T: Interpolate + Sub<Output = T> + Mul<f32, Output = T> + Copy + Debug> BSpline<T> {
+    /// Compute the derivative of the curve, which is a B-spline of one degree lower
+    /// defined over the same knot domain. The curve must be at least degree 1.
+    pub fn derivative(&self) -> BSpline<T> {
+        assert!(self.degree > 0);
+        let p = self.degree;
+        let control_points = self.control_points.windows(2).enumerate().map(|(i, x)| {
+            let dt = self.knots[i + p + 1] - self.knots[i + 1];
+            if dt > 0.0 {
+                (x[1] - x[0]) * (p as f32 / dt)
+            } else {
+                (x[1] - x[0]) * 0.0
+            }
+        }).collect();
+        let knots = self.knots[1..self.knots.len() - 1].to_vec();
+        BSpline { degree: p - 1, control_points: control_points, knots: knots,
+                  regenerate_knots: self.regenerate_knots }
+    }
+}
+
+impl BSpline<Point> {
+    /// Find the clamped curve of `degree` passing through `points`. The points are given
+    /// chord length parameter values and the knots are found by averaging the parameters,
+    /// then the control points are found by solving the collocation system at the parameters.
+    /// The degree is lowered if there aren't enough points to support it, and raised to 1
+    /// for degree 0 since a step curve can't pass through the points.
+    pub fn interpolate(points: &[Point], degree: usize) -> BSpline<Point> {
+        if points.len() < 2 {
+            return BSpline::new(0, points.to_vec(), Vec::new());
+        }
+        let degree = cmp::max(cmp::min(degree, points.len() - 1), 1);
+        let params = BSpline::chord_length_params(points);
+        let n = points.len();
+        let mut knots = vec![0.0; degree + 1];
+        for j in 1..n - degree {
+            knots.push(params[j..j + degree].iter().fold(0.0, |acc, x| acc + x) / degree as f32);
+        }
+        knots.extend(vec![1.0; degree + 1]);
+
+        let basis = BSplineBasis::new(degree, knots.clone());
+        let m = Matrix::from_fn(n, n, |j, i| basis.eval(params[i], j));
+        let mut control_points = vec![Point::new(0.0, 0.0, 0.0); n];
+        for c in 0..3 {
+            let rhs = Vector::new(points.iter().map(|x| x.pos[c]).collect::<Vec<f32>>());
+            let result = m.clone().solve(rhs).expect("Interpolation system could not be solved!?");
+            for i in 0..n {
+                control_points[i].pos[c] = result[i];
+            }
+        }
+        BSpline::new(degree, control_points, knots)
+    }
+    /// Compute the chord length parameterization of the points over [0, 1], if the points
+    /// are all the same we fall back to uniformly spaced parameters.
+    pub fn chord_length_params(points: &[Point]) -> Vec<f32> {
+        let mut params = Vec::with_capacity(points.len());
+        params.push(0.0);
+        for x in points.windows(2) {
+            let prev = params[params.len() - 1];
+            params.push(prev + (x[1] - x[0]).length());
+        }
+        let total = params[params.len() - 1];
+        let last = (points.len() - 1) as f32;
+        params.iter().enumerate().map(|(i, p)| if total > 0.0 { p / total } else { i as f32 / last }).collect()
+    }
+    /// Recompute the interior knots from the chord lengths of the control polygon, by averaging
+    /// the chord length parameters of the control points as done for interpolation. Unlike the
+    /// uniform knots the knot spans follow the spacing of the control points. The end conditions
+    /// and knot domain are kept, floating ends continue the spacing of the first and last spans.
+    pub fn rechord_knots(&mut self) {
+        let n = self.control_points.len();
+        let p = self.degree;
+        if p == 0 || n <= p || self.knots.len() != n + p + 1 {
+            return;
+        }
+        let (left_clamped, right_clamped) = self.end_conditions();
+        let (a, b) = self.knot_domain();
+        let (a, b) = if b > a { (a, b) } else { (0.0, 1.0) };
+        let params = BSpline::chord_length_params(&self.control_points[..]);
+        let interior: Vec<_> = (1..n - p)
+            .map(|j| params[j..j + p].iter().fold(0.0, |acc, x| acc + x) / p as f32).collect();
+        // The spacing of the end knots when the ends aren't clamped
+        let first_span = interior.first().map_or(1.0, |k| *k);
+        let last_span = interior.last().map_or(1.0, |k| 1.0 - k);
+        let mut knots = Vec::with_capacity(n + p + 1);
+        for i in 0..p + 1 {
+            knots.push(if left_clamped { 0.0 } else { (i as f32 - p as f32) * first_span });
+        }
+        knots.extend(interior);
+        for i in 0..p + 1 {
+            knots.push(if right_clamped { 1.0 } else { 1.0 + i as f32 * last_span });
+        }
+        self.knots = knots.iter().map(|k| a + (b - a) * k).collect();
+    }
+    /// Raise the degree of the curve by one without changing its shape. The curve is
+    /// clamped first, then the new control points are found by interpolating the curve
+    /// at the Greville abscissae of the elevated knot vector, where each distinct knot
+    /// has its multiplicity increased by one.
+    pub fn elevate_degree(&mut self) {
+        if self.degree == 0 || self.control_points.len() < 2 {
+            return;
+        }
+        let curve = self.to_clamped();
+        let degree = curve.degree + 1;
+        let mut knots = Vec::with_capacity(2 * curve.knots.len());
+        for (k, multiplicity) in curve.distinct_knots() {
+            knots.extend(iter::repeat(k).take(multiplicity + 1));
+        }
+        let num_points = knots.len() - degree - 1;
+        let basis = BSplineBasis::new(degree, knots.clone());
+        let abscissa = basis.greville_abscissa();
+        assert_eq!(abscissa.len(), num_points);
+        let n = Matrix::from_fn(num_points, num_points, |j, i| basis.eval(abscissa[i], j));
+        let samples: Vec<_> = abscissa.iter().map(|t| curve.point(*t)).collect();
+        let mut control_points = vec![Point::new(0.0, 0.0, 0.0); num_points];
+        for c in 0..3 {
+            let rhs = Vector::new(samples.iter().map(|x| x.pos[c]).collect::<Vec<f32>>());
+            let result = n.clone().solve(rhs).expect("Degree elevation system could not be solved!?");
+            for i in 0..num_points {
+                control_points[i].pos[c] = result[i];
+            }
+        }
+        self.degree = degree;
+        self.control_points = control_points;
+        self.knots = knots;
+    }
+    /// Set the degree of the curve, if the degree is higher than the number of control
+    /// points supports the curve's degree is elevated instead, adding control points
+    /// while preserving its shape.
+    pub fn set_degree_padded(&mut self, degree: usize) {
+        if degree <= self.max_possible_degree() {
+            self.set_degree(degree);
+            return;
+        }
+        while self.degree < degree && self.degree > 0 && self.control_points.len() >= 2 {
+            self.elevate_degree();
+        }
+    }
+    /// Compute the curvature of the curve at `t`, curves below degree 2 are straight
+    /// lines between their control points and have zero curvature.
+    pub fn curvature(&self, t: f32) -> f32 {
+        if self.degree < 2 {
+            return 0.0;
+        }
+        let d1 = self.derivative();
+        let d2 = d1.derivative();
+        let (a, b) = (d1.point(t), d2.point(t));
+        let speed = a.length();
+        if speed == 0.0 {
+            return 0.0;
+        }
+        a.cross(&b).length() / (speed * speed * speed)
+    }
+    /// Find the osculating circle of the curve at `t`, returned as `(center, radius, normal)`
+    /// where `normal` is the unit vector from the curve towards the center. Returns None if
+    /// the curve is (nearly) straight at `t`, where the circle's radius would be infinite.
+    pub fn osculating_circle(&self, t: f32) -> Option<(Point, f32, Point)> {
+        let k = self.curvature(t);
+        if k < 1e-4 {
+            return None;
+        }
+        let d1 = self.derivative();
+        let (a, b) = (d1.point(t), d1.derivative().point(t));
+        // The normal is the part of the second derivative perpendicular to the tangent
+        let normal = (b - a * (b.dot(&a) / a.dot(&a))).normalized();
+        let radius = 1.0 / k;
+        Some((self.point(t) + normal * radius, radius, normal))
+    }
+    /// Compute the arc length of the curve over its entire knot domain. Each non-empty
+    /// knot span is integrated separately with 5 point Gauss-Legendre quadrature.
+    pub fn arc_length(&self) -> f32 {
+        if self.degree == 0 || self.control_points.len() < 2 {
+            return 0.0;
+        }
+        let nodes = [(0.0, 0.5688889),
+                     (-0.5384693, 0.4786287), (0.5384693, 0.4786287),
+                     (-0.9061798, 0.2369269), (0.9061798, 0.2369269)];
+        let deriv = self.derivative();
+        let mut length = 0.0;
+        for i in self.degree..self.knots.len() - self.degree - 1 {
+            let (a, b) = (self.knots[i], self.knots[i + 1]);
+            if b <= a {
+                continue;
+            }
+            let half_width = 0.5 * (b - a);
+            let mid = 0.5 * (a + b);
+            length += nodes.iter().fold(0.0, |acc, &(x, w)| {
+                acc + w * deriv.point(mid + half_width * x).length()
+            }) * half_width;
+        }
+        length
+    }
+    /// Mirror the curve through the xy plane by negating z, to convert it between left
+    /// and right handed coordinates
+    pub fn flip_handedness(&mut self) {
+        for p in self.control_points.iter_mut() {
+            p.pos[2] = -p.pos[2];
+        }
+    }
+    /// Project the control points onto the plane of points `x` with `normal . x = d`,
+    /// `normal` doesn't need to be normalized.
+    pub fn project_to_plane(&mut self, normal: Point, d: f32) {
+        let len = normal.length();
+        let n = normal / len;
+        let d = d / len;
+        for p in self.control_points.iter_mut() {
+            *p = *p - n * (n.dot(p) - d);
+        }
+    }
+    /// Find the least-squares plane through the control points, returned as `(normal, d)`
+    /// for the plane `normal . x = d`. The normal is found by solving for the axis with the
+    /// best conditioned system, which is fine for the nearly planar data we want to fit.
+    /// If the points are collinear or there are fewer than three we pick the xy plane through them.
+    pub fn fit_plane(&self) -> (Point, f32) {
+        let n = self.control_points.len() as f32;
+        let centroid = self.control_points.iter().fold(Point::new(0.0, 0.0, 0.0), |acc, p| acc + *p) / n;
+        let (mut xx, mut xy, mut xz, mut yy, mut yz, mut zz) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        for p in self.control_points.iter() {
+            let r = *p - centroid;
+            xx += r.pos[0] * r.pos[0];
+            xy += r.pos[0] * r.pos[1];
+            xz += r.pos[0] * r.pos[2];
+            yy += r.pos[1] * r.pos[1];
+            yz += r.pos[1] * r.pos[2];
+            zz += r.pos[2] * r.pos[2];
+        }
+        let det_x = yy * zz - yz * yz;
+        let det_y = xx * zz - xz * xz;
+        let det_z = xx * yy - xy * xy;
+        let det_max = f32::max(det_x, f32::max(det_y, det_z));
+        let normal = if det_max <= 1e-12 {
+            Point::new(0.0, 0.0, 1.0)
+        } else if det_max == det_x {
+            Point::new(det_x, xz * yz - xy * zz, xy * yz - xz * yy)
+        } else if det_max == det_y {
+            Point::new(xz * yz - xy * zz, det_y, xy * xz - yz * xx)
+        } else {
+            Point::new(xy * yz - xz * yy, xy * xz - yz * xx, det_z)
+        };
+        let normal = normal.normalized();
+        (normal, normal.dot(&centroid))
+    }
+    /// Find the point on the curve closest to `p`, returns the parameter and the point.
+    /// Each knot span is sampled to find a starting guess which is then refined with
+    /// a golden section search on the neighboring samples.
+    pub fn closest_point(&self, p: &Point) -> (f32, Point) {
+        let samples = 16;
+        let mut best = (0.0, f32::MAX);
+        let mut step = 0.0;
+        for span in self.knot_domain_iter().cloned().collect::<Vec<f32>>().windows(2) {
+            if span[1] <= span[0] {
+                continue;
+            }
+            let h = (span[1] - span[0]) / samples as f32;
+            for i in 0..samples + 1 {
+                let t = span[0] + h * i as f32;
+                let d = (self.point(t) - *p).length();
+                if d < best.1 {
+                    best = (t, d);
+                    step = h;
+                }
+            }
+        }
+        let domain = self.knot_domain();
+        let mut a = point::clamp(best.0 - step, domain.0, domain.1);
+        let mut b = point::clamp(best.0 + step, domain.0, domain.1);
+        let ratio = 0.618034;
+        for _ in 0..32 {
+            let x0 = b - ratio * (b - a);
+            let x1 = a + ratio * (b - a);
+            if (self.point(x0) - *p).length() < (self.point(x1) - *p).length() {
+                b = x1;
+            } else {
+                a = x0;
+            }
+        }
+        let t = 0.5 * (a + b);
+        let refined = self.point(t);
+        if (refined - *p).length() < best.1 {
+            (t, refined)
+        } else {
+            (best.0, self.point(best.0))
+        }
+    }
+    /// Compute the maximum over the control points of each one's distance to the closest
+    /// point on the curve, measuring how closely the curve follows its control polygon.
+    /// Curves passing through all their control points, like polylines, give 0.
+    pub fn control_polygon_distance(&self) -> f32 {
+        if self.degree == 0 || self.control_points.len() < 2 {
+            return 0.0;
+        }
+        self.control_points.iter().map(|c| (self.closest_point(c).1 - *c).length())
+            .fold(0.0, f32::max)
+    }
+    /// Split the curve into dashes for drawing it dashed, alternating `dash_length` of curve
+    /// drawn and `gap_length` skipped along its arc length. The curve is sampled with `step_size`
+    /// in parameter space and each dash returned as a polyline following the curve.
+    pub fn dashes(&self, dash_length: f32, gap_length: f32, step_size: f32) -> Vec<Vec<Point>> {
+        if self.control_points.is_empty() || dash_length <= 0.0 || gap_length < 0.0 {
+            return Vec::new();
+        }
+        let period = dash_length + gap_length;
+        let points: Vec<_> = sample_domain(self.knot_domain(), step_size).iter().map(|t| self.point(*t)).collect();
+        let mut dashes = Vec::new();
+        let mut dash = vec![points[0]];
+        // Distance travelled within the current dash + gap period
+        let mut s = 0.0;
+        for x in points.windows(2) {
+            let len = (x[1] - x[0]).length();
+            let mut pos = 0.0;
+            while len - pos > 1e-6 {
+                let drawing = s < dash_length;
+                let end = if drawing { dash_length } else { period };
+                let step = f32::min(end - s, len - pos);
+                pos += step;
+                s += step;
+                let p = x[0] + (x[1] - x[0]) * (pos / len);
+                if drawing {
+                    dash.push(p);
+                }
+                if end - s <= 1e-6 {
+                    if drawing {
+                        dashes.push(mem::replace(&mut dash, Vec::new()));
+                    } else {
+                        dash.push(p);
+                        s = 0.0;
+                    }
+                }
+            }
+        }
+        if dash.len() > 1 {
+            dashes.push(dash);
+        }
+        dashes
+    }
+    /// Find where the curve crosses itself in the xy plane. Returns the pairs of parameters
+    /// `(s, t)` with `s < t` where `point(s)` and `point(t)` coincide. The curve is split into
+    /// its Bezier segments and each pair of segments whose control polygon bounds overlap
+    /// is flattened and tested for crossings. On a closed curve the end of the domain is the
+    /// same point as the start, so crossings there are reported at the start.
+    pub fn self_intersections(&self) -> Vec<(f32, f32)> {
+        let samples = 32;
+        let domain = self.knot_domain();
+        let eps = (domain.1 - domain.0) * 1e-3;
+        let flatten = |p: Point| Point::new(p.pos[0], p.pos[1], 0.0);
+        let closed = (flatten(self.point(domain.0)) - flatten(self.point(domain.1))).length() < 1e-5;
+        let wrap = |t: f32| if closed && domain.1 - t < eps { domain.0 } else { t };
+        let mut spans: Vec<f32> = self.knot_domain_iter().cloned().collect();
+        spans.dedup();
+        let beziers = self.to_beziers();
+        // Flatten each segment into a polyline, tracking the curve parameter at each vertex
+        let polylines: Vec<Vec<(f32, Point)>> = beziers.iter().zip(spans.windows(2))
+            .map(|(b, span)| {
+                (0..samples + 1).map(|i| {
+                    let x = i as f32 / samples as f32;
+                    (span[0] + x * (span[1] - span[0]), b.point(x))
+                }).collect()
+            }).collect();
+        let bounds: Vec<_> = beziers.iter().map(|b| point::bounding_box(&b.control_points[..])).collect();
+
+        let mut intersections: Vec<(f32, f32)> = Vec::new();
+        for i in 0..polylines.len() {
+            for j in i..polylines.len() {
+                if !boxes_overlap(&bounds[i], &bounds[j]) {
+                    continue;
+                }
+                for (a, seg_a) in polylines[i].windows(2).enumerate() {
+                    // Within a segment only test against the non-neighboring line segments
+                    let skip = if i == j { a + 2 } else { 0 };
+                    for seg_b in polylines[j].windows(2).skip(skip) {
+                        if let Some((u, v)) = segment_intersection(&seg_a[0].1, &seg_a[1].1,
+                                                                   &seg_b[0].1, &seg_b[1].1) {
+                            let s = wrap(seg_a[0].0 + u * (seg_a[1].0 - seg_a[0].0));
+                            let t = wrap(seg_b[0].0 + v * (seg_b[1].0 - seg_b[0].0));
+                            let (s, t) = if s <= t { (s, t) } else { (t, s) };
+                            // Neighboring segments will meet where they're joined, which isn't
+                            // a self-intersection, and on a closed curve the first and last
+                            // segments are neighbors. Crossings found on a shared line segment
+                            // vertex or overlapping segments are also only reported once.
+                            if (t - s).abs() > eps
+                                && !intersections.iter().any(|x| (x.0 - s).abs() < eps && (x.1 - t).abs() < eps) {
+                                intersections.push((s, t));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        intersections
+    }
+    /// Find the parameters of the curve's inflection points in the xy plane, where its signed
+    /// curvature changes sign. Each knot span is sampled to bracket the sign changes, which
+    /// are then refined by bisection. Straight parts of the curve have no inflections.
+    pub fn inflection_points(&self) -> Vec<f32> {
+        if self.degree < 2 {
+            return Vec::new();
+        }
+        let d1 = self.derivative();
+        let d2 = d1.derivative();
+        let signed_curvature = |t: f32| {
+            let (a, b) = (d1.point(t), d2.point(t));
+            let speed_sq = a.dot(&a);
+            if speed_sq == 0.0 { 0.0 } else { a.cross(&b).pos[2] / (speed_sq * speed_sq.sqrt()) }
+        };
+        // Curvature below this is treated as straight and doesn't count as a sign change
+        let eps = 1e-4;
+        let samples = 16;
+        let mut spans: Vec<f32> = self.knot_domain_iter().cloned().collect();
+        spans.dedup();
+        let mut inflections = Vec::new();
+        // The last sample found with non-zero curvature
+        let mut prev: Option<(f32, f32)> = None;
+        for span in spans.windows(2) {
+            for i in 0..samples + 1 {
+                let t = span[0] + (span[1] - span[0]) * i as f32 / samples as f32;
+                let k = signed_curvature(t);
+                if k.abs() < eps {
+                    continue;
+                }
+                if let Some((mut a, ka)) = prev {
+                    if ka.signum() != k.signum() {
+                        let mut b = t;
+                        for _ in 0..32 {
+                            let mid = 0.5 * (a + b);
+                            if signed_curvature(mid).signum() == ka.signum() {
+                                a = mid;
+                            } else {
+                                b = mid;
+                            }
+                        }
+                        inflections.push(0.5 * (a + b));
+                    }
+                }
+                prev = Some((t, k));
+            }
+        }
+        inflections
+    }
+    /// Compute the curve offset by `distance` in the xy plane as a polyline through `samples`
+    /// points, each moved along the curve's normal (the tangent rotated 90 degrees counter
+    /// clockwise). Self intersections from offsets larger than the curve's radius are kept.
+    pub fn offset(&self, distance: f32, samples: usize) -> Vec<Point> {
+        let (t0, t1) = self.knot_domain();
+        let samples = usize::max(samples, 2);
+        let ts = (0..samples).map(|i| t0 + (t1 - t0) * i as f32 / (samples - 1) as f32);
+        if self.degree == 0 {
+            return ts.map(|t| self.point(t)).collect();
+        }
+        let deriv = self.derivative();
+        let mut normal = Point::new(0.0, 0.0, 0.0);
+        ts.map(|t| {
+            let d = deriv.point(t);
+            // Where the curve stops the last normal is kept
+            if d.pos[0] != 0.0 || d.pos[1] != 0.0 {
+                normal = Point::new(-d.pos[1], d.pos[0], 0.0).normalized();
+            }
+            self.point(t) + normal * distance
+        }).collect()
+    }
+    /// Simplify the curve by removing control points deviating from the control polygon
+    /// by less than `tol`, using Ramer-Douglas-Peucker on the control points. For degree 1
+    /// curves this bounds how far the curve moves, higher degree curves follow the simplified
+    /// polygon. The knot vector is regenerated keeping the end conditions, and the degree is
+    /// lowered if too few points remain for it.
+    pub fn decimate(&mut self, tol: f32) {
+        if self.control_points.len() < 3 {
+            return;
+        }
+        let mut keep = vec![false; self.control_points.len()];
+        keep[0] = true;
+        keep[self.control_points.len() - 1] = true;
+        ramer_douglas_peucker(&self.control_points[..], 0, tol, &mut keep[..]);
+        let (left_clamped, right_clamped) = self.end_conditions();
+        self.control_points = self.control_points.iter().zip(keep.iter()).filter(|x| *x.1)
+            .map(|x| *x.0).collect();
+        self.degree = cmp::min(self.degree, self.control_points.len() - 1);
+        self.fill_knot_vector(left_clamped, right_clamped);
+    }
+}
+
+/// Cached de Boor weights for evaluating a curve at a fixed set of parameter values. Moving
+/// control points doesn't change the weights, so the curve can be resampled by just summing
+/// the weighted control points until its degree or knots change.
+#[derive(Clone, Debug)]
+pub struct SampleCache {
+    degree: usize,
+    knots: Vec<f32>,
+    /// The first control point influencing each sample and the weight of each of the
+    /// `degree + 1` control points from it
+    samples: Vec<(usize, Vec<f32>)>,
+}
+
+impl SampleCache {
+    /// Compute the weights for evaluating `curve` at each of `ts`
+    pub fn new<T: Interpolate + Copy + Debug>(curve: &BSpline<T>, ts: &[f32]) -> SampleCache {
+        let p = curve.degree;
+        let samples = ts.iter().map(|t| {
+            let (t, i_start) = curve.find_span(*t);
+            // Run de Boor's algorithm on the weights of each control point instead of the points
+            let mut tmp: Vec<Vec<f32>> = (0..p + 1).map(|j| {
+                let mut w = vec![0.0; p + 1];
+                w[j] = 1.0;
+                w
+            }).collect();
+            for lvl in 0..p {
+                let k = lvl + 1;
+                for j in 0..p - lvl {
+                    let i = j + k + i_start - p;
+                    let alpha = knot_alpha(t, curve.knots[i - 1], curve.knots[i + p - k]);
+                    tmp[j] = tmp[j].iter().zip(tmp[j + 1].iter()).map(|(a, b)| (1.0 - alpha) * a + alpha * b)
+                        .collect();
+                }
+            }
+            (i_start - p - 1, tmp.swap_remove(0))
+        }).collect();
+        SampleCache { degree: p, knots: curve.knots.clone(), samples: samples }
+    }
+    /// Check if the weights can be used for `curve`, i.e. it has the same degree and knots
+    pub fn matches<T>(&self, curve: &BSpline<T>) -> bool {
+        self.degree == curve.degree && self.knots == curve.knots
+    }
+    /// Evaluate the curve with `control_points` at each of the cached parameter values
+    pub fn evaluate(&self, control_points: &[Point]) -> Vec<Point> {
+        self.samples.iter().map(|&(first, ref weights)| {
+            weights.iter().enumerate().fold(Point::new(0.0, 0.0, 0.0), |acc, (j, w)| {
+                acc + control_points[first + j] * *w
+            })
+        }).collect()
+    }
+}
+
+/// Get evenly spaced parameter values covering `domain` at roughly `step_size` apart.
+/// The first and last values are exactly the ends of the domain, so this works for
+/// any knot domain, including negative or scaled ones.
+pub fn sample_domain(domain: (f32, f32), step_size: f32) -> Vec<f32> {
+    let len = domain.1 - domain.0;
+    if !(len > 0.0) || !(step_size > 0.0) {
+        return vec![domain.0];
+    }
+    let steps = f32::max((len / step_size).ceil(), 1.0) as usize;
+    (0..steps + 1).map(|s| {
+        if s == steps {
+            domain.1
+        } else {
+            domain.0 + len * s as f32 / steps as f32
+        }
+    }).collect()
+}
+
+/// Check if two 2D bounding boxes overlap in the xy plane
+fn boxes_overlap(a: &(Point, Point), b: &(Point, Point)) -> bool {
+    a.0.pos[0] <= b.1.pos[0] && b.0.pos[0] <= a.1.pos[0]
+        && a.0.pos[1] <= b.1.pos[1] && b.0.pos[1] <= a.1.pos[1]
+}
+
+/// Intersect the line segments `[p0, p1]` and `[q0, q1]` in the xy plane. Returns the
+/// parameters along each segment where they cross, parallel segments are treated
+/// as not intersecting.
+fn segment_intersection(p0: &Point, p1: &Point, q0: &Point, q1: &Point) -> Option<(f32, f32)> {
+    let r = *p1 - *p0;
+    let s = *q1 - *q0;
+    let denom = r.pos[0] * s.pos[1] - r.pos[1] * s.pos[0];
+    if denom.abs() < 1e-12 {
+        return None;
+    }
+    let qp = *q0 - *p0;
+    let u = (qp.pos[0] * s.pos[1] - qp.pos[1] * s.pos[0]) / denom;
+    let v = (qp.pos[0] * r.pos[1] - qp.pos[1] * r.pos[0]) / denom;
+    if u >= 0.0 && u <= 1.0 && v >= 0.0 && v <= 1.0 {
+        Some((u, v))
+    } else {
+        None
+    }
+}
+
+/// Mark the points to keep in `points` when simplifying the polyline with Ramer-Douglas-Peucker,
+/// `offset` is the index of `points[0]` in `keep`. The ends of `points` are already kept.
+fn ramer_douglas_peucker(points: &[Point], offset: usize, tol: f32, keep: &mut [bool]) {
+    if points.len() < 3 {
+        return;
+    }
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let farthest = points[1..points.len() - 1].iter().enumerate()
+        .map(|(i, p)| (i + 1, p.project(&first, &last).0))
+        .fold((0, -1.0), |acc, x| if x.1 > acc.1 { x } else { acc });
+    if farthest.1 >= tol {
+        keep[offset + farthest.0] = true;
+        ramer_douglas_peucker(&points[..farthest.0 + 1], offset, tol, keep);
+        ramer_douglas_peucker(&points[farthest.0..], offset + farthest.0, tol, keep);
+    }
+}
+
+/// Get how far `t` is between the knots `start` and `end` for de Boor's algorithm. High
+/// multiplicity knots can give zero width spans, where the left point is taken instead of
+/// dividing by zero.
+fn knot_alpha(t: f32, start: f32, end: f32) -> f32 {
+    let width = end - start;
+    if width > 0.0 {
+        (t - start) / width
+    } else {
+        0.0
+    }
+}
+
 /// Return the index of the first element greater than the value passed.
 /// The data **must** be sorted. If no element greater than the value
 /// passed is found the function returns None.
@@ -233,3 +1141,678 @@ fn upper_bounds(data: &[f32], value: f32) -> Option<usize> {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_point_eq(a: Point, b: Point) {
+        assert!((a - b).length() < 1e-5, "expected {:?}, got {:?}", b, a);
+    }
+
+    /// A clamped uniform cubic with 5 control points, the knots are `[0, 0, 0, 0, 1, 2, 2, 2, 2]`
+    fn clamped_cubic() -> BSpline<Point> {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.0), Point::new(2.0, -1.0, 0.0),
+                          Point::new(3.0, 2.0, 0.0), Point::new(4.0, 0.0, 0.0)];
+        BSpline::new(3, points, vec![])
+    }
+
+    /// Get the value of each basis function at `t` by evaluating the curve with a single
+    /// control point set to 1 and the rest to 0.
+    fn basis_values(degree: usize, knots: &[f32], t: f32) -> Vec<f32> {
+        let n = knots.len() - degree - 1;
+        (0..n).map(|i| {
+            let points = (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect();
+            BSpline::new(degree, points, knots.to_vec()).point(t)
+        }).collect()
+    }
+
+    #[test]
+    fn de_boor_clamped_uniform_cubic() {
+        let curve = clamped_cubic();
+        assert_eq!(curve.knots().cloned().collect::<Vec<_>>(), vec![0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 2.0, 2.0, 2.0]);
+        // The basis functions are [1/8, 19/32, 1/4, 1/32, 0] at 0.5, [0, 1/4, 1/2, 1/4, 0] at 1
+        // and mirrored at 1.5
+        assert_point_eq(curve.point(0.5), Point::new(1.1875, 1.0, 0.0));
+        assert_point_eq(curve.point(1.0), Point::new(2.0, 0.5, 0.0));
+        assert_point_eq(curve.point(1.5), Point::new(2.8125, 1.0, 0.0));
+        // A clamped curve interpolates its end points
+        assert_point_eq(curve.point(0.0), Point::new(0.0, 0.0, 0.0));
+        assert_point_eq(curve.point(2.0), Point::new(4.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn de_boor_iterative_matches_steps() {
+        let curve = clamped_cubic();
+        for t in &[0.0, 0.25, 1.0, 1.75, 2.0] {
+            let (t, i) = curve.find_span(*t);
+            let steps = curve.de_boor_steps(t);
+            assert_point_eq(curve.de_boor_iterative(t, i), steps.last().unwrap()[0]);
+        }
+    }
+
+    #[test]
+    fn de_boor_steps_end_at_point() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.0), Point::new(2.0, -1.0, 0.5),
+                          Point::new(3.0, 2.0, 0.0), Point::new(4.0, 0.0, 1.0)];
+        let curves = vec![clamped_cubic(),
+                          BSpline::new(2, points.clone(), vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]),
+                          BSpline::new(3, points, vec![0.0, 0.0, 0.0, 0.0, 0.3, 2.0, 2.0, 2.0, 2.0])];
+        for curve in curves.iter() {
+            let domain = curve.knot_domain();
+            // Parameters outside the domain are clamped to it just as in `point`
+            let outside = vec![domain.0 - 0.5, domain.1 + 0.5];
+            for t in sample_domain(domain, 0.05).into_iter().chain(outside) {
+                let steps = curve.de_boor_steps(t);
+                assert_eq!(steps.len(), curve.degree() + 1);
+                assert_eq!(steps[0].len(), curve.degree() + 1);
+                let last = steps.last().unwrap();
+                assert_eq!(last.len(), 1);
+                assert_point_eq(last[0], curve.point(t));
+            }
+        }
+    }
+
+    #[test]
+    fn homogeneous_de_boor_matches_rational() {
+        use point::HomogeneousPoint;
+        let curve = clamped_cubic();
+        let weights = [1.0, 2.0, 0.5, 3.0, 1.0];
+        let points: Vec<_> = curve.control_points().cloned().collect();
+        let knots: Vec<_> = curve.knots().cloned().collect();
+        let homogeneous = BSpline::new(3, points.iter().zip(weights.iter())
+                                       .map(|(p, w)| HomogeneousPoint::new(*p, *w)).collect(), knots.clone());
+        let basis = BSplineBasis::new(3, knots);
+        for t in sample_domain(curve.knot_domain(), 0.05) {
+            // Evaluate the rational curve directly as the weighted average of the points
+            let n: Vec<_> = (0..points.len()).map(|i| basis.eval(t, i) * weights[i]).collect();
+            let sum: f32 = n.iter().sum();
+            let expected = points.iter().zip(n.iter())
+                .fold(Point::new(0.0, 0.0, 0.0), |acc, (p, w)| acc + *p * *w) / sum;
+            assert_point_eq(homogeneous.point(t).project(), expected);
+        }
+    }
+
+    #[test]
+    fn low_degree_fast_paths_match_de_boor() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.0), Point::new(2.0, -1.0, 0.5),
+                          Point::new(3.0, 2.0, 0.0)];
+        let curves = vec![BSpline::new(0, points.clone(), vec![0.0, 0.5, 1.0, 2.5, 3.0]),
+                          BSpline::new(1, points.clone(), vec![]),
+                          BSpline::new(1, points.clone(), vec![0.0, 0.0, 0.2, 1.5, 1.5, 3.0])];
+        for curve in curves.iter() {
+            for t in sample_domain(curve.knot_domain(), 0.01) {
+                let (_, i) = curve.find_span(t);
+                let general = curve.de_boor_iterative(t, i);
+                assert_eq!(curve.point(t).pos, general.pos, "at t = {} for {:?}", t, curve);
+            }
+        }
+    }
+
+    #[test]
+    fn interior_knot_multiplicity_of_degree() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.0), Point::new(2.0, -1.0, 0.0),
+                          Point::new(3.0, 2.0, 0.0), Point::new(4.0, 0.0, 0.0), Point::new(5.0, 1.0, 0.0),
+                          Point::new(6.0, 0.0, 0.0)];
+        let knots = vec![0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0];
+        let curve = BSpline::new(3, points, knots);
+        for t in sample_domain(curve.knot_domain(), 0.01) {
+            let p = curve.point(t);
+            assert!(p.pos.iter().all(|x| x.is_finite()), "got {:?} at t = {}", p, t);
+            assert_point_eq(curve.de_boor_steps(t).last().unwrap()[0], p);
+        }
+        // With the knot repeated degree times the curve passes through the control point there
+        assert_point_eq(curve.point(1.0), Point::new(3.0, 2.0, 0.0));
+        assert_eq!(knot_alpha(1.0, 1.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn upper_bounds_edge_cases() {
+        let knots = [0.0, 0.0, 1.0, 2.0, 2.0];
+        // Below all values
+        assert_eq!(upper_bounds(&knots, -1.0), Some(0));
+        // Above all values
+        assert_eq!(upper_bounds(&knots, 3.0), None);
+        // Equal to a knot gives the first knot after it
+        assert_eq!(upper_bounds(&knots, 0.0), Some(2));
+        assert_eq!(upper_bounds(&knots, 1.0), Some(3));
+        assert_eq!(upper_bounds(&knots, 2.0), None);
+        // Between knots
+        assert_eq!(upper_bounds(&knots, 1.5), Some(3));
+        assert_eq!(upper_bounds(&[], 1.0), None);
+    }
+
+    #[test]
+    fn partition_of_unity() {
+        let knot_vectors: Vec<(usize, Vec<f32>)> =
+            vec![(3, vec![0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 2.0, 2.0, 2.0]),
+                 (2, vec![0.0, 0.0, 0.0, 0.3, 0.3, 1.7, 4.0, 4.0, 4.0]),
+                 (2, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+                 (3, vec![-5.0, -5.0, -5.0, -5.0, 0.0, 5.0, 5.0, 5.0, 5.0]),
+                 (1, vec![0.0, 0.0, 0.5, 1.0, 1.0])];
+        for &(degree, ref knots) in knot_vectors.iter() {
+            let domain = (knots[degree], knots[knots.len() - 1 - degree]);
+            for t in sample_domain(domain, 0.05) {
+                let sum: f32 = basis_values(degree, knots, t).iter().sum();
+                assert!((sum - 1.0).abs() < 1e-5, "basis sums to {} at t = {} for {:?}", sum, t, knots);
+            }
+        }
+    }
+
+    #[test]
+    fn insert_near_coincident_points() {
+        // The middle two points are stacked to make a sharp corner
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0), Point::new(1.0, 1.0, 0.0),
+                          Point::new(2.0, 0.0, 0.0)];
+        let mut curve = BSpline::new(2, points.clone(), vec![]);
+        // Near the second half of the polygon, after the stacked points
+        assert_eq!(curve.insert_point(Point::new(1.6, 0.5, 0.0)), 3);
+        // Near the first half, before the stacked points
+        let mut curve = BSpline::new(2, points.clone(), vec![]);
+        assert_eq!(curve.insert_point(Point::new(0.4, 0.5, 0.0)), 1);
+        // Past the end, where the last points are stacked
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0)];
+        let mut curve = BSpline::new(2, points, vec![]);
+        assert_eq!(curve.insert_point(Point::new(2.0, 0.0, 0.0)), 3);
+        assert_eq!(curve.insert_point(Point::new(-1.0, 0.0, 0.0)), 0);
+        assert!(curve.control_points().all(|p| p.pos.iter().all(|x| x.is_finite())));
+        assert_eq!(curve.knots().count(), curve.control_points().count() + curve.degree() + 1);
+    }
+
+    #[test]
+    fn project_on_zero_length_segment() {
+        let a = Point::new(1.0, 1.0, 0.0);
+        let (d, t) = Point::new(4.0, 5.0, 0.0).project(&a, &a);
+        assert_eq!(d, 5.0);
+        assert!(t.is_finite());
+    }
+
+    #[test]
+    fn dashes_along_line() {
+        // A straight line of length 10
+        let curve = BSpline::new(1, vec![Point::new(0.0, 0.0, 0.0), Point::new(10.0, 0.0, 0.0)], vec![]);
+        let dashes = curve.dashes(1.0, 1.0, 0.01);
+        assert_eq!(dashes.len(), 5);
+        for d in dashes.iter() {
+            let len = d.windows(2).map(|x| (x[1] - x[0]).length()).sum::<f32>();
+            assert!((len - 1.0).abs() < 1e-4, "dash length {}", len);
+        }
+        assert_point_eq(dashes[1][0], Point::new(2.0, 0.0, 0.0));
+        // The last dash is cut short by the end of the curve
+        let dashes = curve.dashes(1.0, 0.5, 0.01);
+        assert_eq!(dashes.len(), 7);
+        assert_point_eq(*dashes[6].last().unwrap(), Point::new(10.0, 0.0, 0.0));
+        // With no gap it's one dash after another
+        assert_eq!(curve.dashes(2.0, 0.0, 0.01).len(), 5);
+    }
+
+    #[test]
+    fn sample_cache_matches_point() {
+        let mut curve = clamped_cubic();
+        let ts = sample_domain(curve.knot_domain(), 0.01);
+        let cache = SampleCache::new(&curve, &ts);
+        let check = |curve: &BSpline<Point>| {
+            for (p, t) in cache.evaluate(&curve.control_points).iter().zip(ts.iter()) {
+                assert_point_eq(*p, curve.point(*t));
+            }
+        };
+        check(&curve);
+        // Moving a control point keeps the cache valid
+        curve.control_points[2] = Point::new(2.5, 3.0, 1.0);
+        assert!(cache.matches(&curve));
+        check(&curve);
+        // Changing the knots or degree doesn't
+        curve.insert_knot(0.5);
+        assert!(!cache.matches(&curve));
+        let mut steps = curve.clone();
+        steps.set_degree(0);
+        assert!(!cache.matches(&steps));
+    }
+
+    #[test]
+    fn find_span_at_domain_end() {
+        // The end knot is repeated past the degree, so the last span in the domain is empty
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0), Point::new(2.0, 0.0, 0.0),
+                          Point::new(3.0, 1.0, 0.0)];
+        let curve = BSpline::new(2, points, vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0]);
+        let (t, i) = curve.find_span(1.0);
+        assert_eq!(t, 1.0);
+        assert_eq!(i, 3);
+        assert!(!curve.point(1.0).pos[0].is_nan());
+        // Values past the domain are clamped to it
+        assert_eq!(curve.find_span(-1.0).0, 0.0);
+        assert_eq!(curve.find_span(2.0).0, 1.0);
+    }
+
+    #[test]
+    fn edits_keep_non_uniform_knots() {
+        let points: Vec<_> = (0..6).map(|i| Point::new(i as f32, (i % 2) as f32, 0.0)).collect();
+        let knots = vec![0.0, 0.0, 0.0, 0.1, 0.5, 2.0, 3.0, 3.0, 3.0];
+        let mut curve = BSpline::new(2, points, knots.clone());
+        // Every original knot is kept in order with just the one for the new point added
+        let is_subsequence = |sub: &[f32], seq: &[f32]| {
+            let mut it = seq.iter();
+            sub.iter().all(|k| it.any(|x| x == k))
+        };
+        curve.insert_point_at(Point::new(2.5, 2.0, 0.0), 3);
+        assert_eq!(curve.knots.len(), knots.len() + 1);
+        assert!(is_subsequence(&knots[..], &curve.knots[..]), "{:?} lost knots of {:?}", curve.knots, knots);
+        assert_eq!(curve.knot_domain(), (0.0, 3.0));
+        let inserted = curve.knots.clone();
+        curve.remove_point(3);
+        assert_eq!(curve.knots.len(), knots.len());
+        assert!(is_subsequence(&curve.knots[..], &inserted[..]));
+        assert_eq!(curve.knot_domain(), (0.0, 3.0));
+        assert_eq!(curve.validate(), Ok(()));
+        // Knots are only regenerated when asked for
+        curve.set_regenerate_knots(true);
+        curve.insert_point_at(Point::new(2.5, 2.0, 0.0), 3);
+        assert!(curve.has_uniform_knots());
+    }
+
+    #[test]
+    fn insert_point_at_ends_and_middle() {
+        let non_uniform = BSpline::new(3, clamped_cubic().control_points,
+                                       vec![0.0, 0.0, 0.0, 0.0, 0.3, 2.0, 2.0, 2.0, 2.0]);
+        let new_point = Point::new(-1.0, 5.0, 2.0);
+        for original in &[clamped_cubic(), non_uniform] {
+            let n = original.control_points.len();
+            for &index in &[0, n / 2, n] {
+                let mut curve = original.clone();
+                curve.insert_point_at(new_point, index);
+                assert_eq!(curve.control_points.len(), n + 1);
+                assert_eq!(curve.control_points[index].pos, new_point.pos);
+                // The other points keep their order around the new one
+                let others: Vec<_> = curve.control_points.iter().enumerate()
+                    .filter(|&(i, _)| i != index).map(|(_, p)| p.pos).collect();
+                assert_eq!(others, original.control_points.iter().map(|p| p.pos).collect::<Vec<_>>());
+                assert_eq!(curve.knots.len(), n + 1 + curve.degree() + 1);
+                assert_eq!(curve.validate(), Ok(()), "inserting at {} gave knots {:?}", index, curve.knots);
+            }
+        }
+    }
+
+    #[test]
+    fn project_to_xy_plane() {
+        let points = vec![Point::new(0.0, 0.0, 1e-4), Point::new(1.0, 2.0, -3e-5), Point::new(2.5, -1.0, 2e-6),
+                          Point::new(3.0, 2.0, -1e-7), Point::new(4.0, 0.5, 0.0)];
+        let original = BSpline::new(3, points, vec![]);
+        // The normal doesn't need to be unit length
+        for normal in &[Point::new(0.0, 0.0, 1.0), Point::new(0.0, 0.0, 3.0), Point::new(0.0, 0.0, -0.5)] {
+            let mut curve = original.clone();
+            curve.project_to_plane(*normal, 0.0);
+            for (p, o) in curve.control_points().zip(original.control_points()) {
+                assert_eq!(p.pos, [o.pos[0], o.pos[1], 0.0], "projecting onto z = 0 with normal {:?}", normal);
+            }
+        }
+    }
+
+    #[test]
+    fn distinct_knot_multiplicities() {
+        let points: Vec<_> = (0..6).map(|i| Point::new(i as f32, 0.0, 0.0)).collect();
+        let curve = BSpline::new(2, points.clone(), vec![0.0, 0.0, 0.0, 1.0, 1.0, 2.0, 3.0, 3.0, 3.0]);
+        assert_eq!(curve.distinct_knots(), vec![(0.0, 3), (1.0, 2), (2.0, 1), (3.0, 3)]);
+        // Knots within floating point error of each other, like those computed by the editing
+        // operations, are the same knot, while knots just over the tolerance apart are distinct
+        let curve = BSpline::new(2, points, vec![0.0, 0.0, 0.0, 1.0, 1.0 + 1e-7, 1.001, 3.0, 3.0, 3.0 + 1e-7]);
+        let distinct = curve.distinct_knots();
+        assert_eq!(distinct.iter().map(|k| k.1).collect::<Vec<_>>(), vec![3, 2, 1, 3]);
+        assert_eq!(distinct[1].0, 1.0);
+        assert_eq!(distinct[2].0, 1.001);
+        assert_eq!(curve.end_conditions(), (true, true));
+    }
+
+    #[test]
+    fn sample_shifted_domain() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.0), Point::new(2.0, -1.0, 0.5),
+                          Point::new(3.0, 2.0, 0.0), Point::new(4.0, 0.0, 1.0), Point::new(5.0, 1.0, 0.0)];
+        let curve = BSpline::new(3, points, vec![-5.0, -5.0, -5.0, -5.0, -1.3, 2.7, 5.0, 5.0, 5.0, 5.0]);
+        assert_eq!(curve.knot_domain(), (-5.0, 5.0));
+        // Step sizes which don't evenly divide the domain, or accumulate error adding them up
+        for &step in &[0.01, 0.1, 0.3, 0.7, 3.0, 20.0] {
+            let samples = sample_domain(curve.knot_domain(), step);
+            assert_eq!(samples.first(), Some(&-5.0));
+            assert_eq!(samples.last(), Some(&5.0));
+            for w in samples.windows(2) {
+                assert!(w[0] < w[1] && w[1] - w[0] <= step * 1.0001, "bad spacing {:?} for step {}", w, step);
+            }
+            assert!(samples.iter().all(|t| *t >= -5.0 && *t <= 5.0));
+            for t in samples.iter() {
+                assert!(curve.point(*t).pos.iter().all(|x| x.is_finite()));
+            }
+            assert_eq!(curve.point(*samples.last().unwrap()).pos, curve.control_points[5].pos);
+        }
+    }
+
+    #[test]
+    fn end_points_at_domain_bounds() {
+        // A clamped curve starts and ends at its end control points
+        let clamped = clamped_cubic();
+        let (a, b) = clamped.knot_domain();
+        assert_eq!(clamped.point(a).pos, clamped.control_points[0].pos);
+        assert_eq!(clamped.point(b).pos, clamped.control_points[4].pos);
+
+        // A floating uniform cubic starts at (P0 + 4 P1 + P2) / 6 and ends at the same
+        // combination of its last three control points
+        let mut floating = clamped_cubic();
+        floating.set_clamped(false);
+        let (a, b) = floating.knot_domain();
+        let p = &floating.control_points;
+        assert_point_eq(floating.point(a), (p[0] + p[1] * 4.0 + p[2]) * (1.0 / 6.0));
+        assert_point_eq(floating.point(b), (p[2] + p[3] * 4.0 + p[4]) * (1.0 / 6.0));
+        // And a floating uniform quadratic at the midpoints of its end segments
+        floating.set_degree(2);
+        floating.set_clamped(false);
+        let (a, b) = floating.knot_domain();
+        let p = &floating.control_points;
+        assert_point_eq(floating.point(a), (p[0] + p[1]) * 0.5);
+        assert_point_eq(floating.point(b), (p[3] + p[4]) * 0.5);
+    }
+
+    #[test]
+    fn point_past_domain_clamps() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.0), Point::new(2.0, -1.0, 0.5),
+                          Point::new(3.0, 2.0, 0.0), Point::new(4.0, 0.0, 1.0)];
+        let mut floating = BSpline::new(3, points.clone(), vec![]);
+        floating.set_clamped(false);
+        let curves = vec![clamped_cubic(), floating, BSpline::new(1, points.clone(), vec![]),
+                          BSpline::new(0, points, vec![])];
+        for curve in curves.iter() {
+            let (a, b) = curve.knot_domain();
+            let end = curve.point(b);
+            // Overshoots like those accumulated by the sampling loops give the end point
+            for t in &[b + 1e-7, b + 1e-4, b + 1.0] {
+                assert_eq!(curve.point(*t).pos, end.pos, "at t = {} for {:?}", t, curve);
+            }
+            assert_eq!(curve.point(a - 1e-4).pos, curve.point(a).pos);
+        }
+        assert_eq!(clamped_cubic().point(2.0 + 1e-7).pos, clamped_cubic().control_points[4].pos);
+    }
+
+    #[test]
+    fn closest_point_on_polyline() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(2.0, 1.0, 0.0), Point::new(3.0, -1.0, 1.0),
+                          Point::new(5.0, 0.0, 0.0)];
+        let curves = vec![BSpline::new(1, points.clone(), vec![]),
+                          BSpline::new(1, points.clone(), vec![0.0, 0.0, 0.3, 2.5, 3.0, 3.0])];
+        for curve in curves.iter() {
+            // Points on the segments, between the samples of each span, and the vertices
+            let on_curve = points.windows(2).flat_map(|s| vec![s[0], s[0] + (s[1] - s[0]) * 0.37]);
+            for p in on_curve.chain(iter::once(points[3])) {
+                let (t, closest) = curve.closest_point(&p);
+                assert!((closest - p).length() < 1e-5, "{:?} is {} from the curve",
+                        p, (closest - p).length());
+                assert!((curve.point(t) - p).length() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn control_polygon_distance_of_polyline_and_cubic() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(2.0, 1.0, 0.0), Point::new(3.0, -1.0, 1.0),
+                          Point::new(5.0, 0.0, 0.0)];
+        assert!(BSpline::new(1, points, vec![]).control_polygon_distance() < 1e-5);
+
+        // Raising the middle point of a clamped cubic by 1 only raises the curve below it by
+        // 1/2, the middle basis function's value there, and that's the farthest point
+        let mut curve = clamped_cubic();
+        curve.control_points = (0..5).map(|i| Point::new(i as f32, 0.0, 0.0)).collect();
+        assert!(curve.control_polygon_distance() < 1e-5);
+        curve.control_points[2] = Point::new(2.0, 1.0, 0.0);
+        assert_point_eq(curve.point(1.0), Point::new(2.0, 0.5, 0.0));
+        let dist = curve.control_polygon_distance();
+        assert!((dist - 0.5).abs() < 1e-4, "expected a distance of 0.5, got {}", dist);
+    }
+
+    #[test]
+    fn osculating_circle_of_parabola() {
+        // This is the parabola y = x^2 with its vertex at t = 0.5
+        let points = vec![Point::new(-1.0, 1.0, 0.0), Point::new(0.0, -1.0, 0.0), Point::new(1.0, 1.0, 0.0)];
+        let curve = BSpline::new(2, points, vec![]);
+        assert!((curve.curvature(0.5) - 2.0).abs() < 1e-5);
+        let (center, radius, normal) = curve.osculating_circle(0.5).unwrap();
+        assert_point_eq(center, Point::new(0.0, 0.5, 0.0));
+        assert!((radius - 0.5).abs() < 1e-5);
+        assert_point_eq(normal, Point::new(0.0, 1.0, 0.0));
+        // Lines have no osculating circle
+        let line = BSpline::new(2, vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0),
+                                        Point::new(2.0, 2.0, 0.0)], vec![]);
+        assert!(line.osculating_circle(0.5).is_none());
+    }
+
+    #[test]
+    fn validate_messages() {
+        let mut curve = clamped_cubic();
+        assert_eq!(curve.validate(), Ok(()));
+        assert_eq!(BSpline::<Point>::empty().validate(), Ok(()));
+
+        curve.degree = 5;
+        assert_eq!(curve.validate(),
+                   Err(String::from("Too few control points for a degree 5 curve, got 5 but need at least 6")));
+        curve.degree = 3;
+        curve.knots.pop();
+        assert_eq!(curve.validate(), Err(String::from("Invalid number of knots, got 8, expected 9")));
+        curve.knots = vec![0.0, 0.0, 0.0, 0.0, 2.0, 1.0, 2.0, 2.0, 2.0];
+        assert_eq!(curve.validate(), Err(String::from("Knots must be finite and non-decreasing")));
+        curve.knots[4] = f32::NAN;
+        assert_eq!(curve.validate(), Err(String::from("Knots must be finite and non-decreasing")));
+        curve.knots = vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0];
+        assert_eq!(curve.validate(), Err(String::from("The knot domain [1, 1] is empty")));
+    }
+
+    #[test]
+    fn interpolate_passes_through_points() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.5), Point::new(3.0, 2.5, 0.0),
+                          Point::new(4.0, 0.0, -1.0), Point::new(6.0, 1.0, 0.0)];
+        let params = BSpline::chord_length_params(&points[..]);
+        for degree in 0..5 {
+            let curve = BSpline::interpolate(&points[..], degree);
+            assert_eq!(curve.validate(), Ok(()));
+            assert_eq!(curve.degree(), usize::max(degree, 1));
+            for (t, p) in params.iter().zip(points.iter()) {
+                assert!((curve.point(*t) - *p).length() < 1e-4, "degree {} curve misses {:?} at {}",
+                        degree, p, t);
+            }
+        }
+    }
+
+    #[test]
+    fn inflection_of_s_curve() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0), Point::new(2.0, -1.0, 0.0),
+                          Point::new(3.0, 0.0, 0.0)];
+        let inflections = BSpline::new(3, points, vec![]).inflection_points();
+        assert_eq!(inflections.len(), 1);
+        assert!((inflections[0] - 0.5).abs() < 1e-4);
+        // Neither a line nor a curve that only bends one way have inflections
+        let line = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0), Point::new(2.0, 2.0, 0.0),
+                        Point::new(3.0, 3.0, 0.0)];
+        assert!(BSpline::new(3, line, vec![]).inflection_points().is_empty());
+        assert!(clamped_cubic().inflection_points().len() > 0);
+        let arc = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0), Point::new(2.0, 0.0, 0.0)];
+        assert!(BSpline::new(2, arc, vec![]).inflection_points().is_empty());
+    }
+
+    #[test]
+    fn chord_length_knots_follow_spacing() {
+        let points: Vec<_> = [0.0, 1.0, 2.0, 10.0, 11.0, 12.0].iter()
+            .map(|x| Point::new(*x, 0.0, 0.0)).collect();
+        let mut curve = BSpline::new(2, points.clone(), vec![]);
+        curve.normalize_domain();
+        curve.rechord_knots();
+        assert_eq!(curve.knots().count(), 9);
+        // The short spans are at the ends, where the control points are close together
+        let expected = [0.0, 0.0, 0.0, 0.125, 0.5, 0.875, 1.0, 1.0, 1.0];
+        for (k, e) in curve.knots().zip(expected.iter()) {
+            assert!((k - e).abs() < 1e-5, "knots {:?}, expected {:?}", curve.knots, expected);
+        }
+        assert_eq!(curve.end_conditions(), (true, true));
+        assert_eq!(curve.validate(), Ok(()));
+
+        // Floating ends stay floating with the same domain
+        let mut floating = BSpline::new(2, points, vec![]);
+        floating.set_clamped(false);
+        let domain = floating.knot_domain();
+        floating.rechord_knots();
+        assert_eq!(floating.knots().count(), 9);
+        assert_eq!(floating.end_conditions(), (false, false));
+        assert_eq!(floating.knot_domain(), domain);
+        assert_eq!(floating.validate(), Ok(()));
+    }
+
+    #[test]
+    fn clamp_one_end() {
+        let mut curve = clamped_cubic();
+        let points: Vec<_> = curve.control_points().cloned().collect();
+        curve.set_end_conditions(true, false);
+        assert_eq!(curve.knots().cloned().collect::<Vec<_>>(),
+                   vec![0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        let distinct = curve.distinct_knots();
+        assert_eq!(distinct.first(), Some(&(0.0, 4)));
+        assert!(distinct[1..].iter().all(|k| k.1 == 1), "{:?}", distinct);
+        assert_eq!(curve.end_conditions(), (true, false));
+        assert!(!curve.is_clamped());
+        assert_eq!(curve.validate(), Ok(()));
+        // Only the clamped start interpolates its end control point
+        let domain = curve.knot_domain();
+        assert_point_eq(curve.point(domain.0), points[0]);
+        assert!((curve.point(domain.1) - points[4]).length() > 1e-2);
+
+        curve.set_end_conditions(false, true);
+        assert_eq!(curve.knots().cloned().collect::<Vec<_>>(),
+                   vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 5.0, 5.0, 5.0]);
+        assert_eq!(curve.distinct_knots().last(), Some(&(5.0, 4)));
+        assert_eq!(curve.end_conditions(), (false, true));
+        let domain = curve.knot_domain();
+        assert_point_eq(curve.point(domain.1), points[4]);
+    }
+
+    #[test]
+    fn normalize_domain_keeps_shape() {
+        let points = clamped_cubic().control_points;
+        let original = BSpline::new(3, points, vec![-1.0, -1.0, -1.0, -1.0, 0.5, 3.0, 3.0, 3.0, 3.0]);
+        let mut curve = original.clone();
+        curve.normalize_domain();
+        assert_eq!(curve.knot_domain(), (0.0, 1.0));
+        assert_eq!(curve.knots().cloned().collect::<Vec<_>>(),
+                   vec![0.0, 0.0, 0.0, 0.0, 0.375, 1.0, 1.0, 1.0, 1.0]);
+        for f in sample_domain((0.0, 1.0), 0.05) {
+            assert_point_eq(curve.point(f), original.point(-1.0 + 4.0 * f));
+        }
+    }
+
+    #[test]
+    fn append_points_in_order() {
+        let mut curve = BSpline::empty();
+        // The later points are all near the middle of the polygon, where insert_point would put them
+        let points = [Point::new(0.0, 0.0, 0.0), Point::new(2.0, 0.0, 0.0), Point::new(1.0, 0.1, 0.0),
+                      Point::new(1.2, -0.1, 0.0), Point::new(0.8, 0.0, 0.0)];
+        for (i, p) in points.iter().enumerate() {
+            assert_eq!(curve.append_point(*p), i);
+        }
+        assert_eq!(curve.control_points().map(|p| p.pos).collect::<Vec<_>>(),
+                   points.iter().map(|p| p.pos).collect::<Vec<_>>());
+        assert_eq!(curve.validate(), Ok(()));
+    }
+
+    #[test]
+    fn greville_point_per_control_point() {
+        let curve = clamped_cubic();
+        let points = curve.greville_points();
+        assert_eq!(points.len(), curve.control_points.len());
+        // The Greville points of a clamped curve start and end at its end points
+        assert_point_eq(points[0], curve.control_points[0]);
+        assert_point_eq(points[4], curve.control_points[4]);
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0)];
+        let line = BSpline::new(1, points.clone(), vec![]);
+        // For a polyline the Greville points are the control points
+        assert_eq!(line.greville_points().len(), 3);
+        for (g, p) in line.greville_points().iter().zip(line.control_points()) {
+            assert_point_eq(*g, *p);
+        }
+        let steps = BSpline::new(0, points, vec![0.0, 1.0, 2.0, 3.0]);
+        assert!(steps.greville_points().is_empty());
+    }
+
+    #[test]
+    fn offset_straight_line() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0), Point::new(2.0, 2.0, 0.0),
+                          Point::new(3.0, 3.0, 0.0)];
+        let line = BSpline::new(2, points, vec![]);
+        let offset = line.offset(0.5, 20);
+        assert_eq!(offset.len(), 20);
+        // The offset is a parallel line to the upper left, exactly half a unit away
+        let normal = Point::new(-1.0, 1.0, 0.0).normalized();
+        for p in offset {
+            assert!((p.dot(&normal) - 0.5).abs() < 1e-5, "{:?} is off the offset line", p);
+            assert!(p.pos[0] >= -0.5 && p.pos[0] <= 3.0);
+        }
+    }
+
+    #[test]
+    fn decimate_straight_run() {
+        let points = (0..10).map(|i| Point::new(i as f32, 0.001 * (i % 2) as f32, 0.0)).collect();
+        let mut line = BSpline::new(1, points, vec![]);
+        line.decimate(0.01);
+        assert_eq!(line.control_points().map(|p| p.pos).collect::<Vec<_>>(),
+                   vec![[0.0, 0.0, 0.0], [9.0, 0.001, 0.0]]);
+        assert_eq!(line.validate(), Ok(()));
+        // The corner of a bent run is kept
+        let points = (0..10).map(|i| Point::new(i as f32, if i < 5 { 0.0 } else { i as f32 - 4.0 }, 0.0))
+            .collect();
+        let mut bent = BSpline::new(3, points, vec![]);
+        bent.decimate(0.01);
+        assert_eq!(bent.control_points().map(|p| p.pos).collect::<Vec<_>>(),
+                   vec![[0.0, 0.0, 0.0], [4.0, 0.0, 0.0], [9.0, 5.0, 0.0]]);
+        assert_eq!(bent.degree(), 2);
+        assert_eq!(bent.validate(), Ok(()));
+    }
+
+    #[test]
+    fn as_fn_matches_point() {
+        let curve = clamped_cubic();
+        let f = curve.as_fn();
+        let samples: Vec<_> = sample_domain(curve.knot_domain(), 0.1).into_iter().map(|t| (t, f(t))).collect();
+        assert_eq!(samples.len(), 21);
+        for (t, p) in samples {
+            assert_eq!(p.pos, curve.point(t).pos);
+        }
+    }
+
+    #[test]
+    fn set_degree_padded_preserves_shape() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.0), Point::new(2.0, 0.0, 1.0)];
+        let original = BSpline::new(2, points, vec![]);
+        let mut curve = original.clone();
+        curve.set_degree_padded(4);
+        assert_eq!(curve.degree(), 4);
+        assert_eq!(curve.control_points().count(), 5);
+        assert_eq!(curve.knot_domain(), original.knot_domain());
+        for t in sample_domain(original.knot_domain(), 0.05) {
+            let p = curve.point(t);
+            let expected = original.point(t);
+            assert!((p - expected).length() < 1e-4, "at t = {}: expected {:?}, got {:?}", t, expected, p);
+        }
+    }
+
+    #[test]
+    fn closed_figure_eight_crosses_once() {
+        let figure_eight = |pts: &[(f32, f32)]| {
+            BSpline::new(3, pts.iter().map(|&(x, y)| Point::new(x, y, 0.0)).collect(), vec![])
+        };
+        // Starting on the right loop, the mirrored halves cross on the x axis mid-curve
+        let curve = figure_eight(&[(2.0, 0.0), (2.0, 1.5), (-2.0, -1.5), (-2.0, 1.5), (2.0, -1.5), (2.0, 0.0)]);
+        let hits = curve.self_intersections();
+        assert_eq!(hits.len(), 1, "expected one crossing, got {:?}", hits);
+        assert!((curve.point(hits[0].0) - curve.point(hits[0].1)).length() < 1e-2);
+        assert!(curve.point(hits[0].0).pos[1].abs() < 1e-2);
+
+        // Starting at the crossing, the curve passes back through its start point halfway along
+        let curve = figure_eight(&[(0.0, 0.0), (2.0, 2.0), (2.0, -2.0), (-2.0, 2.0), (-2.0, -2.0), (0.0, 0.0)]);
+        let hits = curve.self_intersections();
+        assert_eq!(hits.len(), 1, "expected one crossing, got {:?}", hits);
+        let domain = curve.knot_domain();
+        assert!((hits[0].0 - domain.0).abs() < 1e-2, "expected the crossing at the start, got {:?}", hits);
+        assert!((hits[0].1 - 0.5 * (domain.0 + domain.1)).abs() < 1e-2);
+        assert!(curve.point(hits[0].1).length() < 1e-2);
+    }
+}
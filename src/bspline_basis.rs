@@ -11,6 +11,8 @@ pub struct BSplineBasis {
 }
 
 impl BSplineBasis {
+    /// Make a new basis with the knots, which are sorted if they're out of order. Use
+    /// `unsorted_knots` to check for out of order knots first.
     pub fn new(degree: usize, mut knots: Vec<f32>) -> BSplineBasis {
         knots.sort_by(|a, b| a.partial_cmp(b).unwrap());
         let mut modified_knot = 0;
@@ -105,3 +107,23 @@ impl BSplineBasis {
     }
 }
 
+/// Find the knots which are smaller than the knot before them, returning the index of each
+/// one. The constructors sort the knots, so this is used to report out of order knots in
+/// the input instead of silently reordering them.
+pub fn unsorted_knots(knots: &[f32]) -> Vec<usize> {
+    knots.windows(2).enumerate().filter(|&(_, k)| k[1] < k[0]).map(|(i, _)| i + 1).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn out_of_order_knots() {
+        assert!(unsorted_knots(&[0.0, 0.0, 1.0, 1.0, 2.0]).is_empty());
+        assert!(unsorted_knots(&[]).is_empty());
+        assert_eq!(unsorted_knots(&[0.0, 0.0, 2.0, 1.0, 3.0, 3.0]), vec![3]);
+        assert_eq!(unsorted_knots(&[0.0, 3.0, 1.0, 2.0, 1.5]), vec![2, 4]);
+    }
+}
+
@@ -1,11 +1,18 @@
 #![allow(dead_code)]
 
 use std::fmt::Debug;
+use std::f32;
 use std::iter;
 use std::slice;
+use std::ops::{Mul, Sub};
+
+use rulinalg::matrix::Matrix;
+use rulinalg::vector::Vector;
 
 use bezier::Interpolate;
 use bspline::BSpline;
+use bspline_basis::BSplineBasis;
+use point::{Point, HomogeneousPoint};
 
 /// Represents a B-spline surface that will use polynomials of the
 /// specified degree along u and v to to interpolate the control mesh
@@ -23,6 +30,7 @@ pub struct BSplineSurf<T> {
 impl<T: Interpolate + Copy + Debug> BSplineSurf<T> {
     /// Make a new tensor product B-spline surface. The surface will be the product
     /// of a degree.0, knots.0 and degree.1, knots.1 B-spline using the control mesh.
+    /// The knots must be in non-decreasing order, they aren't sorted for you.
     pub fn new(degree: (usize, usize), knots: (Vec<f32>, Vec<f32>), control_mesh: Vec<Vec<T>>) -> BSplineSurf<T> {
         if control_mesh.is_empty() {
             panic!("Surface control mesh cannot be empty!");
@@ -33,6 +41,42 @@ impl<T: Interpolate + Copy + Debug> BSplineSurf<T> {
                       control_mesh: control_mesh
                     }
     }
+    /// Check if the surface is clamped at both ends along u
+    pub fn is_clamped_u(&self) -> bool {
+        knot_curve(self.degree_u, &self.knots_u, self.control_mesh.len()).is_clamped()
+    }
+    /// Check if the surface is clamped at both ends along v
+    pub fn is_clamped_v(&self) -> bool {
+        knot_curve(self.degree_v, &self.knots_v, self.control_mesh[0].len()).is_clamped()
+    }
+    /// Toggle whether the surface is clamped along u, regenerating a uniform u knot vector
+    pub fn set_clamped_u(&mut self, clamped: bool) {
+        let mut curve = knot_curve(self.degree_u, &self.knots_u, self.control_mesh.len());
+        curve.set_clamped(clamped);
+        self.knots_u = curve.knots().cloned().collect();
+    }
+    /// Toggle whether the surface is clamped along v, regenerating a uniform v knot vector
+    pub fn set_clamped_v(&mut self, clamped: bool) {
+        let mut curve = knot_curve(self.degree_v, &self.knots_v, self.control_mesh[0].len());
+        curve.set_clamped(clamped);
+        self.knots_v = curve.knots().cloned().collect();
+    }
+    /// Set the u knot vector, if the knots aren't valid for the control mesh they're
+    /// rejected and a message describing the problem is returned.
+    pub fn set_knots_u(&mut self, knots: Vec<f32>) -> Result<(), String> {
+        validate_knots(&knots, self.degree_u, self.control_mesh.len())
+            .map_err(|e| format!("Invalid u knots: {}", e))?;
+        self.knots_u = knots;
+        Ok(())
+    }
+    /// Set the v knot vector, if the knots aren't valid for the control mesh they're
+    /// rejected and a message describing the problem is returned.
+    pub fn set_knots_v(&mut self, knots: Vec<f32>) -> Result<(), String> {
+        validate_knots(&knots, self.degree_v, self.control_mesh[0].len())
+            .map_err(|e| format!("Invalid v knots: {}", e))?;
+        self.knots_v = knots;
+        Ok(())
+    }
     /// Get the u curve degree
     pub fn degree_u(&self) -> usize {
         self.degree_u
@@ -123,5 +167,646 @@ impl<T: Interpolate + Copy + Debug> BSplineSurf<T> {
         }
         BSpline::new(self.degree_u, isoline_ctrl_pts, self.knots_u.clone())
     }
+    /// Compute a point on the surface at `(u, v)`
+    pub fn point(&self, u: f32, v: f32) -> T {
+        self.isoline_u(v).point(u)
+    }
+}
+
+impl<T: Interpolate + Sub<Output = T> + Mul<f32, Output = T> + Copy + Debug> BSplineSurf<T> {
+    /// Compute the partial derivative of the surface along u on the isoline along u
+    /// for a fixed value of v, giving the tangent vectors in the u direction along it
+    pub fn derivative_isoline_u(&self, v: f32) -> BSpline<T> {
+        self.isoline_u(v).derivative()
+    }
+    /// Compute the partial derivative of the surface along v on the isoline along v
+    /// for a fixed value of u, giving the tangent vectors in the v direction along it
+    pub fn derivative_isoline_v(&self, u: f32) -> BSpline<T> {
+        self.isoline_v(u).derivative()
+    }
+    /// Compute the second partial derivatives of the surface at `(u, v)`, returned as
+    /// `(d2/du2, d2/dv2, d2/dudv)`. The surface is straight along directions it's below
+    /// degree 2 in, so the second derivative along them is zero.
+    pub fn second_derivatives(&self, u: f32, v: f32) -> (T, T, T) {
+        let zero = self.control_mesh[0][0] * 0.0;
+        let duu = if self.degree_u > 1 {
+            self.derivative_isoline_u(v).derivative().point(u)
+        } else {
+            zero
+        };
+        let dvv = if self.degree_v > 1 {
+            self.derivative_isoline_v(u).derivative().point(v)
+        } else {
+            zero
+        };
+        let duv = if self.degree_u > 0 && self.degree_v > 0 {
+            // Differentiate each column along u to get the control points of the curve along v
+            // of the u tangents, then differentiate that along v
+            let du_ctrl_pts = (0..self.control_mesh[0].len()).map(|j| {
+                let column = self.control_mesh.iter().map(|r| r[j]).collect();
+                BSpline::new(self.degree_u, column, self.knots_u.clone()).derivative().point(u)
+            }).collect();
+            BSpline::new(self.degree_v, du_ctrl_pts, self.knots_v.clone()).derivative().point(v)
+        } else {
+            zero
+        };
+        (duu, dvv, duv)
+    }
+}
+
+impl BSplineSurf<Point> {
+    /// Find the surface interpolating the `curves`, which must all have the same degree and
+    /// knots. The curves are interpolated across u with a clamped uniform basis of `degree`,
+    /// so the isoline along v at the Greville abscissa for each curve reproduces it.
+    pub fn interpolate_curves(curves: &[BSpline<Point>], degree: usize) -> BSplineSurf<Point> {
+        let coords: Vec<Vec<Vec<f32>>> = (0..3).map(|c| {
+            curves.iter().map(|curve| curve.control_points().map(|p| p.pos[c]).collect()).collect()
+        }).collect();
+        let basis = BSplineBasis::clamped_uniform(degree, curves.len());
+        let solved = solve_nodal_interpolation(&basis, &coords[..]);
+        let mesh = (0..curves.len()).map(|i| {
+            (0..curves[0].control_points.len())
+                .map(|j| Point::new(solved[0][i][j], solved[1][i][j], solved[2][i][j])).collect()
+        }).collect();
+        BSplineSurf::new((basis.degree(), curves[0].degree()), (basis.knots, curves[0].knots().cloned().collect()),
+                         mesh)
+    }
+    /// Mirror the surface through the xy plane by negating z, to convert it between left and
+    /// right handed coordinates. The surface is reversed along v as well, so its normals and
+    /// the winding of its tessellation are mirrored with it instead of turning inside out.
+    pub fn flip_handedness(&mut self) {
+        for r in self.control_mesh.iter_mut() {
+            r.reverse();
+            for p in r.iter_mut() {
+                p.pos[2] = -p.pos[2];
+            }
+        }
+        let (a, b) = (self.knots_v[0], self.knots_v[self.knots_v.len() - 1]);
+        self.knots_v = self.knots_v.iter().rev().map(|k| a + b - k).collect();
+    }
+    /// Move each control point in row `index` of the control mesh by `delta`
+    pub fn translate_row(&mut self, index: usize, delta: Point) {
+        for p in self.control_mesh[index].iter_mut() {
+            *p = *p + delta;
+        }
+    }
+    /// Move each control point in column `index` of the control mesh by `delta`
+    pub fn translate_col(&mut self, index: usize, delta: Point) {
+        for r in self.control_mesh.iter_mut() {
+            r[index] = r[index] + delta;
+        }
+    }
+    /// Find the control point closest to the ray from `origin` along the normalized `dir`,
+    /// returning its `(row, column)` if it's within `max_dist` of the ray.
+    pub fn pick_control_point(&self, origin: &Point, dir: &Point, max_dist: f32) -> Option<(usize, usize)> {
+        let mut picked = None;
+        let mut nearest = max_dist;
+        for (i, r) in self.control_mesh.iter().enumerate() {
+            for (j, p) in r.iter().enumerate() {
+                let v = *p - *origin;
+                let t = v.dot(dir);
+                if t < 0.0 {
+                    continue;
+                }
+                let d = (v - *dir * t).length();
+                if d < nearest {
+                    nearest = d;
+                    picked = Some((i, j));
+                }
+            }
+        }
+        picked
+    }
+    /// Find where the ray from `origin` along `dir` first hits the surface, returning the
+    /// `(u, v)` parameters of the hit. The surface is sampled into a grid of triangles with
+    /// `samples` steps along u and v, the hit parameters are interpolated within the triangle hit.
+    pub fn intersect_ray(&self, origin: &Point, dir: &Point, samples: usize) -> Option<(f32, f32)> {
+        let samples = usize::max(samples, 1);
+        let (u_vals, v_vals, grid) = self.sample_grid(samples + 1, samples + 1);
+
+        let mut hit = None;
+        let mut nearest = f32::MAX;
+        for j in 0..v_vals.len().saturating_sub(1) {
+            for i in 0..u_vals.len().saturating_sub(1) {
+                let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+                for tri in &[[0, 1, 2], [0, 2, 3]] {
+                    let c: Vec<_> = tri.iter().map(|k| corners[*k]).collect();
+                    let p = [grid[c[0].1][c[0].0], grid[c[1].1][c[1].0], grid[c[2].1][c[2].0]];
+                    if let Some((t, s, r)) = intersect_triangle(origin, dir, &p) {
+                        if t < nearest {
+                            nearest = t;
+                            let u = u_vals[c[0].0] + s * (u_vals[c[1].0] - u_vals[c[0].0])
+                                + r * (u_vals[c[2].0] - u_vals[c[0].0]);
+                            let v = v_vals[c[0].1] + s * (v_vals[c[1].1] - v_vals[c[0].1])
+                                + r * (v_vals[c[2].1] - v_vals[c[0].1]);
+                            hit = Some((u, v));
+                        }
+                    }
+                }
+            }
+        }
+        hit
+    }
+    /// Tessellate the surface into a grid of `res_u` by `res_v` vertices, returning the
+    /// vertices and the quad faces between them, indexed into the vertices.
+    pub fn tessellate_quads(&self, res_u: usize, res_v: usize) -> (Vec<Point>, Vec<[u32; 4]>) {
+        let res_u = usize::max(res_u, 2);
+        let res_v = usize::max(res_v, 2);
+        let (_, _, grid) = self.sample_grid(res_u, res_v);
+        let points = grid.into_iter().flat_map(|r| r.into_iter()).collect();
+        let mut quads = Vec::with_capacity((res_u - 1) * (res_v - 1));
+        for j in 0..res_v - 1 {
+            for i in 0..res_u - 1 {
+                let idx = |i: usize, j: usize| (j * res_u + i) as u32;
+                quads.push([idx(i, j), idx(i + 1, j), idx(i + 1, j + 1), idx(i, j + 1)]);
+            }
+        }
+        (points, quads)
+    }
+    /// Tessellate the surface into a grid of `res_u` by `res_v` vertices, returning the
+    /// vertices, their normals and the triangles between them, indexed into the vertices.
+    pub fn tessellate(&self, res_u: usize, res_v: usize) -> (Vec<Point>, Vec<Point>, Vec<[u32; 3]>) {
+        let (points, quads) = self.tessellate_quads(res_u, res_v);
+        let (u_vals, v_vals, _) = self.sample_grid(usize::max(res_u, 2), usize::max(res_v, 2));
+        // The derivatives are evaluated along whole isolines at once, instead of building
+        // the derivative isolines again for each vertex
+        let du: Vec<Vec<Point>> = v_vals.iter().map(|v| {
+            let d = self.derivative_isoline_u(*v);
+            u_vals.iter().map(|u| d.point(*u)).collect()
+        }).collect();
+        let dv: Vec<Vec<Point>> = u_vals.iter().map(|u| {
+            let d = self.derivative_isoline_v(*u);
+            v_vals.iter().map(|v| d.point(*v)).collect()
+        }).collect();
+        let mut normals = Vec::with_capacity(points.len());
+        for j in 0..v_vals.len() {
+            for i in 0..u_vals.len() {
+                normals.push(du[j][i].cross(&dv[i][j]).normalized());
+            }
+        }
+        let triangles = quads.iter().flat_map(|q| vec![[q[0], q[1], q[2]], [q[0], q[2], q[3]]]).collect();
+        (points, normals, triangles)
+    }
+    /// Compute the unit normal of the surface at `(u, v)`, the cross product of the partial
+    /// derivatives along u and v
+    pub fn normal(&self, u: f32, v: f32) -> Point {
+        let du = self.derivative_isoline_u(v).point(u);
+        let dv = self.derivative_isoline_v(u).point(v);
+        du.cross(&dv).normalized()
+    }
+    /// Compute the Gaussian curvature of the surface at `(u, v)`, the product of the
+    /// principal curvatures. Returns 0 where the parameterization is degenerate.
+    pub fn gaussian_curvature(&self, u: f32, v: f32) -> f32 {
+        match self.fundamental_forms(u, v) {
+            Some(((e, f, g), (l, m, n))) => (l * n - m * m) / (e * g - f * f),
+            None => 0.0,
+        }
+    }
+    /// Compute the mean curvature of the surface at `(u, v)`, the average of the principal
+    /// curvatures, with its sign given by the surface normal. Returns 0 where the
+    /// parameterization is degenerate.
+    pub fn mean_curvature(&self, u: f32, v: f32) -> f32 {
+        match self.fundamental_forms(u, v) {
+            Some(((e, f, g), (l, m, n))) => (e * n - 2.0 * f * m + g * l) / (2.0 * (e * g - f * f)),
+            None => 0.0,
+        }
+    }
+    /// Compute the coefficients `((E, F, G), (L, M, N))` of the first and second fundamental
+    /// forms at `(u, v)`. Returns None if the partial derivatives are zero or parallel, where
+    /// the surface has no well defined normal.
+    fn fundamental_forms(&self, u: f32, v: f32) -> Option<((f32, f32, f32), (f32, f32, f32))> {
+        let du = self.derivative_isoline_u(v).point(u);
+        let dv = self.derivative_isoline_v(u).point(v);
+        let (e, f, g) = (du.dot(&du), du.dot(&dv), dv.dot(&dv));
+        if e * g - f * f <= 1e-6 * e * g {
+            return None;
+        }
+        let normal = du.cross(&dv).normalized();
+        let (duu, dvv, duv) = self.second_derivatives(u, v);
+        Some(((e, f, g), (duu.dot(&normal), duv.dot(&normal), dvv.dot(&normal))))
+    }
+    /// Sample the surface at `res_u` by `res_v` evenly spaced parameter values across its
+    /// domain, returning the u and v values and the grid of points indexed by `[v][u]`.
+    fn sample_grid(&self, res_u: usize, res_v: usize) -> (Vec<f32>, Vec<f32>, Vec<Vec<Point>>) {
+        let params = |domain: (f32, f32), res: usize| -> Vec<f32> {
+            (0..res).map(|i| {
+                if i + 1 == res {
+                    domain.1
+                } else {
+                    domain.0 + (domain.1 - domain.0) * i as f32 / (res - 1) as f32
+                }
+            }).collect()
+        };
+        let u_vals = params(self.knot_domain_u(), res_u);
+        let v_vals = params(self.knot_domain_v(), res_v);
+        let grid = v_vals.iter().map(|v| {
+            let isoline = self.isoline_u(*v);
+            u_vals.iter().map(|u| isoline.point(*u)).collect()
+        }).collect();
+        (u_vals, v_vals, grid)
+    }
+}
+
+impl BSplineSurf<HomogeneousPoint> {
+    /// Find the rational surface interpolating the rational `curves`, like `interpolate_curves`
+    /// but solved in homogeneous coordinates so the weights are interpolated along with the
+    /// weighted control points.
+    pub fn interpolate_rational_curves(curves: &[BSpline<HomogeneousPoint>], degree: usize)
+        -> BSplineSurf<HomogeneousPoint>
+    {
+        let coords: Vec<Vec<Vec<f32>>> = (0..4).map(|c| {
+            curves.iter().map(|curve| {
+                curve.control_points().map(|p| if c < 3 { p.pos[c] } else { p.w }).collect()
+            }).collect()
+        }).collect();
+        let basis = BSplineBasis::clamped_uniform(degree, curves.len());
+        let solved = solve_nodal_interpolation(&basis, &coords[..]);
+        let mesh = (0..curves.len()).map(|i| {
+            (0..curves[0].control_points.len()).map(|j| {
+                HomogeneousPoint { pos: [solved[0][i][j], solved[1][i][j], solved[2][i][j]], w: solved[3][i][j] }
+            }).collect()
+        }).collect();
+        BSplineSurf::new((basis.degree(), curves[0].degree()), (basis.knots, curves[0].knots().cloned().collect()),
+                         mesh)
+    }
+    /// Compute a point on the rational surface at `(u, v)`, projected back to 3D
+    pub fn rational_point(&self, u: f32, v: f32) -> Point {
+        self.point(u, v).project()
+    }
+    /// Approximate the rational surface with a bicubic polynomial one for display, which
+    /// interpolates the rational surface at `res` by `res` points over the same domain. The
+    /// approximation matches the rational surface at the Greville abscissa of its knots.
+    pub fn polynomial_approximation(&self, res: usize) -> BSplineSurf<Point> {
+        let degree = 3;
+        let res = usize::max(res, degree + 1);
+        // A clamped uniform basis remapped to the surface's domain along each direction
+        let basis = |domain: (f32, f32)| {
+            let uniform = BSplineBasis::clamped_uniform(degree, res);
+            let end = uniform.knot_domain().1;
+            let knots = uniform.knots.iter().map(|k| domain.0 + (domain.1 - domain.0) * k / end).collect();
+            BSplineBasis::new(degree, knots)
+        };
+        let (basis_u, basis_v) = (basis(self.knot_domain_u()), basis(self.knot_domain_v()));
+        let (abscissa_u, abscissa_v) = (basis_u.greville_abscissa(), basis_v.greville_abscissa());
+        let samples: Vec<Vec<Point>> = abscissa_u.iter()
+            .map(|u| abscissa_v.iter().map(|v| self.rational_point(*u, *v)).collect()).collect();
+        // Interpolate the samples along v for each u, then the resulting control points along u
+        let coords: Vec<Vec<Vec<f32>>> = (0..3).map(|c| {
+            (0..res).map(|j| (0..res).map(|i| samples[i][j].pos[c]).collect()).collect()
+        }).collect();
+        let along_v = solve_nodal_interpolation(&basis_v, &coords[..]);
+        let coords: Vec<Vec<Vec<f32>>> = along_v.iter()
+            .map(|coord| (0..res).map(|i| (0..res).map(|j| coord[j][i]).collect()).collect()).collect();
+        let solved = solve_nodal_interpolation(&basis_u, &coords[..]);
+        let mesh = (0..res).map(|i| {
+            (0..res).map(|j| Point::new(solved[0][i][j], solved[1][i][j], solved[2][i][j])).collect()
+        }).collect();
+        BSplineSurf::new((degree, degree), (basis_u.knots, basis_v.knots), mesh)
+    }
+}
+
+/// Solve for the control mesh of the surface interpolating curves across u with `basis`
+/// at its Greville abscissa. Each coordinate is solved for separately, `coords[c][i][j]`
+/// is coordinate `c` of control point `j` on curve `i` and the solved mesh is indexed the same way.
+fn solve_nodal_interpolation(basis: &BSplineBasis, coords: &[Vec<Vec<f32>>]) -> Vec<Vec<Vec<f32>>> {
+    let abscissa = basis.greville_abscissa();
+    let num_curves = coords[0].len();
+    let num_points = coords[0][0].len();
+    // The collocation matrix of the basis functions at the abscissa
+    let f = Matrix::from_fn(num_curves, abscissa.len(), |i, j| basis.eval(abscissa[j], i));
+    coords.iter().map(|coord| {
+        let mut solved = vec![vec![0.0; num_points]; num_curves];
+        // Solve each column of the mesh
+        for j in 0..num_points {
+            let rhs = Vector::new((0..num_curves).map(|i| coord[i][j]).collect::<Vec<f32>>());
+            let result = f.clone().solve(rhs).expect("System could not be solved!?");
+            for i in 0..num_curves {
+                solved[i][j] = result[i];
+            }
+        }
+        solved
+    }).collect()
+}
+
+/// Make a curve with the degree, knots and number of control points along one direction
+/// of a surface, to reuse the curve's end condition handling for its knots
+fn knot_curve(degree: usize, knots: &[f32], num_points: usize) -> BSpline<f32> {
+    BSpline::new(degree, vec![0.0; num_points], knots.to_vec())
+}
+
+/// Check the knots are valid for `num_points` control points of `degree` along one
+/// direction of a surface
+fn validate_knots(knots: &[f32], degree: usize, num_points: usize) -> Result<(), String> {
+    if knots.len() != num_points + degree + 1 {
+        return Err(format!("got {} knots, expected {}", knots.len(), num_points + degree + 1));
+    }
+    if knots.iter().any(|k| !k.is_finite()) || knots.windows(2).any(|k| k[1] < k[0]) {
+        return Err(String::from("knots must be finite and non-decreasing"));
+    }
+    if knots[degree] >= knots[knots.len() - 1 - degree] {
+        return Err(String::from("the knot domain is empty"));
+    }
+    Ok(())
 }
 
+/// Intersect the ray with the triangle `p`, returning the distance along the ray and the
+/// barycentric coordinates of the hit relative to `p[1]` and `p[2]`.
+fn intersect_triangle(origin: &Point, dir: &Point, p: &[Point; 3]) -> Option<(f32, f32, f32)> {
+    let e1 = p[1] - p[0];
+    let e2 = p[2] - p[0];
+    let pvec = dir.cross(&e2);
+    let det = e1.dot(&pvec);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let tvec = *origin - p[0];
+    let s = tvec.dot(&pvec) / det;
+    if s < 0.0 || s > 1.0 {
+        return None;
+    }
+    let qvec = tvec.cross(&e1);
+    let r = dir.dot(&qvec) / det;
+    if r < 0.0 || s + r > 1.0 {
+        return None;
+    }
+    let t = e2.dot(&qvec) / det;
+    if t < 0.0 { None } else { Some((t, s, r)) }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A clamped surface of degree 2 along u and 1 along v over a 4x3 grid of control points
+    fn clamped_surf() -> BSplineSurf<Point> {
+        let mesh = (0..4).map(|i| (0..3).map(|j| Point::new(i as f32, (i * j) as f32 * 0.1, j as f32)).collect())
+            .collect();
+        BSplineSurf::new((2, 1), (vec![0.0, 0.0, 0.0, 1.0, 2.0, 2.0, 2.0], vec![0.0, 0.0, 1.0, 2.0, 2.0]), mesh)
+    }
+
+    #[test]
+    fn toggle_clamped_u() {
+        let mut surf = clamped_surf();
+        assert!(surf.is_clamped_u() && surf.is_clamped_v());
+        // A clamped surface interpolates its corner control points
+        let corner = surf.point(surf.knot_domain_u().0, surf.knot_domain_v().0);
+        assert!((corner - surf.control_mesh[0][0]).length() < 1e-5);
+
+        surf.set_clamped_u(false);
+        assert!(!surf.is_clamped_u() && surf.is_clamped_v());
+        assert_eq!(surf.knots_u, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let corner = surf.point(surf.knot_domain_u().0, surf.knot_domain_v().0);
+        assert!((corner - surf.control_mesh[0][0]).length() > 0.1);
+
+        surf.set_clamped_u(true);
+        assert!(surf.is_clamped_u());
+        assert_eq!(surf.knots_u, vec![0.0, 0.0, 0.0, 1.0, 2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn translate_row_and_col() {
+        let mut surf = clamped_surf();
+        let orig = surf.control_mesh.clone();
+        let delta = Point::new(0.5, -1.0, 2.0);
+        surf.translate_row(1, delta);
+        for (i, r) in surf.control_mesh.iter().enumerate() {
+            for (j, p) in r.iter().enumerate() {
+                let expect = if i == 1 { orig[i][j] + delta } else { orig[i][j] };
+                assert_eq!(p.pos, expect.pos);
+            }
+        }
+        surf.control_mesh = orig.clone();
+        surf.translate_col(2, delta);
+        for (i, r) in surf.control_mesh.iter().enumerate() {
+            for (j, p) in r.iter().enumerate() {
+                let expect = if j == 2 { orig[i][j] + delta } else { orig[i][j] };
+                assert_eq!(p.pos, expect.pos);
+            }
+        }
+    }
+
+    #[test]
+    fn tessellate_quads_grid() {
+        let surf = clamped_surf();
+        let (points, quads) = surf.tessellate_quads(5, 4);
+        assert_eq!(points.len(), 5 * 4);
+        assert_eq!(quads.len(), (5 - 1) * (4 - 1));
+        assert!(quads.iter().all(|q| q.iter().all(|i| (*i as usize) < points.len())));
+        // The corners of the grid are the corners of the surface
+        assert!((points[0] - surf.control_mesh[0][0]).length() < 1e-5);
+        assert!((points[points.len() - 1] - surf.control_mesh[3][2]).length() < 1e-5);
+    }
+
+    #[test]
+    fn tessellate_planar_surf() {
+        // A bilinear patch in the xz plane, its normals all point along -y
+        let mesh = (0..3).map(|i| (0..3).map(|j| Point::new(i as f32, 0.0, j as f32)).collect()).collect();
+        let surf = BSplineSurf::new((1, 2), (vec![0.0, 0.0, 1.0, 2.0, 2.0], vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0]), mesh);
+        let (points, normals, triangles) = surf.tessellate(4, 6);
+        assert_eq!(points.len(), 4 * 6);
+        assert_eq!(normals.len(), points.len());
+        assert_eq!(triangles.len(), 2 * (4 - 1) * (6 - 1));
+        assert!(triangles.iter().all(|t| t.iter().all(|i| (*i as usize) < points.len())));
+        for n in normals.iter().chain([surf.normal(0.5, 0.5)].iter()) {
+            assert!((*n - Point::new(0.0, -1.0, 0.0)).length() < 1e-5, "got normal {:?}", n);
+        }
+    }
+
+    #[test]
+    fn derivative_isolines_match_finite_differences() {
+        let mesh = (0..4).map(|i| (0..4).map(|j| Point::new(i as f32, j as f32 + 0.3 * (i * i) as f32,
+                                                             ((i + 2 * j) % 3) as f32))
+                                         .collect()).collect();
+        let knots = vec![0.0, 0.0, 0.0, 1.0, 2.0, 2.0, 2.0];
+        let surf = BSplineSurf::new((2, 2), (knots.clone(), knots), mesh);
+        let h = 1e-2;
+        for &u in &[0.3, 0.7, 1.4, 1.8] {
+            for &v in &[0.2, 0.9, 1.3, 1.6] {
+                let du = (surf.point(u + h, v) - surf.point(u - h, v)) * (0.5 / h);
+                let dv = (surf.point(u, v + h) - surf.point(u, v - h)) * (0.5 / h);
+                let tan_u = surf.derivative_isoline_u(v).point(u);
+                let tan_v = surf.derivative_isoline_v(u).point(v);
+                assert!((tan_u - du).length() < 1e-2, "d/du at ({}, {}): {:?} vs {:?}", u, v, tan_u, du);
+                assert!((tan_v - dv).length() < 1e-2, "d/dv at ({}, {}): {:?} vs {:?}", u, v, tan_v, dv);
+            }
+        }
+    }
+
+    #[test]
+    fn isolines_lie_on_surface() {
+        let surf = clamped_surf();
+        // Evaluate the tensor product surface directly from its basis functions
+        let basis_u = BSplineBasis::new(2, surf.knots_u.clone());
+        let basis_v = BSplineBasis::new(1, surf.knots_v.clone());
+        let direct = |u: f32, v: f32| {
+            let mut p = Point::new(0.0, 0.0, 0.0);
+            for (i, row) in surf.control_mesh.iter().enumerate() {
+                for (j, x) in row.iter().enumerate() {
+                    p = p + *x * (basis_u.eval(u, i) * basis_v.eval(v, j));
+                }
+            }
+            p
+        };
+        for &fixed in &[0.0, 0.4, 1.0, 1.5, 2.0] {
+            let along_u = surf.isoline_u(fixed);
+            let along_v = surf.isoline_v(fixed);
+            assert_eq!(along_u.degree(), 2);
+            assert_eq!(along_v.degree(), 1);
+            for i in 0..21 {
+                let t = i as f32 * 0.1;
+                let (p, expected) = (along_u.point(t), direct(t, fixed));
+                assert!((p - expected).length() < 1e-5, "isoline u at v = {}, u = {}: {:?} vs {:?}",
+                        fixed, t, p, expected);
+                assert!((p - surf.point(t, fixed)).length() < 1e-5);
+                let (p, expected) = (along_v.point(t), direct(fixed, t));
+                assert!((p - expected).length() < 1e-5, "isoline v at u = {}, v = {}: {:?} vs {:?}",
+                        fixed, t, p, expected);
+                assert!((p - surf.point(fixed, t)).length() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn planar_surf_curvature() {
+        // A biquadratic patch in the xy plane with unevenly spaced control points, so it has
+        // second derivatives but they all lie in the plane
+        let mesh = (0..3).map(|i| (0..3).map(|j| Point::new((i * i) as f32, j as f32 + 0.5 * (i * j) as f32, 0.0))
+                                         .collect()).collect();
+        let knots = vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+        let surf = BSplineSurf::new((2, 2), (knots.clone(), knots.clone()), mesh);
+        for i in 0..5 {
+            for j in 0..5 {
+                let (u, v) = (i as f32 / 4.0, j as f32 / 4.0);
+                assert!(surf.gaussian_curvature(u, v).abs() < 1e-4, "K = {} at ({}, {})",
+                        surf.gaussian_curvature(u, v), u, v);
+                assert!(surf.mean_curvature(u, v).abs() < 1e-4);
+            }
+        }
+        assert!(surf.second_derivatives(0.5, 0.5).2.length() > 0.0);
+
+        // Collapsing a row of the mesh to a point leaves no normal along that edge
+        let mut mesh: Vec<Vec<_>> = (0..3).map(|i| (0..3).map(|j| Point::new(i as f32, j as f32, (i * j) as f32))
+                                                     .collect()).collect();
+        mesh[0] = vec![Point::new(0.0, 0.0, 0.0); 3];
+        let surf = BSplineSurf::new((2, 2), (knots.clone(), knots), mesh);
+        assert_eq!(surf.gaussian_curvature(0.0, 0.5), 0.0);
+        assert_eq!(surf.mean_curvature(0.0, 0.5), 0.0);
+    }
+
+    #[test]
+    fn flip_handedness_keeps_winding() {
+        let mesh = (0..3).map(|i| (0..4).map(|j| Point::new(i as f32, (i * j) as f32 * 0.25, j as f32))
+                              .collect())
+            .collect();
+        let surf = BSplineSurf::new((2, 2), (vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0],
+                                             vec![0.0, 0.0, 0.0, 1.0, 2.0, 2.0, 2.0]), mesh);
+        let mut flipped = surf.clone();
+        flipped.flip_handedness();
+        // The flipped surface is the mirror image and its normals are the mirrored normals
+        let mirror = |p: Point| Point::new(p.pos[0], p.pos[1], -p.pos[2]);
+        let (points, normals, triangles) = surf.tessellate(5, 5);
+        let (flipped_points, flipped_normals, flipped_triangles) = flipped.tessellate(5, 5);
+        assert_eq!(triangles, flipped_triangles);
+        for j in 0..5 {
+            for i in 0..5 {
+                // The vertices are ordered by v then u, and v now runs the other way
+                let (a, b) = (j * 5 + i, (4 - j) * 5 + i);
+                assert!((mirror(points[a]) - flipped_points[b]).length() < 1e-5);
+                assert!((mirror(normals[a]) - flipped_normals[b]).length() < 1e-4);
+            }
+        }
+        // The triangles wind the same way around the normals as before
+        for (pts, ns) in [(&points, &normals), (&flipped_points, &flipped_normals)].iter() {
+            for t in triangles.iter() {
+                let (a, b, c) = (pts[t[0] as usize], pts[t[1] as usize], pts[t[2] as usize]);
+                assert!((b - a).cross(&(c - a)).dot(&ns[t[0] as usize]) > 0.0);
+            }
+        }
+        // Flipping back gives the original surface
+        flipped.flip_handedness();
+        assert_eq!(flipped.knots_v, surf.knots_v);
+        for (r, s) in flipped.control_mesh.iter().zip(surf.control_mesh.iter()) {
+            for (a, b) in r.iter().zip(s.iter()) {
+                assert_eq!(a.pos, b.pos);
+            }
+        }
+    }
+
+    #[test]
+    fn rational_interpolation_unit_weights() {
+        let knots = vec![0.0, 0.0, 0.0, 1.0, 2.0, 2.0, 2.0];
+        let curves: Vec<_> = (0..3).map(|i| {
+            let points = (0..4).map(|j| Point::new(j as f32, (i * j) as f32 * 0.5, i as f32)).collect();
+            BSpline::new(2, points, knots.clone())
+        }).collect();
+        let rational_curves: Vec<_> = curves.iter().map(|c| {
+            BSpline::new(2, c.control_points().map(|p| HomogeneousPoint::new(*p, 1.0)).collect(), knots.clone())
+        }).collect();
+        let surf = BSplineSurf::interpolate_curves(&curves[..], 2);
+        let rational = BSplineSurf::interpolate_rational_curves(&rational_curves[..], 2);
+        assert_eq!(rational.knots_u, surf.knots_u);
+        assert_eq!(rational.knots_v, surf.knots_v);
+        for (r, s) in rational.control_mesh.iter().zip(surf.control_mesh.iter()) {
+            for (a, b) in r.iter().zip(s.iter()) {
+                assert!((a.w - 1.0).abs() < 1e-5);
+                assert!((a.project() - *b).length() < 1e-5, "{:?} != {:?}", a.project(), b);
+            }
+        }
+    }
+
+    #[test]
+    fn rational_interpolation_cylinder() {
+        // Quarter circle arcs of the unit cylinder at z = 0 and z = 2, interpolating them
+        // linearly must give the exact cylinder patch between them
+        let w = f32::consts::FRAC_1_SQRT_2;
+        let curves: Vec<_> = [0.0, 2.0].iter().map(|z| {
+            let points = vec![HomogeneousPoint::new(Point::new(1.0, 0.0, *z), 1.0),
+                              HomogeneousPoint::new(Point::new(1.0, 1.0, *z), w),
+                              HomogeneousPoint::new(Point::new(0.0, 1.0, *z), 1.0)];
+            BSpline::new(2, points, vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0])
+        }).collect();
+        let surf = BSplineSurf::interpolate_rational_curves(&curves[..], 1);
+        for i in 0..5 {
+            for j in 0..5 {
+                let (u, v) = (i as f32 / 4.0, j as f32 / 4.0);
+                let p = surf.rational_point(u, v);
+                assert!((p.pos[0] * p.pos[0] + p.pos[1] * p.pos[1] - 1.0).abs() < 1e-5, "{:?} is off the cylinder", p);
+                assert!((p.pos[2] - 2.0 * u).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn polynomial_approximation_of_cylinder() {
+        let w = f32::consts::FRAC_1_SQRT_2;
+        let curves: Vec<_> = [0.0, 2.0].iter().map(|z| {
+            let points = vec![HomogeneousPoint::new(Point::new(1.0, 0.0, *z), 1.0),
+                              HomogeneousPoint::new(Point::new(1.0, 1.0, *z), w),
+                              HomogeneousPoint::new(Point::new(0.0, 1.0, *z), 1.0)];
+            BSpline::new(2, points, vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0])
+        }).collect();
+        let rational = BSplineSurf::interpolate_rational_curves(&curves[..], 1);
+        let surf = rational.polynomial_approximation(8);
+        assert_eq!(surf.knot_domain_u(), rational.knot_domain_u());
+        assert_eq!(surf.knot_domain_v(), rational.knot_domain_v());
+        for i in 0..9 {
+            for j in 0..9 {
+                let (u, v) = (i as f32 / 8.0, j as f32 / 8.0);
+                let (p, r) = (surf.point(u, v), rational.rational_point(u, v));
+                assert!((p - r).length() < 1e-3, "{:?} != {:?} at ({}, {})", p, r, u, v);
+            }
+        }
+    }
+
+    #[test]
+    fn set_knots_validates() {
+        let mut surf = clamped_surf();
+        assert!(surf.set_knots_u(vec![0.0, 0.0, 0.0, 1.0, 2.0, 2.0]).is_err());
+        assert!(surf.set_knots_v(vec![0.0, 0.0, 2.0, 1.0, 2.0]).is_err());
+        assert!(surf.set_knots_v(vec![0.0, 1.0, 1.0, 1.0, 2.0]).is_err());
+        assert_eq!(surf.knots_v, vec![0.0, 0.0, 1.0, 2.0, 2.0]);
+        assert_eq!(surf.set_knots_u(vec![0.0, 0.0, 0.0, 0.5, 2.0, 2.0, 2.0]), Ok(()));
+        assert_eq!(surf.knots_u[3], 0.5);
+    }
+}
@@ -10,8 +10,13 @@ impl Camera2d {
     pub fn new() -> Camera2d {
         Camera2d { position: vec3(0.0, 0.0, 2.0), zoom: 1.0 }
     }
+    /// Move the camera back to its initial position and zoom
+    pub fn reset(&mut self) {
+        *self = Camera2d::new();
+    }
+    /// Translate the camera by a world space offset
     pub fn translate(&mut self, x: f32, y: f32) {
-        self.position += vec3(x, y, 0.0) / self.zoom;
+        self.position += vec3(x, y, 0.0);
     }
     pub fn zoom(&mut self, z: f32) {
         self.zoom += z;
@@ -20,9 +25,56 @@ impl Camera2d {
             self.zoom = 0.1;
         }
     }
+    /// Zoom by `z` keeping the world space point `center` at the same place on screen,
+    /// e.g. to zoom towards the cursor.
+    pub fn zoom_about(&mut self, z: f32, center: (f32, f32)) {
+        let prev_zoom = self.zoom;
+        self.zoom(z);
+        let s = prev_zoom / self.zoom;
+        self.position.x = s * (center.0 + self.position.x) - center.0;
+        self.position.y = s * (center.1 + self.position.y) - center.1;
+    }
+    /// Center the camera on the box `[min, max]` and zoom to fit it in the view, where
+    /// `view_size` is the size of the visible region in world space at zoom 1
+    pub fn fit(&mut self, min: (f32, f32), max: (f32, f32), view_size: (f32, f32)) {
+        self.position = vec3(-0.5 * (min.0 + max.0), -0.5 * (min.1 + max.1), self.position.z);
+        // Leave a bit of a margin around the box
+        let size = (f32::max(max.0 - min.0, 0.01) * 1.1, f32::max(max.1 - min.1, 0.01) * 1.1);
+        self.zoom = f32::max(f32::min(view_size.0 / size.0, view_size.1 / size.1), 0.1);
+    }
     pub fn get_mat4(&self) -> Matrix4<f32> {
         Matrix4::from_nonuniform_scale(self.zoom, self.zoom, 1.0)
             * Matrix4::from_translation(self.position)
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cgmath::{Point3, Transform};
+
+    #[test]
+    fn zoom_about_keeps_point_fixed() {
+        let mut camera = Camera2d::new();
+        camera.translate(0.3, -1.2);
+        let center = Point3::new(1.5, 0.5, 0.0);
+        for z in &[0.5, 2.0, -0.8, -5.0] {
+            let before = camera.get_mat4().transform_point(center);
+            camera.zoom_about(*z, (center.x, center.y));
+            let after = camera.get_mat4().transform_point(center);
+            assert!((before.x - after.x).abs() < 1e-5 && (before.y - after.y).abs() < 1e-5,
+                    "moved from {:?} to {:?} zooming by {}", before, after, z);
+        }
+        // The zoom is still clamped
+        assert_eq!(camera.zoom, 0.1);
+    }
+
+    #[test]
+    fn reset_restores_initial_view() {
+        let mut camera = Camera2d::new();
+        camera.translate(0.3, -1.2);
+        camera.zoom_about(2.0, (1.0, 1.0));
+        camera.reset();
+        assert_eq!(camera.get_mat4(), Camera2d::new().get_mat4());
+    }
+}
@@ -1,142 +1,554 @@
 /// Manages displaying and toggling interaction modes with
-/// a specific BSpline curve in the scene.
+/// a specific BSpline curve in the scene. Both 2D and 3D curves are
+/// displayed with this, 2D curves just have all their points at z = 0.
 
-use std::f32;
+use std::{f32, cmp};
 
-use glium::{Surface, VertexBuffer, Program, DrawParameters};
+use glium::{Surface, VertexBuffer, Program, DrawParameters, Frame};
 use glium::backend::Facade;
 use glium::index::{NoIndices, PrimitiveType};
 use imgui::Ui;
 
-use bspline::BSpline;
-use point::Point;
+use bspline::{self, BSpline, SampleCache};
+use point::{self, Point, ColorPoint};
+use scene_object::{SceneObject, EditableCurve, Geometry, hsv_to_rgb};
+use imgui_support;
+
+/// The highest degree offered in the UI for curves with too few points to support it
+const MAX_PADDED_DEGREE: usize = 7;
+
+/// The order the curve and its control polygon are drawn in, the one drawn last is on top.
+/// Control points and other markers are always drawn on top of both.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DrawOrder {
+    CurveOnTop,
+    PolygonOnTop,
+}
 
 pub struct DisplayCurve<'a, F: 'a + Facade> {
     display: &'a F,
     pub curve: BSpline<Point>,
+    is_3d: bool,
+    name: String,
+    // The file the curve was loaded from and its index among the file's objects
+    source: Option<(String, usize)>,
+    // If the curve is mirrored from the coordinates it was loaded in
+    flip_handedness: bool,
     curve_points_vbo:  VertexBuffer<Point>,
     control_points_vbo: VertexBuffer<Point>,
     break_points_vbo: VertexBuffer<Point>,
+    gradient_points_vbo: VertexBuffer<ColorPoint>,
+    span_points_vbo: VertexBuffer<ColorPoint>,
+    intersections_vbo: VertexBuffer<Point>,
+    inflections_vbo: VertexBuffer<Point>,
+    offset_vbo: VertexBuffer<Point>,
+    // Weights for resampling the curve, kept until its degree or knots change
+    sample_cache: Option<SampleCache>,
+    greville_vbo: VertexBuffer<Point>,
+    marker_vbo: VertexBuffer<Point>,
+    construction_lines_vbo: VertexBuffer<Point>,
+    construction_points_vbo: VertexBuffer<Point>,
+    tangents_vbo: VertexBuffer<Point>,
+    dashes_vbo: VertexBuffer<Point>,
+    osculating_circle_vbo: VertexBuffer<Point>,
+    num_intersections: usize,
+    num_inflections: usize,
     draw_curve: bool,
+    draw_gradient: bool,
+    draw_spans: bool,
     draw_control_poly: bool,
     draw_control_points: bool,
     draw_break_points: bool,
+    draw_intersections: bool,
+    draw_inflections: bool,
+    draw_offset: bool,
+    draw_greville: bool,
+    // Signed distance of the offset curve, positive offsets are to the left of the curve
+    offset_distance: f32,
+    // When set clicks always add points to the end of the curve instead of inserting them
+    // on the nearest segment of the control polygon
+    append_mode: bool,
+    // When set clicking on the curve inserts a knot at the clicked point instead of adding
+    // a control point
+    knot_insert_mode: bool,
+    // Set after a click inserts a knot until the mouse is released, so holding the click
+    // only inserts one knot
+    inserted_knot: bool,
+    draw_construction: bool,
+    draw_tangents: bool,
+    dashed: bool,
+    draw_osculating_circle: bool,
+    // The control polygon, control point and break point toggles saved when presentation
+    // mode hid them, restored when it's turned off
+    presentation_mode: Option<[bool; 3]>,
+    draw_order: DrawOrder,
+    construction_t: f32,
+    tangent_scale: f32,
+    // Max distance a control point can be from the simplified control polygon to be removed
+    simplify_tolerance: f32,
+    osculating_t: f32,
+    // Parameter the user typed in to evaluate the curve at, and if the tangent there is shown too
+    eval_t: f32,
+    eval_tangent: bool,
+    // Radius of the osculating circle at `osculating_t`, None if the curve is straight there
+    osculating_radius: Option<f32>,
+    // Arc length of each dash and the gap between dashes when drawing the curve dashed
+    dash_length: f32,
+    gap_length: f32,
+    animate: bool,
+    animation_speed: f32,
+    animation_t: f32,
     moving_point: Option<usize>,
+    selected_point: Option<usize>,
     curve_color: [f32; 3],
+    // Opacity of the whole object, below 1 it's blended over the objects behind it
+    alpha: f32,
+    // If the 3D curve's shadow is drawn on the ground plane when the scene shows shadows
+    draw_shadow: bool,
     control_color: [f32; 3],
+    curve_length: f32,
+    polygon_distance: f32,
+    metrics_dirty: bool,
+    // Set if the curve's degree, control points and knots are inconsistent, in which
+    // case it isn't drawn and the problem is shown in the UI instead
+    validation_error: Option<String>,
     break_point_color: [f32; 3],
+    gradient_start_color: [f32; 3],
+    gradient_end_color: [f32; 3],
+    intersection_color: [f32; 3],
+    inflection_color: [f32; 3],
+    offset_color: [f32; 3],
+    greville_color: [f32; 3],
+    marker_color: [f32; 3],
+    construction_color: [f32; 3],
+    tangent_color: [f32; 3],
+    osculating_color: [f32; 3],
 }
 
 impl<'a, F: 'a + Facade> DisplayCurve<'a, F> {
-    pub fn new(curve: BSpline<Point>, display: &'a F) -> DisplayCurve<'a, F> {
-        let control_points_vbo;
-        let curve_points_vbo;
-        let break_points_vbo;
-        if !curve.control_points.is_empty() {
-            let step_size = 0.01;
-            let t_range = curve.knot_domain();
-            let steps = ((t_range.1 - t_range.0) / step_size) as usize;
-            control_points_vbo = VertexBuffer::new(display, &curve.control_points[..]).unwrap();
-            let mut points = Vec::with_capacity(steps);
-            // Just draw the first one for now
-            for s in 0..steps + 1 {
-                let t = step_size * s as f32 + t_range.0;
-                points.push(curve.point(t));
-            }
-            curve_points_vbo = VertexBuffer::new(display, &points[..]).unwrap();
-            let break_points: Vec<_> = curve.knot_domain_iter().map(|b| curve.point(*b)).collect();
-            break_points_vbo = VertexBuffer::new(display, &break_points[..]).unwrap();
+    /// Create a new display curve, 2D curves can be edited by clicking in the
+    /// 2D view while 3D curves can only be viewed.
+    pub fn new(curve: BSpline<Point>, is_3d: bool, display: &'a F) -> DisplayCurve<'a, F> {
+        // The buffers are filled in by rebuild_geometry once the curve is setup
+        let control_points_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let curve_points_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let break_points_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let gradient_points_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let span_points_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let intersections_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let inflections_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let offset_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let greville_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let marker_vbo = VertexBuffer::empty_dynamic(display, 1).unwrap();
+        let construction_lines_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let construction_points_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let tangents_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let osculating_circle_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let dashes_vbo = VertexBuffer::empty(display, 10).unwrap();
+        let mut display_curve = DisplayCurve { display: display,
+                                               curve: curve,
+                                               is_3d: is_3d,
+                                               name: "Curve".to_owned(),
+                                               source: None,
+                                               flip_handedness: false,
+                                               curve_points_vbo: curve_points_vbo,
+                                               control_points_vbo: control_points_vbo,
+                                               break_points_vbo: break_points_vbo,
+                                               gradient_points_vbo: gradient_points_vbo,
+                                               span_points_vbo: span_points_vbo,
+                                               intersections_vbo: intersections_vbo,
+                                               num_intersections: 0,
+                                               inflections_vbo: inflections_vbo,
+                                               offset_vbo: offset_vbo,
+                                               sample_cache: None,
+                                               greville_vbo: greville_vbo,
+                                               num_inflections: 0,
+                                               marker_vbo: marker_vbo,
+                                               construction_lines_vbo: construction_lines_vbo,
+                                               construction_points_vbo: construction_points_vbo,
+                                               tangents_vbo: tangents_vbo,
+                                               osculating_circle_vbo: osculating_circle_vbo,
+                                               dashes_vbo: dashes_vbo,
+                                               draw_curve: true,
+                                               draw_gradient: false,
+                                               draw_spans: false,
+                                               draw_control_poly: true,
+                                               draw_control_points: true,
+                                               draw_break_points: true,
+                                               draw_intersections: false,
+                                               draw_inflections: false,
+                                               draw_offset: false,
+                                               draw_greville: false,
+                                               offset_distance: 0.2,
+                                               append_mode: false,
+                                               knot_insert_mode: false,
+                                               inserted_knot: false,
+                                               draw_construction: false,
+                                               draw_tangents: false,
+                                               draw_osculating_circle: false,
+                                               dashed: false,
+                                               presentation_mode: None,
+                                               draw_order: DrawOrder::CurveOnTop,
+                                               construction_t: 0.0,
+                                               tangent_scale: 0.25,
+                                               simplify_tolerance: 0.01,
+                                               eval_t: 0.0,
+                                               eval_tangent: false,
+                                               osculating_t: 0.0,
+                                               osculating_radius: None,
+                                               dash_length: 0.1,
+                                               gap_length: 0.05,
+                                               animate: false,
+                                               animation_speed: 0.5,
+                                               animation_t: 0.0,
+                                               moving_point: None,
+                                               selected_point: None,
+                                               curve_color: [0.8, 0.8, 0.1],
+                                               alpha: 1.0,
+                                               draw_shadow: true,
+                                               control_color: [0.8, 0.8, 0.8],
+                                               curve_length: 0.0,
+                                               polygon_distance: 0.0,
+                                               metrics_dirty: true,
+                                               validation_error: None,
+                                               break_point_color: [0.1, 0.8, 0.8],
+                                               gradient_start_color: [0.1, 0.1, 0.8],
+                                               gradient_end_color: [0.8, 0.1, 0.1],
+                                               intersection_color: [0.9, 0.2, 0.2],
+                                               inflection_color: [0.2, 0.9, 0.9],
+                                               offset_color: [0.6, 0.6, 0.9],
+                                               greville_color: [0.1, 0.8, 0.8],
+                                               marker_color: [0.9, 0.9, 0.9],
+                                               construction_color: [0.3, 0.9, 0.4],
+                                               tangent_color: [0.9, 0.4, 0.9],
+                                               osculating_color: [0.4, 0.7, 1.0],
+        };
+        display_curve.rebuild_geometry();
+        display_curve
+    }
+    /// Hide the control polygon, control points and break points for a clean view of the
+    /// curve, turning presentation mode off restores them to how they were.
+    pub fn set_presentation_mode(&mut self, on: bool) {
+        if on && self.presentation_mode.is_none() {
+            self.presentation_mode = Some([self.draw_control_poly, self.draw_control_points,
+                                           self.draw_break_points]);
+            self.draw_control_poly = false;
+            self.draw_control_points = false;
+            self.draw_break_points = false;
+        } else if !on {
+            if let Some(saved) = self.presentation_mode.take() {
+                self.draw_control_poly = saved[0];
+                self.draw_control_points = saved[1];
+                self.draw_break_points = saved[2];
+            }
+        }
+    }
+    /// Rebuild all the curve's vertex buffers, this must be called whenever the curve
+    /// is changed so everything drawn stays in sync with it.
+    pub fn rebuild_geometry(&mut self) {
+        self.metrics_dirty = true;
+        self.validation_error = self.curve.validate().err();
+        if self.curve.control_points.is_empty() || self.validation_error.is_some() {
+            return;
+        }
+        self.control_points_vbo = VertexBuffer::new(self.display, &self.curve.control_points[..]).unwrap();
+        let points = self.sample_points();
+        self.curve_points_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
+        let break_points: Vec<_> = self.curve.knot_domain_iter().map(|b| self.curve.point(*b)).collect();
+        self.break_points_vbo = VertexBuffer::new(self.display, &break_points[..]).unwrap();
+        self.build_gradient_vbo();
+        self.build_span_vbo();
+        self.build_intersections_vbo();
+        self.build_inflections_vbo();
+        self.build_offset_vbo();
+        // Degree 0 curves have no Greville points
+        if self.curve.degree() > 0 {
+            self.greville_vbo = VertexBuffer::new(self.display, &self.curve.greville_points()[..]).unwrap();
+        }
+        self.build_construction_vbo();
+        self.build_tangents_vbo();
+        self.build_dashes_vbo();
+        self.build_osculating_circle_vbo();
+    }
+    /// Sample the curve evenly across its domain, the sampling weights are cached and only
+    /// recomputed when the curve's degree or knots change.
+    fn sample_points(&mut self) -> Vec<Point> {
+        if !self.sample_cache.as_ref().map_or(false, |c| c.matches(&self.curve)) {
+            let ts = bspline::sample_domain(self.curve.knot_domain(), 0.01);
+            self.sample_cache = Some(SampleCache::new(&self.curve, &ts[..]));
+        }
+        self.sample_cache.as_ref().unwrap().evaluate(&self.curve.control_points[..])
+    }
+    /// Split the curve into dashes and build the vertex buffer for drawing them as
+    /// line segments, this is only done if we're drawing the curve dashed.
+    fn build_dashes_vbo(&mut self) {
+        if !self.dashed || self.curve.control_points.is_empty() {
+            return;
+        }
+        let mut lines = Vec::new();
+        for d in self.curve.dashes(self.dash_length, self.gap_length, 0.01) {
+            for x in d.windows(2) {
+                lines.push(x[0]);
+                lines.push(x[1]);
+            }
+        }
+        if lines.is_empty() {
+            self.dashes_vbo = VertexBuffer::empty(self.display, 10).unwrap();
         } else {
-            control_points_vbo = VertexBuffer::empty(display, 10).unwrap();
-            curve_points_vbo = VertexBuffer::empty(display, 10).unwrap();
-            break_points_vbo = VertexBuffer::empty(display, 10).unwrap();
-        }
-        DisplayCurve { display: display,
-                       curve: curve,
-                       curve_points_vbo: curve_points_vbo,
-                       control_points_vbo: control_points_vbo,
-                       break_points_vbo: break_points_vbo,
-                       draw_curve: true,
-                       draw_control_poly: true,
-                       draw_control_points: true,
-                       draw_break_points: true,
-                       moving_point: None,
-                       curve_color: [0.8, 0.8, 0.1],
-                       control_color: [0.8, 0.8, 0.8],
-                       break_point_color: [0.1, 0.8, 0.8],
-        }
-    }
-    pub fn handle_click(&mut self, pos: Point, shift_down: bool, zoom_factor: f32) {
-        // If we're close to control point of the selected curve we're dragging it,
-        // otherwise we're adding a new point
-        let nearest = self.curve.control_points().enumerate().map(|(i, x)| (i, (*x - pos).length()))
-            .fold((0, f32::MAX), |acc, (i, d)| if d < acc.1 { (i, d) } else { acc });
-        let point_size = 12.0 / (100.0 * zoom_factor);
-        if shift_down {
-            self.moving_point = None;
-            if nearest.1 < point_size {
-                self.curve.remove_point(nearest.0);
+            self.dashes_vbo = VertexBuffer::new(self.display, &lines[..]).unwrap();
+        }
+    }
+    /// Sample the curve and build the vertex buffer for drawing it colored by a gradient going
+    /// from the start color to the end color along `t`, this is only done if we're drawing the gradient.
+    fn build_gradient_vbo(&mut self) {
+        if !self.draw_gradient || self.curve.control_points.is_empty() || self.validation_error.is_some() {
+            return;
+        }
+        let samples = self.sample_points();
+        let steps = samples.len() - 1;
+        let mut points = Vec::with_capacity(samples.len());
+        for (s, p) in samples.iter().enumerate() {
+            let x = if steps == 0 { 0.0 } else { s as f32 / steps as f32 };
+            let color = [self.gradient_start_color[0] * (1.0 - x) + self.gradient_end_color[0] * x,
+                         self.gradient_start_color[1] * (1.0 - x) + self.gradient_end_color[1] * x,
+                         self.gradient_start_color[2] * (1.0 - x) + self.gradient_end_color[2] * x];
+            points.push(ColorPoint::new(*p, color));
+        }
+        self.gradient_points_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
+    }
+    /// Sample the curve span by span and build the vertex buffer for drawing it with each
+    /// knot span in a different color, this is only done if we're showing the spans.
+    fn build_span_vbo(&mut self) {
+        if !self.draw_spans || self.curve.control_points.is_empty() {
+            return;
+        }
+        let step_size = 0.01;
+        let mut spans: Vec<f32> = self.curve.knot_domain_iter().cloned().collect();
+        // Repeated knots give empty spans which we skip
+        spans.dedup();
+        let mut points = Vec::new();
+        for (i, s) in spans.windows(2).enumerate() {
+            // Step the hue by the golden angle so neighboring spans are easy to tell apart
+            let color = hsv_to_rgb((i as f32 * 137.508) % 360.0, 0.75, 0.9);
+            let steps = f32::max(((s[1] - s[0]) / step_size).ceil(), 1.0) as usize;
+            let mut prev = self.curve.point(s[0]);
+            for j in 1..steps + 1 {
+                let p = self.curve.point(s[0] + (s[1] - s[0]) * j as f32 / steps as f32);
+                points.push(ColorPoint::new(prev, color));
+                points.push(ColorPoint::new(p, color));
+                prev = p;
             }
-        } else if let Some(p) = self.moving_point {
-            self.curve.control_points[p] = pos;
-        } else if nearest.1 < point_size {
-            self.moving_point = Some(nearest.0);
-            self.curve.control_points[nearest.0] = pos;
+        }
+        if !points.is_empty() {
+            self.span_points_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
+        }
+    }
+    /// Find the curve's self intersections and build the vertex buffer marking them,
+    /// this is only done if we're showing the intersections.
+    fn build_intersections_vbo(&mut self) {
+        if !self.draw_intersections || self.curve.control_points.is_empty() {
+            return;
+        }
+        let points: Vec<_> = self.curve.self_intersections().iter().map(|x| self.curve.point(x.0)).collect();
+        self.num_intersections = points.len();
+        if !points.is_empty() {
+            self.intersections_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
+        }
+    }
+    /// Find the curve's inflection points and build the vertex buffer marking them,
+    /// this is only done if we're showing the inflections.
+    fn build_inflections_vbo(&mut self) {
+        if !self.draw_inflections || self.curve.control_points.is_empty() {
+            return;
+        }
+        let points: Vec<_> = self.curve.inflection_points().iter().map(|t| self.curve.point(*t)).collect();
+        self.num_inflections = points.len();
+        if !points.is_empty() {
+            self.inflections_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
+        }
+    }
+    /// Build the vertex buffer for the offset curve, this is only done if we're showing it
+    fn build_offset_vbo(&mut self) {
+        if !self.draw_offset || self.curve.control_points.is_empty() {
+            return;
+        }
+        let points = self.curve.offset(self.offset_distance, 200);
+        self.offset_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
+    }
+    /// Draw the curve, either flat shaded or with the gradient along t
+    fn render_curve(&self, target: &mut Frame, program: &Program, color_program: &Program,
+                    draw_params: &DrawParameters, proj_view: &[[f32; 4]; 4], curve_color: [f32; 3],
+                    attenuation: f32) {
+        if self.draw_curve && self.draw_spans {
+            let uniforms = uniform! {
+                proj_view: *proj_view,
+                attenuation: attenuation,
+            };
+            target.draw(&self.span_points_vbo, &NoIndices(PrimitiveType::LinesList),
+                        &color_program, &uniforms, &draw_params).unwrap();
+        } else if self.draw_curve && self.draw_gradient {
+            let uniforms = uniform! {
+                proj_view: *proj_view,
+                attenuation: attenuation,
+            };
+            target.draw(&self.gradient_points_vbo, &NoIndices(PrimitiveType::LineStrip),
+                        &color_program, &uniforms, &draw_params).unwrap();
+        } else if self.draw_curve && self.dashed {
+            let uniforms = uniform! {
+                proj_view: *proj_view,
+                pcolor: curve_color,
+            };
+            target.draw(&self.dashes_vbo, &NoIndices(PrimitiveType::LinesList),
+                        &program, &uniforms, &draw_params).unwrap();
+        } else if self.draw_curve {
+            let uniforms = uniform! {
+                proj_view: *proj_view,
+                pcolor: curve_color,
+            };
+            target.draw(&self.curve_points_vbo, &NoIndices(PrimitiveType::LineStrip),
+                        &program, &uniforms, &draw_params).unwrap();
+        }
+    }
+    /// Draw the control polygon
+    fn render_control_polygon(&self, target: &mut Frame, program: &Program, draw_params: &DrawParameters,
+                              proj_view: &[[f32; 4]; 4], control_color: [f32; 3]) {
+        if self.draw_control_poly {
+            let uniforms = uniform! {
+                proj_view: *proj_view,
+                pcolor: control_color,
+            };
+            target.draw(&self.control_points_vbo, &NoIndices(PrimitiveType::LineStrip),
+                        &program, &uniforms, &draw_params).unwrap();
+        }
+    }
+    /// Draw the buttons for inserting a point before or after the selected control point.
+    /// New points are placed halfway to the neighboring point, or extended out past the
+    /// end of the control polygon if there's no neighbor.
+    fn draw_point_insertion_ui(&mut self, ui: &Ui) {
+        let i = match self.selected_point {
+            Some(i) if i < self.curve.control_points.len() => i,
+            _ => return,
+        };
+        ui.text(im_str!("Selected Point: {}", i));
+        let points = &self.curve.control_points;
+        let step = if points.len() == 1 {
+            Point::new(0.5, 0.0, 0.0)
+        } else if i + 1 < points.len() {
+            (points[i + 1] - points[i]) * 0.5
         } else {
-            self.moving_point = Some(self.curve.insert_point(pos));
+            (points[i] - points[i - 1]) * 0.5
+        };
+        let insert = if ui.small_button(im_str!("Insert Before Selected")) {
+            let p = if i > 0 { (points[i - 1] + points[i]) * 0.5 } else { points[i] - step };
+            Some((p, i))
+        } else if ui.small_button(im_str!("Insert After Selected")) {
+            let p = if i + 1 < points.len() { (points[i] + points[i + 1]) * 0.5 } else { points[i] + step };
+            Some((p, i + 1))
+        } else {
+            None
+        };
+        if let Some((p, idx)) = insert {
+            self.curve.insert_point_at(p, idx);
+            self.selected_point = Some(idx);
+            self.rebuild_geometry();
         }
-        if !self.curve.control_points.is_empty() {
-            let step_size = 0.01;
-            let t_range = self.curve.knot_domain();
-            let steps = ((t_range.1 - t_range.0) / step_size) as usize;
-            self.control_points_vbo = VertexBuffer::new(self.display, &self.curve.control_points[..]).unwrap();
-            let mut points = Vec::with_capacity(steps);
-            // Just draw the first one for now
-            for s in 0..steps + 1 {
-                let t = step_size * s as f32 + t_range.0;
-                points.push(self.curve.point(t));
+    }
+    /// Build the vertex buffers showing the levels of de Boor's algorithm for the point
+    /// at `construction_t`, this is only done if we're showing the construction.
+    fn build_construction_vbo(&mut self) {
+        if !self.draw_construction || self.curve.control_points.is_empty() {
+            return;
+        }
+        let levels = self.curve.de_boor_steps(self.construction_t);
+        let mut lines = Vec::new();
+        for l in levels.iter() {
+            for x in l.windows(2) {
+                lines.push(x[0]);
+                lines.push(x[1]);
             }
-            self.curve_points_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
-            let break_points: Vec<_> = self.curve.knot_domain_iter().map(|b| self.curve.point(*b)).collect();
-            self.break_points_vbo = VertexBuffer::new(self.display, &break_points[..]).unwrap();
         }
+        // The first level is the control points, which are already drawn
+        let points: Vec<_> = levels.iter().skip(1).flat_map(|l| l.iter().cloned()).collect();
+        self.construction_lines_vbo = VertexBuffer::new(self.display, &lines[..]).unwrap();
+        self.construction_points_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
     }
-    /// Release any held point that was being dragged
-    pub fn release_point(&mut self) {
-        self.moving_point = None;
+    /// Build the vertex buffer for the tangent lines at the start and end of the curve,
+    /// the derivatives are scaled by `tangent_scale`. This is only done if we're showing them.
+    fn build_tangents_vbo(&mut self) {
+        if !self.draw_tangents || self.curve.degree() == 0 || self.curve.control_points.len() < 2 {
+            return;
+        }
+        let derivative = self.curve.derivative();
+        let t_range = self.curve.knot_domain();
+        let mut lines = Vec::with_capacity(4);
+        for t in &[t_range.0, t_range.1] {
+            let p = self.curve.point(*t);
+            lines.push(p);
+            lines.push(p + derivative.point(*t) * self.tangent_scale);
+        }
+        self.tangents_vbo = VertexBuffer::new(self.display, &lines[..]).unwrap();
+    }
+    /// Build the vertex buffer for the osculating circle at `osculating_t`, this is only done
+    /// if we're showing it. Nothing is drawn where the curve is straight.
+    fn build_osculating_circle_vbo(&mut self) {
+        if !self.draw_osculating_circle || self.curve.control_points.is_empty() {
+            return;
+        }
+        let t_range = self.curve.knot_domain();
+        self.osculating_t = point::clamp(self.osculating_t, t_range.0, t_range.1);
+        let circle = self.curve.osculating_circle(self.osculating_t);
+        self.osculating_radius = circle.map(|c| c.1);
+        let points: Vec<_> = match circle {
+            Some((center, radius, normal)) => {
+                let tangent = self.curve.derivative().point(self.osculating_t).normalized();
+                let segments = 64;
+                (0..segments).map(|i| {
+                    let theta = 2.0 * f32::consts::PI * i as f32 / segments as f32;
+                    center + (normal * -f32::cos(theta) + tangent * f32::sin(theta)) * radius
+                }).collect()
+            },
+            None => Vec::new(),
+        };
+        self.osculating_circle_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
     }
-    pub fn render<S: Surface>(&self, target: &mut S, program: &Program, draw_params: &DrawParameters,
-                  proj_view: &[[f32; 4]; 4], selected: bool, attenuation: f32) {
-        let (curve_color, control_color, break_color) =
+}
+
+impl<'a, F: 'a + Facade> SceneObject for DisplayCurve<'a, F> {
+    fn render(&self, target: &mut Frame, program: &Program, color_program: &Program,
+              draw_params: &DrawParameters, proj_view: &[[f32; 4]; 4], selected: bool, attenuation: f32) {
+        if self.validation_error.is_some() {
+            return;
+        }
+        let (curve_color, control_color, break_color, intersection_color) =
             if selected {
-                (self.curve_color, self.control_color, self.break_point_color)
+                (self.curve_color, self.control_color, self.break_point_color, self.intersection_color)
             } else {
                 ([attenuation * self.curve_color[0], attenuation * self.curve_color[1],
                   attenuation * self.curve_color[2]],
                  [attenuation * self.control_color[0], attenuation * self.control_color[1],
                   attenuation * self.control_color[2]],
                  [attenuation * self.break_point_color[0], attenuation * self.break_point_color[1],
-                  attenuation * self.break_point_color[2]])
+                  attenuation * self.break_point_color[2]],
+                 [attenuation * self.intersection_color[0], attenuation * self.intersection_color[1],
+                  attenuation * self.intersection_color[2]])
             };
         if !self.curve.control_points.is_empty() {
-            let uniforms = uniform! {
-                proj_view: *proj_view,
-                pcolor: curve_color,
-            };
-            // Draw the curve
-            if self.draw_curve {
-                target.draw(&self.curve_points_vbo, &NoIndices(PrimitiveType::LineStrip),
-                            &program, &uniforms, &draw_params).unwrap();
+            match self.draw_order {
+                DrawOrder::CurveOnTop => {
+                    self.render_control_polygon(target, program, draw_params, proj_view, control_color);
+                    self.render_curve(target, program, color_program, draw_params, proj_view, curve_color,
+                                      if selected { 1.0 } else { attenuation });
+                },
+                DrawOrder::PolygonOnTop => {
+                    self.render_curve(target, program, color_program, draw_params, proj_view, curve_color,
+                                      if selected { 1.0 } else { attenuation });
+                    self.render_control_polygon(target, program, draw_params, proj_view, control_color);
+                },
             }
             let uniforms = uniform! {
                 proj_view: *proj_view,
                 pcolor: control_color,
             };
-            // Draw the control polygon
-            if self.draw_control_poly {
-                target.draw(&self.control_points_vbo, &NoIndices(PrimitiveType::LineStrip),
-                            &program, &uniforms, &draw_params).unwrap();
-            }
             if self.draw_control_points {
                 // Draw the control points
                 target.draw(&self.control_points_vbo, &NoIndices(PrimitiveType::Points),
@@ -151,50 +563,512 @@ impl<'a, F: 'a + Facade> DisplayCurve<'a, F> {
                 target.draw(&self.break_points_vbo, &NoIndices(PrimitiveType::Points),
                             &program, &uniforms, &draw_params).unwrap();
             }
+            if self.animate {
+                let uniforms = uniform! {
+                    proj_view: *proj_view,
+                    pcolor: self.marker_color,
+                };
+                target.draw(&self.marker_vbo, &NoIndices(PrimitiveType::Points),
+                            &program, &uniforms, &draw_params).unwrap();
+            }
+            if self.draw_construction {
+                let uniforms = uniform! {
+                    proj_view: *proj_view,
+                    pcolor: self.construction_color,
+                };
+                target.draw(&self.construction_lines_vbo, &NoIndices(PrimitiveType::LinesList),
+                            &program, &uniforms, &draw_params).unwrap();
+                target.draw(&self.construction_points_vbo, &NoIndices(PrimitiveType::Points),
+                            &program, &uniforms, &draw_params).unwrap();
+            }
+            if self.draw_tangents && self.curve.degree() > 0 && self.curve.control_points.len() > 1 {
+                let uniforms = uniform! {
+                    proj_view: *proj_view,
+                    pcolor: self.tangent_color,
+                };
+                target.draw(&self.tangents_vbo, &NoIndices(PrimitiveType::LinesList),
+                            &program, &uniforms, &draw_params).unwrap();
+            }
+            if self.draw_osculating_circle && self.osculating_radius.is_some() {
+                let uniforms = uniform! {
+                    proj_view: *proj_view,
+                    pcolor: self.osculating_color,
+                };
+                target.draw(&self.osculating_circle_vbo, &NoIndices(PrimitiveType::LineLoop),
+                            &program, &uniforms, &draw_params).unwrap();
+            }
+            if self.draw_intersections && self.num_intersections > 0 {
+                let uniforms = uniform! {
+                    proj_view: *proj_view,
+                    pcolor: intersection_color,
+                };
+                target.draw(&self.intersections_vbo, &NoIndices(PrimitiveType::Points),
+                            &program, &uniforms, &draw_params).unwrap();
+            }
+            if self.draw_inflections && self.num_inflections > 0 {
+                let uniforms = uniform! {
+                    proj_view: *proj_view,
+                    pcolor: self.inflection_color,
+                };
+                target.draw(&self.inflections_vbo, &NoIndices(PrimitiveType::Points),
+                            &program, &uniforms, &draw_params).unwrap();
+            }
+            if self.draw_greville && self.curve.degree() > 0 {
+                let uniforms = uniform! {
+                    proj_view: *proj_view,
+                    pcolor: self.greville_color,
+                };
+                target.draw(&self.greville_vbo, &NoIndices(PrimitiveType::Points),
+                            &program, &uniforms, &draw_params).unwrap();
+            }
+            if self.draw_offset {
+                let uniforms = uniform! {
+                    proj_view: *proj_view,
+                    pcolor: self.offset_color,
+                };
+                target.draw(&self.offset_vbo, &NoIndices(PrimitiveType::LineStrip),
+                            &program, &uniforms, &draw_params).unwrap();
+            }
         }
     }
-    pub fn draw_ui(&mut self, ui: &Ui) {
-        ui.text(im_str!("2D Curve"));
+    fn draw_ui(&mut self, ui: &Ui) {
+        if self.is_3d {
+            ui.text(im_str!("3D Curve"));
+        } else {
+            ui.text(im_str!("2D Curve"));
+        }
+        imgui_support::input_text(im_str!("Name"), &mut self.name, 64);
         ui.text(im_str!("Number of Control Points: {}", self.curve.control_points.len()));
+        if let Some(ref e) = self.validation_error {
+            ui.text_colored((1.0, 0.4, 0.4, 1.0), im_str!("Invalid curve: {}", e));
+        }
+        if self.metrics_dirty && self.validation_error.is_none() {
+            self.curve_length = self.curve.arc_length();
+            self.polygon_distance = self.curve.control_polygon_distance();
+            self.metrics_dirty = false;
+        }
+        ui.text(im_str!("Degree: {}, Number of Knots: {}", self.curve.degree(), self.curve.knots().len()));
+        ui.text(im_str!("Arc Length: {:.4}", self.curve_length));
+        ui.text(im_str!("Max Control Point Distance: {:.4}", self.polygon_distance));
         ui.checkbox(im_str!("Draw Curve"), &mut self.draw_curve);
+        if ui.checkbox(im_str!("Draw Gradient"), &mut self.draw_gradient) {
+            self.build_gradient_vbo();
+        }
+        if self.is_3d {
+            ui.checkbox(im_str!("Draw Ground Shadow"), &mut self.draw_shadow);
+        }
+        if ui.checkbox(im_str!("Dashed"), &mut self.dashed) {
+            self.build_dashes_vbo();
+        }
+        if self.dashed {
+            let dash_changed = ui.slider_float(im_str!("Dash Length"), &mut self.dash_length, 0.01, 1.0).build();
+            let gap_changed = ui.slider_float(im_str!("Gap Length"), &mut self.gap_length, 0.01, 1.0).build();
+            if dash_changed || gap_changed {
+                self.build_dashes_vbo();
+            }
+        }
+        if ui.checkbox(im_str!("Color Knot Spans"), &mut self.draw_spans) {
+            self.build_span_vbo();
+        }
+        let mut presentation = self.presentation_mode.is_some();
+        if ui.checkbox(im_str!("Presentation Mode"), &mut presentation) {
+            self.set_presentation_mode(presentation);
+        }
         ui.checkbox(im_str!("Draw Control Polygon"), &mut self.draw_control_poly);
         ui.checkbox(im_str!("Draw Control Points"), &mut self.draw_control_points);
         ui.checkbox(im_str!("Draw Break Points"), &mut self.draw_break_points);
-        let mut curve_changed = false;
-        // I use the open curve term b/c Elaine will be interacting with it and she
-        // calls clamped curves open.
-        let mut curve_clamped = self.curve.is_clamped();
-        if ui.checkbox(im_str!("Open Curve"), &mut curve_clamped) {
-            self.curve.set_clamped(curve_clamped);
-            curve_changed = true;
-        }
-        let mut curve_degree = self.curve.degree() as i32;
-        if ui.slider_int(im_str!("Curve Degree"), &mut curve_degree, 1,
-            self.curve.max_possible_degree() as i32).build()
+        ui.checkbox(im_str!("Draw Greville Points"), &mut self.draw_greville);
+        let mut polygon_on_top = self.draw_order == DrawOrder::PolygonOnTop;
+        if ui.checkbox(im_str!("Control Polygon on Top"), &mut polygon_on_top) {
+            self.draw_order = if polygon_on_top { DrawOrder::PolygonOnTop } else { DrawOrder::CurveOnTop };
+        }
+        // Only 2D curves are edited by clicking in the view
+        if !self.is_3d {
+            ui.checkbox(im_str!("Append Mode"), &mut self.append_mode);
+            ui.checkbox(im_str!("Knot Insertion Mode"), &mut self.knot_insert_mode);
+        }
+        // Self intersections are found in the xy plane so only make sense for 2D curves
+        if !self.is_3d {
+            if ui.checkbox(im_str!("Draw Self Intersections"), &mut self.draw_intersections) {
+                self.build_intersections_vbo();
+            }
+            if self.draw_intersections {
+                ui.text(im_str!("Self Intersections: {}", self.num_intersections));
+            }
+            if ui.checkbox(im_str!("Draw Inflection Points"), &mut self.draw_inflections) {
+                self.build_inflections_vbo();
+            }
+            if self.draw_inflections {
+                ui.text(im_str!("Inflection Points: {}", self.num_inflections));
+            }
+            if ui.checkbox(im_str!("Draw Offset Curve"), &mut self.draw_offset) {
+                self.build_offset_vbo();
+            }
+            if self.draw_offset
+                && ui.slider_float(im_str!("Offset Distance"), &mut self.offset_distance, -2.0, 2.0).build()
+            {
+                self.build_offset_vbo();
+            }
+        }
+        if ui.checkbox(im_str!("Draw de Boor Construction"), &mut self.draw_construction) {
+            self.build_construction_vbo();
+        }
+        if self.draw_construction && !self.curve.control_points.is_empty() {
+            let t_range = self.curve.knot_domain();
+            if ui.slider_float(im_str!("Construction t"), &mut self.construction_t,
+                               t_range.0, t_range.1).build()
+            {
+                self.build_construction_vbo();
+            }
+        }
+        if ui.checkbox(im_str!("Draw End Tangents"), &mut self.draw_tangents) {
+            self.build_tangents_vbo();
+        }
+        if self.draw_tangents
+            && ui.slider_float(im_str!("Tangent Scale"), &mut self.tangent_scale, 0.01, 2.0).build()
+        {
+            self.build_tangents_vbo();
+        }
+        if ui.checkbox(im_str!("Draw Osculating Circle"), &mut self.draw_osculating_circle) {
+            self.build_osculating_circle_vbo();
+        }
+        if self.draw_osculating_circle && !self.curve.control_points.is_empty() {
+            let t_range = self.curve.knot_domain();
+            if ui.slider_float(im_str!("Osculating t"), &mut self.osculating_t,
+                               t_range.0, t_range.1).build()
+            {
+                self.build_osculating_circle_vbo();
+            }
+            match self.osculating_radius {
+                Some(r) => ui.text(im_str!("Curvature: {:.4}, Radius: {:.4}", 1.0 / r, r)),
+                None => ui.text(im_str!("Curvature: 0 (no circle)")),
+            }
+        }
+        if self.curve.control_points.len() > 1 && self.curve.degree() == 0 {
+            // A step curve can't pass through its points, the degree must be raised first
+            ui.text_disabled(im_str!("Interpolate Points (needs degree 1 or higher)"));
+        } else if self.curve.control_points.len() > 1 && ui.small_button(im_str!("Interpolate Points")) {
+            // Replace the curve with one passing through its control points
+            self.curve = BSpline::interpolate(&self.curve.control_points[..], self.curve.degree());
+            self.rebuild_geometry();
+        }
+        if !self.curve.control_points.is_empty() && self.validation_error.is_none() {
+            ui.tree_node(im_str!("Evaluate Point")).build(|| {
+                ui.input_float(im_str!("Evaluate t"), &mut self.eval_t).build();
+                ui.checkbox(im_str!("Show Tangent"), &mut self.eval_tangent);
+                let domain = self.curve.knot_domain();
+                if !(self.eval_t >= domain.0 && self.eval_t <= domain.1) {
+                    ui.text_colored((1.0, 0.4, 0.4, 1.0),
+                                    im_str!("t must be in the knot domain [{:.4}, {:.4}]", domain.0, domain.1));
+                    return;
+                }
+                let p = self.curve.point(self.eval_t);
+                ui.text(im_str!("Point: ({:.6}, {:.6}, {:.6})", p.pos[0], p.pos[1], p.pos[2]));
+                if self.eval_tangent && self.curve.degree() > 0 {
+                    let d = self.curve.derivative().point(self.eval_t);
+                    ui.text(im_str!("Tangent: ({:.6}, {:.6}, {:.6})", d.pos[0], d.pos[1], d.pos[2]));
+                }
+            });
+        }
+        if self.curve.control_points.len() > 2 {
+            ui.slider_float(im_str!("Simplify Tolerance"), &mut self.simplify_tolerance, 0.001, 0.5).build();
+            if ui.small_button(im_str!("Simplify")) {
+                self.curve.decimate(self.simplify_tolerance);
+                self.selected_point = None;
+                self.rebuild_geometry();
+            }
+        }
+        if !self.curve.control_points.is_empty() && ui.small_button(im_str!("Normalize Domain")) {
+            // Remap the parameters we're showing things at so they stay at the same place on the curve
+            let (a, b) = self.curve.knot_domain();
+            if b > a {
+                for t in &mut [&mut self.construction_t, &mut self.osculating_t, &mut self.animation_t] {
+                    **t = (**t - a) / (b - a);
+                }
+            }
+            self.curve.normalize_domain();
+            self.rebuild_geometry();
+        }
+        // Step curves have no interior knots to space out
+        if can_edit_degree(&self.curve) && self.curve.degree() > 0
+            && ui.small_button(im_str!("Chord-length Knots"))
         {
-            if self.curve.max_possible_degree() != 0 {
-                self.curve.set_degree(curve_degree as usize);
+            self.curve.rechord_knots();
+            self.rebuild_geometry();
+        }
+        if self.is_3d && self.curve.control_points.len() > 2 && ui.small_button(im_str!("Flatten")) {
+            // Project the curve onto the plane that best fits its control points
+            let (normal, d) = self.curve.fit_plane();
+            self.curve.project_to_plane(normal, d);
+            self.rebuild_geometry();
+        }
+        if self.is_3d && ui.checkbox(im_str!("Flip Handedness"), &mut self.flip_handedness) {
+            self.curve.flip_handedness();
+            self.rebuild_geometry();
+        }
+        ui.checkbox(im_str!("Animate Marker"), &mut self.animate);
+        if self.animate {
+            ui.slider_float(im_str!("Marker Speed"), &mut self.animation_speed, 0.01, 5.0).build();
+        }
+        let mut curve_changed = false;
+        if can_edit_degree(&self.curve) {
+            // I use the open curve term b/c Elaine will be interacting with it and she
+            // calls clamped curves open.
+            let mut curve_clamped = self.curve.is_clamped();
+            if ui.checkbox(im_str!("Open Curve"), &mut curve_clamped) {
+                self.curve.set_clamped(curve_clamped);
+                curve_changed = true;
+            }
+            let (mut clamp_start, mut clamp_end) = self.curve.end_conditions();
+            let start_changed = ui.checkbox(im_str!("Clamp Start"), &mut clamp_start);
+            let end_changed = ui.checkbox(im_str!("Clamp End"), &mut clamp_end);
+            if start_changed || end_changed {
+                self.curve.set_end_conditions(clamp_start, clamp_end);
                 curve_changed = true;
             }
         }
-        if curve_changed && !self.curve.control_points.is_empty() {
-            let step_size = 0.01;
-            let t_range = self.curve.knot_domain();
-            let steps = ((t_range.1 - t_range.0) / step_size) as usize;
-            self.control_points_vbo = VertexBuffer::new(self.display, &self.curve.control_points[..]).unwrap();
-            let mut points = Vec::with_capacity(steps);
-            // Just draw the first one for now
-            for s in 0..steps + 1 {
-                let t = step_size * s as f32 + t_range.0;
-                points.push(self.curve.point(t));
-            }
-            self.curve_points_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
-            let break_points: Vec<_> = self.curve.knot_domain_iter().map(|b| self.curve.point(*b)).collect();
-            self.break_points_vbo = VertexBuffer::new(self.display, &break_points[..]).unwrap();
+        let mut regenerate_knots = self.curve.regenerates_knots();
+        if ui.checkbox(im_str!("Regenerate Knots on Edit"), &mut regenerate_knots) {
+            self.curve.set_regenerate_knots(regenerate_knots);
+        }
+        if can_edit_degree(&self.curve) {
+            let mut curve_degree = self.curve.degree() as i32;
+            // Degrees past what the control points support are reached by degree elevation
+            let max_degree = cmp::max(self.curve.max_possible_degree(), MAX_PADDED_DEGREE);
+            if ui.slider_int(im_str!("Curve Degree"), &mut curve_degree, 1, max_degree as i32).build() {
+                self.curve.set_degree_padded(curve_degree as usize);
+                curve_changed = true;
+            }
+        } else {
+            ui.text_disabled(im_str!("Add more points to set the degree and end conditions"));
+        }
+        if curve_changed {
+            self.rebuild_geometry();
         }
         ui.color_edit3(im_str!("Curve Color"), &mut self.curve_color).build();
+        ui.slider_float(im_str!("Opacity"), &mut self.alpha, 0.0, 1.0).build();
         ui.color_edit3(im_str!("Control Color"), &mut self.control_color).build();
         ui.color_edit3(im_str!("Break Point Color"), &mut self.break_point_color).build();
+        if self.draw_intersections {
+            ui.color_edit3(im_str!("Intersection Color"), &mut self.intersection_color).build();
+        }
+        if self.draw_inflections {
+            ui.color_edit3(im_str!("Inflection Color"), &mut self.inflection_color).build();
+        }
+        if self.draw_greville {
+            ui.color_edit3(im_str!("Greville Color"), &mut self.greville_color).build();
+        }
+        if self.draw_offset {
+            ui.color_edit3(im_str!("Offset Color"), &mut self.offset_color).build();
+        }
+        if self.animate {
+            ui.color_edit3(im_str!("Marker Color"), &mut self.marker_color).build();
+        }
+        self.draw_point_insertion_ui(ui);
+        if self.draw_construction {
+            ui.color_edit3(im_str!("Construction Color"), &mut self.construction_color).build();
+        }
+        if self.draw_osculating_circle {
+            ui.color_edit3(im_str!("Osculating Circle Color"), &mut self.osculating_color).build();
+        }
+        if self.draw_gradient {
+            let start_changed = ui.color_edit3(im_str!("Gradient Start"), &mut self.gradient_start_color).build();
+            let end_changed = ui.color_edit3(im_str!("Gradient End"), &mut self.gradient_end_color).build();
+            if start_changed || end_changed {
+                self.build_gradient_vbo();
+            }
+        }
+    }
+    /// Advance the animated marker along the curve by the time elapsed since the last
+    /// frame, looping back to the start of the knot domain once it reaches the end.
+    fn update(&mut self, delta_s: f32) {
+        if !self.animate || self.curve.control_points.is_empty() || self.validation_error.is_some() {
+            return;
+        }
+        let t_range = self.curve.knot_domain();
+        let domain_length = t_range.1 - t_range.0;
+        self.animation_t += self.animation_speed * delta_s;
+        if self.animation_t < t_range.0 || domain_length <= 0.0 {
+            self.animation_t = t_range.0;
+        } else if self.animation_t > t_range.1 {
+            self.animation_t = t_range.0 + (self.animation_t - t_range.1) % domain_length;
+        }
+        self.marker_vbo.write(&[self.curve.point(self.animation_t)]);
+    }
+    fn bounds(&self) -> Option<(Point, Point)> {
+        // The curve lies within the convex hull of its control points
+        if self.curve.control_points.is_empty() {
+            None
+        } else {
+            Some(point::bounding_box(&self.curve.control_points[..]))
+        }
+    }
+    fn translate(&mut self, offset: Point) {
+        for p in self.curve.control_points.iter_mut() {
+            *p = *p + offset;
+        }
+        self.rebuild_geometry();
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+    fn source(&self) -> Option<(&str, usize)> {
+        self.source.as_ref().map(|s| (&s.0[..], s.1))
+    }
+    fn set_source(&mut self, path: String, index: usize) {
+        self.source = Some((path, index));
+    }
+    fn set_geometry(&mut self, mut geometry: Geometry) -> bool {
+        if self.flip_handedness {
+            geometry.flip_handedness();
+        }
+        match geometry {
+            Geometry::Curve(curve, is_3d) => {
+                self.curve = curve;
+                self.is_3d = is_3d;
+                // The points being edited may not be on the new curve
+                self.moving_point = None;
+                self.selected_point = None;
+                self.rebuild_geometry();
+                true
+            },
+            _ => false,
+        }
+    }
+    fn color(&self) -> [f32; 3] {
+        self.curve_color
+    }
+    fn set_color(&mut self, color: [f32; 3]) {
+        self.curve_color = color;
+    }
+    fn alpha(&self) -> f32 {
+        self.alpha
+    }
+    fn ground_shadow(&self) -> Option<&VertexBuffer<Point>> {
+        if self.is_3d && self.draw_shadow && self.draw_curve {
+            Some(&self.curve_points_vbo)
+        } else {
+            None
+        }
+    }
+    fn as_editable(&mut self) -> Option<&mut dyn EditableCurve> {
+        Some(self)
     }
 }
 
+impl<'a, F: 'a + Facade> EditableCurve for DisplayCurve<'a, F> {
+    fn curve(&self) -> &BSpline<Point> {
+        &self.curve
+    }
+    fn is_3d(&self) -> bool {
+        self.is_3d
+    }
+    fn handle_click(&mut self, pos: Point, shift_down: bool, zoom_factor: f32) {
+        // If we're close to control point of the selected curve we're dragging it,
+        // otherwise we're adding a new point. The 2D view only edits x and y, so points
+        // are picked by their distance in the xy plane and keep their z when moved
+        let flatten = |p: Point| Point::new(p.pos[0], p.pos[1], 0.0);
+        let nearest = self.curve.control_points().enumerate().map(|(i, x)| (i, (flatten(*x) - pos).length()))
+            .fold((0, f32::MAX), |acc, (i, d)| if d < acc.1 { (i, d) } else { acc });
+        let keep_z = |p: Point| Point::new(pos.pos[0], pos.pos[1], p.pos[2]);
+        let point_size = 12.0 / (100.0 * zoom_factor);
+        if shift_down {
+            self.moving_point = None;
+            if nearest.1 < point_size {
+                self.curve.remove_point(nearest.0);
+                self.selected_point = None;
+            }
+        } else if let Some(p) = self.moving_point {
+            self.curve.control_points[p] = keep_z(self.curve.control_points[p]);
+        } else if self.knot_insert_mode && nearest.1 >= point_size {
+            if !self.inserted_knot && insert_knot_at(&mut self.curve, pos, point_size) {
+                self.inserted_knot = true;
+                self.selected_point = None;
+            }
+        } else if self.append_mode {
+            self.moving_point = Some(self.curve.append_point(pos));
+        } else if nearest.1 < point_size {
+            self.moving_point = Some(nearest.0);
+            self.curve.control_points[nearest.0] = keep_z(self.curve.control_points[nearest.0]);
+        } else {
+            self.moving_point = Some(self.curve.insert_point(pos));
+        }
+        if self.moving_point.is_some() {
+            self.selected_point = self.moving_point;
+        }
+        self.rebuild_geometry();
+    }
+    fn release_point(&mut self) {
+        self.moving_point = None;
+        self.inserted_knot = false;
+    }
+    fn elevate_degree(&mut self) {
+        self.curve.elevate_degree();
+        self.rebuild_geometry();
+    }
+}
+
+/// Check if the curve has enough control points for its degree and end conditions to be
+/// changed, curves with fewer points than their degree needs have no valid knot domain.
+fn can_edit_degree(curve: &BSpline<Point>) -> bool {
+    curve.control_points.len() > 1 && curve.validate().is_ok()
+}
+
+/// Insert a knot into the curve at the parameter of the point on it nearest `pos`, if that
+/// point is within `tolerance` of `pos` in the xy plane. Returns true if a knot was inserted,
+/// the shape of the curve is unchanged but it gains a control point.
+fn insert_knot_at(curve: &mut BSpline<Point>, pos: Point, tolerance: f32) -> bool {
+    if curve.control_points.is_empty() || curve.validate().is_err() {
+        return false;
+    }
+    let (t, p) = curve.closest_point(&pos);
+    if (Point::new(p.pos[0], p.pos[1], 0.0) - pos).length() >= tolerance {
+        return false;
+    }
+    let n = curve.control_points.len();
+    curve.insert_knot(t);
+    curve.control_points.len() > n
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn knot_insert_click_keeps_shape() {
+        let points = vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.0), Point::new(3.0, 2.0, 0.0),
+                          Point::new(4.0, 0.0, 0.0), Point::new(5.0, 1.0, 0.0)];
+        let original = BSpline::new(3, points, Vec::new());
+        let mut curve = original.clone();
+        // Clicks away from the curve don't insert anything
+        assert!(!insert_knot_at(&mut curve, Point::new(2.0, 10.0, 0.0), 0.1));
+        assert_eq!(curve.control_points.len(), 5);
+
+        let click = original.point(0.7) + Point::new(0.0, 0.01, 0.0);
+        assert!(insert_knot_at(&mut curve, click, 0.1));
+        assert_eq!(curve.control_points.len(), 6);
+        assert_eq!(curve.knots().count(), 10);
+        assert_eq!(curve.knot_domain(), original.knot_domain());
+        for t in bspline::sample_domain(original.knot_domain(), 0.01) {
+            assert!((curve.point(t) - original.point(t)).length() < 1e-4, "shape changed at t = {}", t);
+        }
+    }
+
+    #[test]
+    fn degree_editing_needs_enough_points() {
+        let mut curve = BSpline::empty();
+        assert!(!can_edit_degree(&curve));
+        curve.append_point(Point::new(0.0, 0.0, 0.0));
+        assert!(!can_edit_degree(&curve));
+        curve.append_point(Point::new(1.0, 0.0, 0.0));
+        assert!(can_edit_degree(&curve));
+        let mut cubic = BSpline::new(3, (0..4).map(|i| Point::new(i as f32, 0.0, 0.0)).collect(), Vec::new());
+        assert!(can_edit_degree(&cubic));
+        // Removing points below what the degree needs lowers the degree to stay valid
+        cubic.remove_point(3);
+        assert!(can_edit_degree(&cubic));
+    }
+}
@@ -3,18 +3,42 @@
 
 use std::f32;
 
-use glium::{Surface, VertexBuffer, Program, DrawParameters};
+use glium::{Surface, VertexBuffer, Program, DrawParameters, Frame};
 use glium::backend::Facade;
 use glium::index::{NoIndices, PrimitiveType};
 use imgui::Ui;
 
+use bspline::{self, BSpline};
 use bspline_surf::BSplineSurf;
-use point::Point;
+use point::{self, Point, ColorPoint};
+use scene_object::{self, SceneObject, Geometry};
+use message_log::log_message;
+use mesh_export;
+use imgui_support;
 
-pub struct DisplaySurf {
+pub struct DisplaySurf<'a, F: 'a + Facade> {
+    display: &'a F,
+    surf: BSplineSurf<Point>,
+    name: String,
+    // The file the surface was loaded from and its index among the file's objects
+    source: Option<(String, usize)>,
+    // If the surface is mirrored from the coordinates it was loaded in
+    flip_handedness: bool,
+    // Spacing in parameter space between the plain isolines
+    isoline_spacing: f32,
+    // Step size in parameter space when sampling along an isoline
+    sample_step_size: f32,
+    // Max number of isolines to draw, surfaces with more are thinned out
+    max_isolines: i32,
     // Plain isolines along the curve
     isolines_u_vbos: Vec<VertexBuffer<Point>>,
     isolines_v_vbos: Vec<VertexBuffer<Point>>,
+    // The plain isolines colored by the scalar field picked by color_by
+    scalar_isolines_vbos: Vec<VertexBuffer<ColorPoint>>,
+    // The scalar field to color the surface by, 0 is none, then height, Gaussian and mean curvature
+    color_by: i32,
+    // The range of the scalar field mapped onto the colormap
+    scalar_range: (f32, f32),
     // Isolines along the greville abscissa
     greville_u_vbos: Vec<VertexBuffer<Point>>,
     greville_v_vbos: Vec<VertexBuffer<Point>>,
@@ -22,32 +46,170 @@ pub struct DisplaySurf {
     knot_u_vbos: Vec<VertexBuffer<Point>>,
     knot_v_vbos: Vec<VertexBuffer<Point>>,
     control_points_vbo: VertexBuffer<Point>,
+    // Tangent vectors along the Greville isolines, drawn as line segments
+    tangents_u_vbo: VertexBuffer<Point>,
+    tangents_v_vbo: VertexBuffer<Point>,
+    // Markers pinned on the surface by the user, stored as their (u, v) parameters
+    markers: Vec<(f32, f32)>,
+    markers_vbo: VertexBuffer<Point>,
+    bounds: (Point, Point),
     draw_surf: bool,
     draw_greville: bool,
     draw_knots: bool,
     draw_control_points: bool,
+    draw_tangents_u: bool,
+    draw_tangents_v: bool,
+    // The Greville, knot, control point and tangent toggles saved when presentation mode
+    // hid them, restored when it's turned off
+    presentation_mode: Option<[bool; 5]>,
+    // Whether the selected line of the control mesh is a column instead of a row
+    edit_columns: bool,
+    selected_line: Option<usize>,
+    selected_line_vbo: VertexBuffer<Point>,
+    // Distance the selected row or column is moved by each nudge
+    nudge_step: f32,
+    // The problem with the last knot edit, if it was rejected
+    knot_error: Option<String>,
     curve_color: [f32; 3],
+    // Opacity of the whole object, below 1 it's blended over the objects behind it
+    alpha: f32,
     greville_color: [f32; 3],
     knot_color: [f32; 3],
     control_color: [f32; 3],
+    tangent_color: [f32; 3],
+    marker_color: [f32; 3],
+    // Whether to extract the isoline along u or v and the fixed parameter value it's at
+    extract_along_u: bool,
+    extract_param: f32,
+    extracted_curve: Option<BSpline<Point>>,
+    // Number of vertices along u and v in the exported mesh
+    export_resolution: i32,
+    // Write quad faces to the exported mesh instead of splitting them into triangles
+    export_quads: bool,
+    // Index of the format to export the mesh as
+    export_format: i32,
 }
 
-impl DisplaySurf {
-    pub fn new<'a, F: 'a + Facade>(surf: BSplineSurf<Point>, display: &'a F) -> DisplaySurf {
-        let isoline_step_size = 0.1;
-        let step_size = 0.01;
+impl<'a, F: 'a + Facade> DisplaySurf<'a, F> {
+    pub fn new(surf: BSplineSurf<Point>, display: &'a F) -> DisplaySurf<'a, F> {
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let mut display_surf = DisplaySurf { display: display,
+                                             surf: surf,
+                                             name: "Surface".to_owned(),
+                                             source: None,
+                                             flip_handedness: false,
+                                             isoline_spacing: 0.1,
+                                             sample_step_size: 0.01,
+                                             max_isolines: 200,
+                                             isolines_u_vbos: Vec::new(),
+                                             isolines_v_vbos: Vec::new(),
+                                             scalar_isolines_vbos: Vec::new(),
+                                             color_by: 0,
+                                             scalar_range: (0.0, 0.0),
+                                             greville_u_vbos: Vec::new(),
+                                             greville_v_vbos: Vec::new(),
+                                             knot_u_vbos: Vec::new(),
+                                             knot_v_vbos: Vec::new(),
+                                             control_points_vbo: VertexBuffer::empty(display, 10).unwrap(),
+                                             tangents_u_vbo: VertexBuffer::empty(display, 10).unwrap(),
+                                             tangents_v_vbo: VertexBuffer::empty(display, 10).unwrap(),
+                                             markers: Vec::new(),
+                                             markers_vbo: VertexBuffer::empty(display, 10).unwrap(),
+                                             bounds: (origin, origin),
+                                             draw_surf: true,
+                                             draw_greville: true,
+                                             draw_knots: true,
+                                             draw_control_points: true,
+                                             draw_tangents_u: false,
+                                             draw_tangents_v: false,
+                                             presentation_mode: None,
+                                             edit_columns: false,
+                                             selected_line: None,
+                                             selected_line_vbo: VertexBuffer::empty(display, 10).unwrap(),
+                                             nudge_step: 0.1,
+                                             knot_error: None,
+                                             curve_color: [0.8, 0.8, 0.1],
+                                             alpha: 1.0,
+                                             greville_color: [0.1, 0.8, 0.8],
+                                             knot_color: [0.8, 0.1, 0.8],
+                                             control_color: [0.8, 0.8, 0.8],
+                                             tangent_color: [0.9, 0.5, 0.1],
+                                             marker_color: [0.9, 0.2, 0.2],
+                                             extract_along_u: true,
+                                             extract_param: 0.0,
+                                             extracted_curve: None,
+                                             export_resolution: 32,
+                                             export_quads: false,
+                                             export_format: 0,
+        };
+        display_surf.rebuild_geometry();
+        display_surf
+    }
+    /// Hide the Greville and knot isolines, control points and tangents for a clean view of
+    /// the surface, turning presentation mode off restores them to how they were.
+    pub fn set_presentation_mode(&mut self, on: bool) {
+        if on && self.presentation_mode.is_none() {
+            self.presentation_mode = Some([self.draw_greville, self.draw_knots, self.draw_control_points,
+                                           self.draw_tangents_u, self.draw_tangents_v]);
+            self.draw_greville = false;
+            self.draw_knots = false;
+            self.draw_control_points = false;
+            self.draw_tangents_u = false;
+            self.draw_tangents_v = false;
+        } else if !on {
+            if let Some(saved) = self.presentation_mode.take() {
+                self.draw_greville = saved[0];
+                self.draw_knots = saved[1];
+                self.draw_control_points = saved[2];
+                self.draw_tangents_u = saved[3];
+                self.draw_tangents_v = saved[4];
+            }
+        }
+    }
+    pub fn presentation_mode(&self) -> bool {
+        self.presentation_mode.is_some()
+    }
+    /// Check if the surface is mirrored from the coordinates it was loaded in
+    pub fn flipped_handedness(&self) -> bool {
+        self.flip_handedness
+    }
+    /// Replace the surface being displayed, keeping the current display settings
+    pub fn set_surface(&mut self, surf: BSplineSurf<Point>) {
+        self.surf = surf;
+        self.rebuild_geometry();
+    }
+    /// Rebuild all the vertex buffers for the surface, this must be called whenever the
+    /// surface or its isoline spacing or sampling step size is changed.
+    pub fn rebuild_geometry(&mut self) {
+        let surf = &self.surf;
+        let display = self.display;
+        let isoline_step_size = self.isoline_spacing;
+        let step_size = self.sample_step_size;
 
         let t_range_u = surf.knot_domain_u();
         let t_range_v = surf.knot_domain_v();
 
         let isoline_start_steps_u = ((t_range_u.1 - t_range_u.0) / isoline_step_size) as usize;
         let isoline_start_steps_v = ((t_range_v.1 - t_range_v.0) / isoline_step_size) as usize;
-        let steps_u = ((t_range_u.1 - t_range_u.0) / step_size) as usize;
-        let steps_v = ((t_range_v.1 - t_range_v.0) / step_size) as usize;
+        let samples_u = bspline::sample_domain(t_range_u, step_size);
+        let samples_v = bspline::sample_domain(t_range_v, step_size);
+        let steps_u = samples_u.len();
+        let steps_v = samples_v.len();
 
         let abscissa_u = surf.greville_abscissa_u();
         let abscissa_v = surf.greville_abscissa_v();
 
+        // Cap the number of isolines so surfaces with many knots don't hang the viewer, if we're
+        // over the cap each set of isolines is thinned out by taking every stride'th line
+        let set_sizes = [abscissa_u.len(), abscissa_v.len(), surf.knot_domain_u_iter().count(),
+                         surf.knot_domain_v_iter().count(), isoline_start_steps_u + 1, isoline_start_steps_v + 1];
+        let stride = isoline_stride(&set_sizes, self.max_isolines as usize);
+        if stride > 1 {
+            log_message(format!("Warning: surface has {} isolines, only drawing every {} to stay under {}",
+                                set_sizes.iter().sum::<usize>(), stride,
+                                usize::max(self.max_isolines as usize, set_sizes.len())));
+        }
+
         // We need in addition to the regular line sample steps to also sample where
         // an isoline is along the other axis, or greville point, or knot value so that
         // when the lines cross they both have that crossing point
@@ -70,13 +232,13 @@ impl DisplaySurf {
         t_vals_v.dedup();
 
         // t values for an isoline along u
-        let mut isoline_u_t_vals: Vec<_> = (0..steps_u + 1).map(|u| step_size * u as f32 + t_range_u.0)
+        let mut isoline_u_t_vals: Vec<_> = samples_u.iter().map(|x| *x)
             .chain(t_vals_u.iter().map(|x| *x)).collect();
         isoline_u_t_vals.sort_by(|a, b| a.partial_cmp(b).unwrap());
         isoline_u_t_vals.dedup();
 
         // t values for an isoline along v
-        let mut isoline_v_t_vals: Vec<_> = (0..steps_v + 1).map(|u| step_size * u as f32 + t_range_v.0)
+        let mut isoline_v_t_vals: Vec<_> = samples_v.iter().map(|x| *x)
             .chain(t_vals_v.iter().map(|x| *x)).collect();
         isoline_v_t_vals.sort_by(|a, b| a.partial_cmp(b).unwrap());
         isoline_v_t_vals.dedup();
@@ -84,7 +246,7 @@ impl DisplaySurf {
         let mut greville_u_vbos = Vec::with_capacity(abscissa_u.len());
         let mut greville_v_vbos = Vec::with_capacity(abscissa_v.len());
         // For each Greville abscissa on u draw an isoline along v
-        for u in &abscissa_u[..] {
+        for u in abscissa_u.iter().step_by(stride) {
             let curve = surf.isoline_v(*u);
             let mut points = Vec::with_capacity(steps_v);
             for t in &isoline_v_t_vals[..] {
@@ -93,7 +255,7 @@ impl DisplaySurf {
             greville_u_vbos.push(VertexBuffer::new(display, &points[..]).unwrap());
         }
         // For each Greville abscissa on v draw an isoline along u
-        for v in &abscissa_v[..] {
+        for v in abscissa_v.iter().step_by(stride) {
             let curve = surf.isoline_u(*v);
             let mut points = Vec::with_capacity(steps_u);
             for t in &isoline_u_t_vals[..] {
@@ -105,7 +267,7 @@ impl DisplaySurf {
         let mut knot_u_vbos = Vec::with_capacity(surf.knots_u.len());
         let mut knot_v_vbos = Vec::with_capacity(surf.knots_v.len());
         // For each knot on u draw an isoline along v
-        for u in surf.knot_domain_u_iter() {
+        for u in surf.knot_domain_u_iter().step_by(stride) {
             let curve = surf.isoline_v(*u);
             let mut points = Vec::with_capacity(steps_v);
             for t in &isoline_v_t_vals[..] {
@@ -114,7 +276,7 @@ impl DisplaySurf {
             knot_u_vbos.push(VertexBuffer::new(display, &points[..]).unwrap());
         }
         // For each knot on v draw an isoline along u
-        for v in surf.knot_domain_v_iter() {
+        for v in surf.knot_domain_v_iter().step_by(stride) {
             let curve = surf.isoline_u(*v);
             let mut points = Vec::with_capacity(steps_u);
             for t in &isoline_u_t_vals[..] {
@@ -125,8 +287,11 @@ impl DisplaySurf {
 
         let mut isolines_u_vbos = Vec::with_capacity(isoline_start_steps_v);
         let mut isolines_v_vbos = Vec::with_capacity(isoline_start_steps_u);
+        // The (u, v) parameters of the samples along each plain isoline, to evaluate the scalar
+        // field the surface is colored by at
+        let mut isoline_params = Vec::new();
         // Compute isolines along u
-        for vs in 0..isoline_start_steps_v + 1 {
+        for vs in (0..isoline_start_steps_v + 1).step_by(stride) {
             let v = isoline_step_size * vs as f32 + t_range_v.0;
             if !abscissa_v.iter().chain(surf.knots_v.iter()).any(|x| *x == v) {
                 let curve = surf.isoline_u(v);
@@ -135,10 +300,11 @@ impl DisplaySurf {
                     points.push(curve.point(*t));
                 }
                 isolines_u_vbos.push(VertexBuffer::new(display, &points[..]).unwrap());
+                isoline_params.push(isoline_u_t_vals.iter().map(|t| (*t, v)).collect::<Vec<_>>());
             }
         }
         // Compute isolines along v
-        for us in 0..isoline_start_steps_u + 1 {
+        for us in (0..isoline_start_steps_u + 1).step_by(stride) {
             let u = isoline_step_size * us as f32 + t_range_u.0;
             if !abscissa_u.iter().chain(surf.knots_u.iter()).any(|x| *x == u) {
                 let curve = surf.isoline_v(u);
@@ -147,8 +313,37 @@ impl DisplaySurf {
                     points.push(curve.point(*t));
                 }
                 isolines_v_vbos.push(VertexBuffer::new(display, &points[..]).unwrap());
+                isoline_params.push(isoline_v_t_vals.iter().map(|t| (u, *t)).collect::<Vec<_>>());
+            }
+        }
+
+        // The tangents are scaled down a bit so they don't cover the surface
+        let tangent_scale = 0.1;
+        let mut tangents_u = Vec::new();
+        // Along each Greville isoline on v draw the tangents in the u direction, the tangents
+        // are thinned out by the same stride as the isolines
+        for v in abscissa_v.iter().step_by(stride) {
+            let curve = surf.isoline_u(*v);
+            let deriv = surf.derivative_isoline_u(*v);
+            for t in t_vals_u.iter().step_by(stride) {
+                let p = curve.point(*t);
+                tangents_u.push(p);
+                tangents_u.push(p + deriv.point(*t) * tangent_scale);
+            }
+        }
+        let mut tangents_v = Vec::new();
+        // Along each Greville isoline on u draw the tangents in the v direction
+        for u in abscissa_u.iter().step_by(stride) {
+            let curve = surf.isoline_v(*u);
+            let deriv = surf.derivative_isoline_v(*u);
+            for t in t_vals_v.iter().step_by(stride) {
+                let p = curve.point(*t);
+                tangents_v.push(p);
+                tangents_v.push(p + deriv.point(*t) * tangent_scale);
             }
         }
+        let tangents_u_vbo = VertexBuffer::new(display, &tangents_u[..]).unwrap();
+        let tangents_v_vbo = VertexBuffer::new(display, &tangents_v[..]).unwrap();
 
         let mut control_points = Vec::new();
         for r in &surf.control_mesh[..] {
@@ -158,28 +353,121 @@ impl DisplaySurf {
         }
         let control_points_vbo = VertexBuffer::new(display, &control_points[..]).unwrap();
 
-        DisplaySurf { isolines_u_vbos: isolines_u_vbos,
-                      isolines_v_vbos: isolines_v_vbos,
-                      greville_u_vbos: greville_u_vbos,
-                      greville_v_vbos: greville_v_vbos,
-                      knot_u_vbos: knot_u_vbos,
-                      knot_v_vbos: knot_v_vbos,
-                      control_points_vbo: control_points_vbo,
-                      draw_surf: true,
-                      draw_greville: true,
-                      draw_knots: true,
-                      draw_control_points: true,
-                      curve_color: [0.8, 0.8, 0.1],
-                      greville_color: [0.1, 0.8, 0.8],
-                      knot_color: [0.8, 0.1, 0.8],
-                      control_color: [0.8, 0.8, 0.8],
-        }
-    }
-    pub fn render<S: Surface>(&self, target: &mut S, program: &Program, draw_params: &DrawParameters,
-                  proj_view: &[[f32; 4]; 4], selected: bool, attenuation: f32) {
-        let (curve_color, control_color, greville_color, knot_color) =
+        self.isolines_u_vbos = isolines_u_vbos;
+        self.isolines_v_vbos = isolines_v_vbos;
+        self.greville_u_vbos = greville_u_vbos;
+        self.greville_v_vbos = greville_v_vbos;
+        self.knot_u_vbos = knot_u_vbos;
+        self.knot_v_vbos = knot_v_vbos;
+        self.control_points_vbo = control_points_vbo;
+        self.tangents_u_vbo = tangents_u_vbo;
+        self.tangents_v_vbo = tangents_v_vbo;
+        self.bounds = point::bounding_box(&control_points[..]);
+        self.build_markers_vbo();
+        self.build_selected_line_vbo();
+        self.build_scalar_vbos(&isoline_params[..]);
+    }
+    /// Evaluate the scalar field picked by `color_by` along the plain isolines sampled at
+    /// `isoline_params` and build their vertex buffers colored by it.
+    fn build_scalar_vbos(&mut self, isoline_params: &[Vec<(f32, f32)>]) {
+        if self.color_by == 0 {
+            self.scalar_isolines_vbos = Vec::new();
+            return;
+        }
+        let surf = &self.surf;
+        let scalar = |u: f32, v: f32| -> f32 {
+            match self.color_by {
+                1 => surf.point(u, v).pos[2],
+                2 => surf.gaussian_curvature(u, v),
+                _ => surf.mean_curvature(u, v),
+            }
+        };
+        let values: Vec<Vec<f32>> = isoline_params.iter()
+            .map(|line| line.iter().map(|&(u, v)| scalar(u, v)).collect()).collect();
+        let all_values: Vec<_> = values.iter().flat_map(|l| l.iter().cloned()).collect();
+        self.scalar_range = scene_object::scalar_range(&all_values[..]);
+        let mut vbos = Vec::with_capacity(isoline_params.len());
+        for (line, vals) in isoline_params.iter().zip(values.iter()) {
+            let points: Vec<_> = line.iter().zip(vals.iter())
+                .map(|(&(u, v), x)| ColorPoint::new(surf.point(u, v), scene_object::colormap(*x, self.scalar_range)))
+                .collect();
+            vbos.push(VertexBuffer::new(self.display, &points[..]).unwrap());
+        }
+        self.scalar_isolines_vbos = vbos;
+    }
+    /// Get the number of rows or columns in the control mesh, for the current edit mode
+    fn num_mesh_lines(&self) -> usize {
+        if self.edit_columns { self.surf.control_mesh[0].len() } else { self.surf.control_mesh.len() }
+    }
+    /// Build the vertex buffer to highlight the selected row or column of the control mesh
+    fn build_selected_line_vbo(&mut self) {
+        if self.selected_line.map_or(false, |l| l >= self.num_mesh_lines()) {
+            self.selected_line = None;
+        }
+        if let Some(l) = self.selected_line {
+            let points: Vec<_> =
+                if self.edit_columns {
+                    self.surf.control_mesh.iter().map(|r| r[l]).collect()
+                } else {
+                    self.surf.control_mesh[l].clone()
+                };
+            self.selected_line_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
+        }
+    }
+    /// Move the selected row or column of the control mesh by `delta` and rebuild the isolines
+    fn nudge_selected_line(&mut self, delta: Point) {
+        if let Some(l) = self.selected_line {
+            if self.edit_columns {
+                self.surf.translate_col(l, delta);
+            } else {
+                self.surf.translate_row(l, delta);
+            }
+            self.rebuild_geometry();
+        }
+    }
+    /// Evaluate the pinned markers on the surface and build the vertex buffer to draw them
+    fn build_markers_vbo(&mut self) {
+        if self.markers.is_empty() {
+            return;
+        }
+        let points: Vec<_> = self.markers.iter().map(|m| self.surf.point(m.0, m.1)).collect();
+        self.markers_vbo = VertexBuffer::new(self.display, &points[..]).unwrap();
+    }
+}
+
+/// Find the stride to thin out each set of isolines by, taking every stride'th line, so that
+/// at most `max_isolines` are drawn in total for sets of `set_sizes` lines. At least one
+/// line from each set is always drawn, even if that's over the cap.
+fn isoline_stride(set_sizes: &[usize], max_isolines: usize) -> usize {
+    let num_isolines = |stride: usize| -> usize {
+        set_sizes.iter().map(|n| (n + stride - 1) / stride).sum()
+    };
+    let max_isolines = usize::max(max_isolines, set_sizes.len());
+    let mut stride = 1;
+    while num_isolines(stride) > max_isolines {
+        stride += 1;
+    }
+    stride
+}
+
+/// Draw a slider for each knot in `knots`, named by the `axis` they're along. Each knot can
+/// only be moved between its neighbors so the knots stay sorted. Returns true if a knot changed.
+fn edit_knots(ui: &Ui, axis: &str, knots: &mut [f32]) -> bool {
+    let mut changed = false;
+    for i in 0..knots.len() {
+        let min = if i > 0 { knots[i - 1] } else { knots[i] - 1.0 };
+        let max = if i + 1 < knots.len() { knots[i + 1] } else { knots[i] + 1.0 };
+        changed |= ui.slider_float(im_str!("{}[{}]", axis, i), &mut knots[i], min, max).build();
+    }
+    changed
+}
+
+impl<'a, F: 'a + Facade> SceneObject for DisplaySurf<'a, F> {
+    fn render(&self, target: &mut Frame, program: &Program, color_program: &Program,
+              draw_params: &DrawParameters, proj_view: &[[f32; 4]; 4], selected: bool, attenuation: f32) {
+        let (curve_color, control_color, greville_color, knot_color, tangent_color) =
             if selected {
-                (self.curve_color, self.control_color, self.greville_color, self.knot_color)
+                (self.curve_color, self.control_color, self.greville_color, self.knot_color, self.tangent_color)
             } else {
                 ([attenuation * self.curve_color[0], attenuation * self.curve_color[1],
                   attenuation * self.curve_color[2]],
@@ -191,14 +479,26 @@ impl DisplaySurf {
                   attenuation * self.greville_color[2]],
 
                  [attenuation * self.knot_color[0], attenuation * self.knot_color[1],
-                  attenuation * self.knot_color[2]])
+                  attenuation * self.knot_color[2]],
+
+                 [attenuation * self.tangent_color[0], attenuation * self.tangent_color[1],
+                  attenuation * self.tangent_color[2]])
             };
         let uniforms = uniform! {
             proj_view: *proj_view,
             pcolor: curve_color,
         };
         // Draw the curve
-        if self.draw_surf {
+        if self.draw_surf && self.color_by != 0 {
+            let uniforms = uniform! {
+                proj_view: *proj_view,
+                attenuation: if selected { 1.0 } else { attenuation },
+            };
+            for iso in self.scalar_isolines_vbos.iter() {
+                target.draw(iso, &NoIndices(PrimitiveType::LineStrip),
+                            &color_program, &uniforms, &draw_params).unwrap();
+            }
+        } else if self.draw_surf {
             for iso in self.isolines_u_vbos.iter().chain(self.isolines_v_vbos.iter()) {
                 target.draw(iso, &NoIndices(PrimitiveType::LineStrip),
                             &program, &uniforms, &draw_params).unwrap();
@@ -224,6 +524,18 @@ impl DisplaySurf {
                             &program, &uniforms, &draw_params).unwrap();
             }
         }
+        let uniforms = uniform! {
+            proj_view: *proj_view,
+            pcolor: tangent_color,
+        };
+        if self.draw_tangents_u {
+            target.draw(&self.tangents_u_vbo, &NoIndices(PrimitiveType::LinesList),
+                        &program, &uniforms, &draw_params).unwrap();
+        }
+        if self.draw_tangents_v {
+            target.draw(&self.tangents_v_vbo, &NoIndices(PrimitiveType::LinesList),
+                        &program, &uniforms, &draw_params).unwrap();
+        }
         let uniforms = uniform! {
             proj_view: *proj_view,
             pcolor: control_color,
@@ -233,18 +545,254 @@ impl DisplaySurf {
             target.draw(&self.control_points_vbo, &NoIndices(PrimitiveType::Points),
                         &program, &uniforms, &draw_params).unwrap();
         }
+        if !self.markers.is_empty() {
+            let uniforms = uniform! {
+                proj_view: *proj_view,
+                pcolor: self.marker_color,
+            };
+            target.draw(&self.markers_vbo, &NoIndices(PrimitiveType::Points),
+                        &program, &uniforms, &draw_params).unwrap();
+        }
+        if selected && self.selected_line.is_some() {
+            let uniforms = uniform! {
+                proj_view: *proj_view,
+                pcolor: self.marker_color,
+            };
+            target.draw(&self.selected_line_vbo, &NoIndices(PrimitiveType::LineStrip),
+                        &program, &uniforms, &draw_params).unwrap();
+            target.draw(&self.selected_line_vbo, &NoIndices(PrimitiveType::Points),
+                        &program, &uniforms, &draw_params).unwrap();
+        }
     }
-    pub fn draw_ui(&mut self, ui: &Ui) {
+    fn draw_ui(&mut self, ui: &Ui) {
         ui.text(im_str!("3D Surface"));
+        imgui_support::input_text(im_str!("Name"), &mut self.name, 64);
+        if ui.checkbox(im_str!("Flip Handedness"), &mut self.flip_handedness) {
+            self.surf.flip_handedness();
+            self.rebuild_geometry();
+        }
         ui.checkbox(im_str!("Draw Surface"), &mut self.draw_surf);
+        let mut presentation = self.presentation_mode.is_some();
+        if ui.checkbox(im_str!("Presentation Mode"), &mut presentation) {
+            self.set_presentation_mode(presentation);
+        }
         ui.checkbox(im_str!("Draw Greville Isolines"), &mut self.draw_greville);
         ui.checkbox(im_str!("Draw Knot Isolines"), &mut self.draw_knots);
         ui.checkbox(im_str!("Draw Control Points"), &mut self.draw_control_points);
+        ui.checkbox(im_str!("Draw U Tangents"), &mut self.draw_tangents_u);
+        ui.checkbox(im_str!("Draw V Tangents"), &mut self.draw_tangents_v);
+        let spacing_changed = ui.slider_float(im_str!("Isoline spacing"), &mut self.isoline_spacing,
+                                              0.01, 1.0).build();
+        let step_changed = ui.slider_float(im_str!("Isoline smoothness"), &mut self.sample_step_size,
+                                           0.001, 0.1).build();
+        let max_changed = ui.slider_int(im_str!("Max Isolines"), &mut self.max_isolines, 10, 1000).build();
+        let color_by_changed = ui.combo(im_str!("Color By"), &mut self.color_by,
+                                        &[im_str!("None"), im_str!("Height (z)"), im_str!("Gaussian Curvature"),
+                                          im_str!("Mean Curvature")], 4);
+        if self.color_by != 0 {
+            // Show the colormap as a legend, labeled with the scalar values at each step
+            let steps = 5;
+            let (lo, hi) = self.scalar_range;
+            for i in 0..steps {
+                let x = lo + (hi - lo) * i as f32 / (steps - 1) as f32;
+                let c = scene_object::colormap(x, self.scalar_range);
+                ui.text_colored((c[0], c[1], c[2], 1.0), im_str!("{:.4}", x));
+                if i + 1 < steps {
+                    ui.same_line(0.0);
+                }
+            }
+        }
+        let mut clamp_u = self.surf.is_clamped_u();
+        let mut clamp_v = self.surf.is_clamped_v();
+        let clamp_u_changed = ui.checkbox(im_str!("Clamp U"), &mut clamp_u);
+        let clamp_v_changed = ui.checkbox(im_str!("Clamp V"), &mut clamp_v);
+        if clamp_u_changed {
+            self.surf.set_clamped_u(clamp_u);
+            self.knot_error = None;
+        }
+        if clamp_v_changed {
+            self.surf.set_clamped_v(clamp_v);
+            self.knot_error = None;
+        }
+        let mut knots_u = self.surf.knots_u.clone();
+        let mut knots_v = self.surf.knots_v.clone();
+        let mut knots_u_changed = false;
+        let mut knots_v_changed = false;
+        ui.tree_node(im_str!("U Knots")).build(|| knots_u_changed = edit_knots(ui, "u", &mut knots_u));
+        ui.tree_node(im_str!("V Knots")).build(|| knots_v_changed = edit_knots(ui, "v", &mut knots_v));
+        if knots_u_changed || knots_v_changed {
+            let result = if knots_u_changed { self.surf.set_knots_u(knots_u) } else { self.surf.set_knots_v(knots_v) };
+            self.knot_error = result.err();
+        }
+        if let Some(ref e) = self.knot_error {
+            ui.text_colored((1.0, 0.4, 0.4, 1.0), im_str!("{}", e));
+        }
+        ui.tree_node(im_str!("Edit Control Mesh")).build(|| {
+            if ui.checkbox(im_str!("Edit Columns"), &mut self.edit_columns) {
+                self.selected_line = None;
+            }
+            let mut line = self.selected_line.map_or(-1, |l| l as i32);
+            let label = if self.edit_columns { im_str!("Column") } else { im_str!("Row") };
+            if ui.slider_int(label, &mut line, -1, self.num_mesh_lines() as i32 - 1).build() {
+                self.selected_line = if line >= 0 { Some(line as usize) } else { None };
+                self.build_selected_line_vbo();
+            }
+            if self.selected_line.is_some() {
+                ui.slider_float(im_str!("Nudge Step"), &mut self.nudge_step, 0.01, 1.0).build();
+                let step = self.nudge_step;
+                let axes = [(im_str!("-X"), Point::new(-step, 0.0, 0.0)), (im_str!("+X"), Point::new(step, 0.0, 0.0)),
+                            (im_str!("-Y"), Point::new(0.0, -step, 0.0)), (im_str!("+Y"), Point::new(0.0, step, 0.0)),
+                            (im_str!("-Z"), Point::new(0.0, 0.0, -step)), (im_str!("+Z"), Point::new(0.0, 0.0, step))];
+                for (i, &(ref label, delta)) in axes.iter().enumerate() {
+                    if i % 2 == 1 {
+                        ui.same_line(0.0);
+                    }
+                    if ui.small_button(label.clone()) {
+                        self.nudge_selected_line(delta);
+                    }
+                }
+            }
+        });
+        if spacing_changed || step_changed || max_changed || clamp_u_changed || clamp_v_changed
+            || knots_u_changed || knots_v_changed || color_by_changed
+        {
+            self.rebuild_geometry();
+        }
         ui.color_edit3(im_str!("Curve Color"), &mut self.curve_color).build();
+        ui.slider_float(im_str!("Opacity"), &mut self.alpha, 0.0, 1.0).build();
         ui.color_edit3(im_str!("Greville Color"), &mut self.greville_color).build();
         ui.color_edit3(im_str!("Knot Color"), &mut self.knot_color).build();
         ui.color_edit3(im_str!("Control Color"), &mut self.control_color).build();
+        if self.draw_tangents_u || self.draw_tangents_v {
+            ui.color_edit3(im_str!("Tangent Color"), &mut self.tangent_color).build();
+        }
+        if !self.markers.is_empty() {
+            ui.text(im_str!("Markers:"));
+            for m in &self.markers[..] {
+                let p = self.surf.point(m.0, m.1);
+                ui.bullet_text(im_str!("({:.3}, {:.3}) at ({:.3}, {:.3}, {:.3})",
+                                       m.0, m.1, p.pos[0], p.pos[1], p.pos[2]));
+            }
+            if ui.small_button(im_str!("Clear Markers")) {
+                self.markers.clear();
+            }
+        }
+        ui.checkbox(im_str!("Extract Along U"), &mut self.extract_along_u);
+        // The isoline along u is at a fixed v and vice versa
+        let domain = if self.extract_along_u { self.surf.knot_domain_v() } else { self.surf.knot_domain_u() };
+        self.extract_param = point::clamp(self.extract_param, domain.0, domain.1);
+        let label = if self.extract_along_u { im_str!("Isoline v") } else { im_str!("Isoline u") };
+        ui.slider_float(label, &mut self.extract_param, domain.0, domain.1).build();
+        if ui.small_button(im_str!("Extract Isoline")) {
+            self.extracted_curve =
+                if self.extract_along_u {
+                    Some(self.surf.isoline_u(self.extract_param))
+                } else {
+                    Some(self.surf.isoline_v(self.extract_param))
+                };
+        }
+        ui.slider_int(im_str!("Export Resolution"), &mut self.export_resolution, 2, 256).build();
+        ui.combo(im_str!("Export Format"), &mut self.export_format,
+                 &[im_str!("OBJ"), im_str!("glTF")], 2);
+        // glTF meshes are always triangulated
+        if self.export_format == 0 {
+            ui.checkbox(im_str!("Export Quad Faces"), &mut self.export_quads);
+        }
+        if ui.small_button(im_str!("Export Mesh")) {
+            let file = if self.export_format == 0 { "surface.obj" } else { "surface.gltf" };
+            let res = self.export_resolution as usize;
+            match mesh_export::export_surface(&self.surf, file, res, self.export_quads) {
+                Ok(_) => log_message(format!("Exported surface to {}", file)),
+                Err(e) => log_message(format!("Error: failed to export {}: {}", file, e)),
+            }
+        }
+    }
+    fn bounds(&self) -> Option<(Point, Point)> {
+        Some(self.bounds)
+    }
+    fn translate(&mut self, offset: Point) {
+        for r in self.surf.control_mesh.iter_mut() {
+            for p in r.iter_mut() {
+                *p = *p + offset;
+            }
+        }
+        self.rebuild_geometry();
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+    fn source(&self) -> Option<(&str, usize)> {
+        self.source.as_ref().map(|s| (&s.0[..], s.1))
+    }
+    fn set_source(&mut self, path: String, index: usize) {
+        self.source = Some((path, index));
+    }
+    fn set_geometry(&mut self, mut geometry: Geometry) -> bool {
+        if self.flip_handedness {
+            geometry.flip_handedness();
+        }
+        match geometry {
+            Geometry::Surface(surf) => {
+                self.set_surface(surf);
+                true
+            },
+            _ => false,
+        }
+    }
+    fn color(&self) -> [f32; 3] {
+        self.curve_color
+    }
+    fn set_color(&mut self, color: [f32; 3]) {
+        self.curve_color = color;
+    }
+    fn alpha(&self) -> f32 {
+        self.alpha
+    }
+    fn pin_marker(&mut self, origin: &Point, dir: &Point) -> bool {
+        match self.surf.intersect_ray(origin, dir, 64) {
+            Some(m) => {
+                self.markers.push(m);
+                self.build_markers_vbo();
+                true
+            },
+            None => false,
+        }
+    }
+    fn pick_mesh_line(&mut self, origin: &Point, dir: &Point) -> bool {
+        let diagonal = (self.bounds.1 - self.bounds.0).length();
+        match self.surf.pick_control_point(origin, dir, f32::max(0.02 * diagonal, 0.01)) {
+            Some((i, j)) => {
+                self.selected_line = Some(if self.edit_columns { j } else { i });
+                self.build_selected_line_vbo();
+                true
+            },
+            None => false,
+        }
+    }
+    fn take_extracted_curve(&mut self) -> Option<BSpline<Point>> {
+        self.extracted_curve.take()
     }
 }
 
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn isoline_stride_caps_lines() {
+        // Under the cap every isoline is drawn
+        assert_eq!(isoline_stride(&[4, 4, 3, 3, 11, 11], 100), 1);
+        assert_eq!(isoline_stride(&[4, 4, 3, 3, 11, 11], 36), 1);
+        // Over the cap, the smallest stride taking at most the cap's worth of lines
+        assert_eq!(isoline_stride(&[4, 4, 3, 3, 11, 11], 35), 2);
+        assert_eq!(isoline_stride(&[100, 100, 98, 98, 201, 201], 50), 17);
+        // Each set still gets one line when the cap is smaller than the number of sets
+        assert_eq!(isoline_stride(&[10, 10, 10, 10, 10, 10], 0), 10);
+        assert_eq!(isoline_stride(&[0, 0, 0, 0, 0, 0], 0), 1);
+    }
+}
@@ -3,69 +3,73 @@
 
 use std::f32;
 
-use glium::{Surface, VertexBuffer, Program, DrawParameters};
+use glium::{Surface, VertexBuffer, Program, DrawParameters, Frame};
 use glium::backend::Facade;
 use glium::index::{NoIndices, PrimitiveType};
 use imgui::Ui;
-use rulinalg::matrix::Matrix;
-use rulinalg::vector::Vector;
 
-use bspline::BSpline;
+use bspline::{self, BSpline};
 use bspline_surf::BSplineSurf;
 use bspline_basis::BSplineBasis;
 use display_surf::DisplaySurf;
-use point::Point;
+use point::{self, Point};
+use scene_object::{SceneObject, Geometry};
 
 pub struct DisplaySurfInterpolation<'a, F: 'a + Facade> {
     display: &'a F,
     curves: Vec<BSpline<Point>>,
-    surf: DisplaySurf,
+    surf: DisplaySurf<'a, F>,
     interpolation_degree: usize,
+    // Max distance between the input curves and the surface isolines interpolating them
+    max_error: f32,
     // The input curves
     input_curves_vbo: Vec<VertexBuffer<Point>>,
     // The input control points
     input_points_vbo: VertexBuffer<Point>,
     draw_input_curves: bool,
     draw_input_points: bool,
+    // The input control point toggle saved when the surface's presentation mode hid them
+    saved_input_points: Option<bool>,
+    // If the input curves are mirrored from the coordinates they were loaded in
+    flip_handedness: bool,
     curve_color: [f32; 3],
 }
 
 impl<'a, F: 'a + Facade> DisplaySurfInterpolation<'a, F> {
     pub fn new(curves: Vec<BSpline<Point>>, display: &'a F) -> DisplaySurfInterpolation<'a, F> {
-        let mut control_points = Vec::new();
-        let mut input_curves_vbo = Vec::with_capacity(curves.len());
-        let step_size = 0.01;
-        for c in curves.iter() {
-            let t_range = c.knot_domain();
-            let steps = ((t_range.1 - t_range.0) / step_size) as usize;
-            let mut points = Vec::with_capacity(steps);
-            // Just draw the first one for now
-            for s in 0..steps + 1 {
-                let t = step_size * s as f32 + t_range.0;
-                points.push(c.point(t));
-            }
-            input_curves_vbo.push(VertexBuffer::new(display, &points[..]).unwrap());
-
-            for pt in &c.control_points[..] {
-                control_points.push(*pt);
-            }
-        }
-        let control_points_vbo = VertexBuffer::new(display, &control_points[..]).unwrap();
-        let surf = compute_nodal_interpolation(&curves[..], 1);
+        let (input_curves_vbo, control_points_vbo) = build_input_vbos(&curves[..], display);
+        let surf = BSplineSurf::interpolate_curves(&curves[..], 1);
+        let max_error = interpolation_error(&curves[..], &surf, 1);
 
         DisplaySurfInterpolation { display: display,
                       curves: curves,
                       surf: DisplaySurf::new(surf, display),
                       interpolation_degree: 1,
+                      max_error: max_error,
                       input_curves_vbo: input_curves_vbo,
                       input_points_vbo: control_points_vbo,
                       draw_input_curves: true,
                       draw_input_points: true,
+                      saved_input_points: None,
+                      flip_handedness: false,
                       curve_color: [0.1, 0.8, 0.1],
         }
     }
-    pub fn render<S: Surface>(&self, target: &mut S, program: &Program, draw_params: &DrawParameters,
-                  proj_view: &[[f32; 4]; 4], selected: bool, attenuation: f32) {
+    /// Recompute the interpolated surface and rebuild all the vertex buffers, this must
+    /// be called whenever the input curves or interpolation degree are changed.
+    pub fn rebuild_geometry(&mut self) {
+        let (input_curves_vbo, input_points_vbo) = build_input_vbos(&self.curves[..], self.display);
+        self.input_curves_vbo = input_curves_vbo;
+        self.input_points_vbo = input_points_vbo;
+        let surf = BSplineSurf::interpolate_curves(&self.curves[..], self.interpolation_degree);
+        self.max_error = interpolation_error(&self.curves[..], &surf, self.interpolation_degree);
+        self.surf.set_surface(surf);
+    }
+}
+
+impl<'a, F: 'a + Facade> SceneObject for DisplaySurfInterpolation<'a, F> {
+    fn render(&self, target: &mut Frame, program: &Program, color_program: &Program,
+              draw_params: &DrawParameters, proj_view: &[[f32; 4]; 4], selected: bool, attenuation: f32) {
         let curve_color =
             if selected {
                 self.curve_color
@@ -88,9 +92,9 @@ impl<'a, F: 'a + Facade> DisplaySurfInterpolation<'a, F> {
             target.draw(&self.input_points_vbo, &NoIndices(PrimitiveType::Points),
                         &program, &uniforms, &draw_params).unwrap();
         }
-        self.surf.render(target, program, draw_params, proj_view, selected, attenuation);
+        self.surf.render(target, program, color_program, draw_params, proj_view, selected, attenuation);
     }
-    pub fn draw_ui(&mut self, ui: &Ui) {
+    fn draw_ui(&mut self, ui: &Ui) {
         ui.text(im_str!("3D Surface Interpolation"));
         ui.checkbox(im_str!("Draw Input Curves"), &mut self.draw_input_curves);
         ui.checkbox(im_str!("Draw Input Control Points"), &mut self.draw_input_points);
@@ -99,62 +103,161 @@ impl<'a, F: 'a + Facade> DisplaySurfInterpolation<'a, F> {
         let mut current_degree = self.interpolation_degree as i32;
         if ui.slider_int(im_str!("Interp. Degree"), &mut current_degree, 1, max_degree).build() {
             self.interpolation_degree = current_degree as usize;
-            self.surf = DisplaySurf::new(compute_nodal_interpolation(&self.curves[..], self.interpolation_degree),
-                                         self.display);
+            self.rebuild_geometry();
         }
+        ui.text(im_str!("Max Interpolation Error: {:.6}", self.max_error));
         self.surf.draw_ui(ui);
+        // Flipping the surface flips the input curves it interpolates
+        if self.surf.flipped_handedness() != self.flip_handedness {
+            self.flip_handedness = !self.flip_handedness;
+            for c in self.curves.iter_mut() {
+                c.flip_handedness();
+                c.reverse();
+            }
+            self.rebuild_geometry();
+        }
+        // The surface's presentation mode hides the input control points as well
+        if self.surf.presentation_mode() && self.saved_input_points.is_none() {
+            self.saved_input_points = Some(self.draw_input_points);
+            self.draw_input_points = false;
+        } else if !self.surf.presentation_mode() {
+            if let Some(saved) = self.saved_input_points.take() {
+                self.draw_input_points = saved;
+            }
+        }
+    }
+    fn translate(&mut self, offset: Point) {
+        for c in self.curves.iter_mut() {
+            for p in c.control_points.iter_mut() {
+                *p = *p + offset;
+            }
+        }
+        self.rebuild_geometry();
+    }
+    fn name(&self) -> &str {
+        self.surf.name()
+    }
+    fn set_name(&mut self, name: String) {
+        self.surf.set_name(name);
+    }
+    fn source(&self) -> Option<(&str, usize)> {
+        self.surf.source()
+    }
+    fn set_source(&mut self, path: String, index: usize) {
+        self.surf.set_source(path, index);
+    }
+    fn set_geometry(&mut self, mut geometry: Geometry) -> bool {
+        if self.flip_handedness {
+            geometry.flip_handedness();
+        }
+        match geometry {
+            // There's no surface to interpolate without any curves, so keep the current one
+            Geometry::Interpolation(ref curves) if curves.is_empty() => false,
+            Geometry::Interpolation(curves) => {
+                // Fewer input curves may not support the current interpolation degree
+                self.interpolation_degree = usize::max(usize::min(self.interpolation_degree, curves.len() - 1), 1);
+                self.curves = curves;
+                self.rebuild_geometry();
+                true
+            },
+            _ => false,
+        }
+    }
+    fn color(&self) -> [f32; 3] {
+        self.surf.color()
+    }
+    fn set_color(&mut self, color: [f32; 3]) {
+        self.surf.set_color(color);
+    }
+    fn alpha(&self) -> f32 {
+        self.surf.alpha()
+    }
+    fn pin_marker(&mut self, origin: &Point, dir: &Point) -> bool {
+        self.surf.pin_marker(origin, dir)
+    }
+    fn pick_mesh_line(&mut self, origin: &Point, dir: &Point) -> bool {
+        self.surf.pick_mesh_line(origin, dir)
+    }
+    fn take_extracted_curve(&mut self) -> Option<BSpline<Point>> {
+        self.surf.take_extracted_curve()
+    }
+    fn bounds(&self) -> Option<(Point, Point)> {
+        // The interpolated surface may not stay within the input control points
+        let surf_bounds = self.surf.bounds().unwrap();
+        let points: Vec<_> = self.curves.iter().flat_map(|c| c.control_points())
+            .chain([surf_bounds.0, surf_bounds.1].iter()).cloned().collect();
+        Some(point::bounding_box(&points[..]))
     }
 }
 
-fn compute_nodal_interpolation(curves: &[BSpline<Point>], degree: usize) -> BSplineSurf<Point> {
+/// Build the vertex buffers for the input curves and their control points
+fn build_input_vbos<F: Facade>(curves: &[BSpline<Point>], display: &F)
+    -> (Vec<VertexBuffer<Point>>, VertexBuffer<Point>)
+{
     let mut control_points = Vec::new();
+    let mut input_curves_vbo = Vec::with_capacity(curves.len());
+    let step_size = 0.01;
     for c in curves.iter() {
+        let points: Vec<_> = bspline::sample_domain(c.knot_domain(), step_size).iter()
+            .map(|t| c.point(*t)).collect();
+        input_curves_vbo.push(VertexBuffer::new(display, &points[..]).unwrap());
+
         for pt in &c.control_points[..] {
             control_points.push(*pt);
         }
     }
-    // Setup the bases for u and v so we can build the matrices
-    let basis_u = BSplineBasis::new(curves[0].degree(), curves[0].knots().map(|x| *x).collect());
-    //let abscissa_u = basis_u.greville_abscissa();
-    // Is the result right for cubic?
-    let basis_v = BSplineBasis::clamped_uniform(degree, curves.len());
-    let abscissa_v = basis_v.greville_abscissa();
+    (input_curves_vbo, VertexBuffer::new(display, &control_points[..]).unwrap())
+}
+
+/// Compute the max distance between the input curves and the isolines of the surface
+/// interpolating them with `degree`. Each curve is interpolated by the isoline at the
+/// Greville abscissa used for it, so if the interpolation is exact this is ~0.
+fn interpolation_error(curves: &[BSpline<Point>], surf: &BSplineSurf<Point>, degree: usize) -> f32 {
+    let abscissa = BSplineBasis::clamped_uniform(degree, curves.len()).greville_abscissa();
+    let samples = 100;
+    let mut max_error: f32 = 0.0;
+    for (c, u) in curves.iter().zip(abscissa.iter()) {
+        let isoline = surf.isoline_v(*u);
+        let domain = c.knot_domain();
+        for s in 0..samples + 1 {
+            let t = domain.0 + (domain.1 - domain.0) * s as f32 / samples as f32;
+            max_error = f32::max(max_error, (isoline.point(t) - c.point(t)).length());
+        }
+    }
+    max_error
+}
 
-    // This is actually the N matrix in the 12/5 notes.
-    let f = Matrix::from_fn(curves.len(), abscissa_v.len(),
-                            |i, j| basis_v.eval(abscissa_v[j], i));
-    let x_pos: Vec<_> = control_points.iter().map(|x| x.pos[0]).collect();
-    let y_pos: Vec<_> = control_points.iter().map(|x| x.pos[1]).collect();
-    let z_pos: Vec<_> = control_points.iter().map(|x| x.pos[2]).collect();
-    // Are these dimensions right?
-    let r_mats = vec![Matrix::new(curves.len(), curves[0].control_points.len(), x_pos),
-                 Matrix::new(curves.len(), curves[0].control_points.len(), y_pos),
-                 Matrix::new(curves.len(), curves[0].control_points.len(), z_pos)];
-    let mut result_mats = Vec::with_capacity(r_mats.len());
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    // Solve each column
-    let mut x = 0;
-    for r in &r_mats[..] {
-        let mut res_mat = Matrix::zeros(curves.len(), curves[0].control_points.len());
-        for j in 0..curves[0].control_points.len() {
-            let rhs = Vector::new((0..curves.len()).map(|i| r[[i, j]]).collect::<Vec<f32>>());
-            let result = f.clone().solve(rhs).expect("System could not be solved!?");
-            for i in 0..curves.len() {
-                res_mat[[i, j]] = result[i];
+    #[test]
+    fn interpolation_reproduces_surface() {
+        // A biquadratic by cubic surface whose isolines at the Greville abscissa of the
+        // interpolation basis are the input curves, interpolating them gives back the surface
+        let basis = BSplineBasis::clamped_uniform(2, 4);
+        let mesh = (0..4).map(|i| (0..5).map(|j| Point::new(j as f32, ((i * j) % 3) as f32 * 0.5, i as f32))
+                                         .collect()).collect();
+        let knots_v = vec![0.0, 0.0, 0.0, 0.0, 0.4, 1.0, 1.0, 1.0, 1.0];
+        let original = BSplineSurf::new((2, 3), (basis.knots.clone(), knots_v), mesh);
+        let curves: Vec<_> = basis.greville_abscissa().iter().map(|u| original.isoline_v(*u)).collect();
+
+        let surf = BSplineSurf::interpolate_curves(&curves[..], 2);
+        assert!(interpolation_error(&curves[..], &surf, 2) < 1e-4);
+        let (domain_u, domain_v) = (original.knot_domain_u(), original.knot_domain_v());
+        for i in 0..11 {
+            for j in 0..11 {
+                let u = domain_u.0 + (domain_u.1 - domain_u.0) * i as f32 / 10.0;
+                let v = domain_v.0 + (domain_v.1 - domain_v.0) * j as f32 / 10.0;
+                let (p, expected) = (surf.point(u, v), original.point(u, v));
+                assert!((p - expected).length() < 1e-4, "{:?} != {:?} at ({}, {})", p, expected, u, v);
             }
         }
-        result_mats.push(res_mat);
-        x = x + 1;
-    }
-    let mut surf_mesh = Vec::new();
-    for i in 0..curves.len() {
-        let mut mesh_row = Vec::new();
-        for j in 0..curves[0].control_points.len() {
-            let p = Point::new(result_mats[0][[i, j]], result_mats[1][[i, j]], result_mats[2][[i, j]]);
-            mesh_row.push(p);
-        }
-        surf_mesh.push(mesh_row);
+
+        // Lower degree interpolations still pass through the curves, though not the surface between them
+        let linear = BSplineSurf::interpolate_curves(&curves[..], 1);
+        assert!(interpolation_error(&curves[..], &linear, 1) < 1e-4);
+        // But measuring a surface against the wrong abscissa shows a large error
+        assert!(interpolation_error(&curves[..], &linear, 2) > 1e-2);
     }
-    BSplineSurf::new((basis_v.degree(), basis_u.degree()), (basis_v.knots, basis_u.knots), surf_mesh)
 }
-
@@ -0,0 +1,93 @@
+//! A translation gizmo with a handle along each axis which can be
+//! dragged to move the selected object in the 3D view.
+
+use std::f32;
+
+use glium::{Surface, VertexBuffer, Program, DrawParameters};
+use glium::backend::Facade;
+use glium::index::{NoIndices, PrimitiveType};
+use cgmath::{Matrix4, Vector3};
+
+use point::Point;
+
+pub struct TranslateGizmo {
+    axes_vbo: VertexBuffer<Point>,
+    axis_colors: [[f32; 3]; 3],
+}
+
+impl TranslateGizmo {
+    pub fn new<F: Facade>(display: &F) -> TranslateGizmo {
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let axes = [origin, Point::new(1.0, 0.0, 0.0),
+                    origin, Point::new(0.0, 1.0, 0.0),
+                    origin, Point::new(0.0, 0.0, 1.0)];
+        TranslateGizmo { axes_vbo: VertexBuffer::immutable(display, &axes[..]).unwrap(),
+                         axis_colors: [[0.9, 0.1, 0.1], [0.1, 0.9, 0.1], [0.1, 0.1, 0.9]],
+        }
+    }
+    /// Render the gizmo's axis handles starting at `center` with each handle `scale` long
+    pub fn render<S: Surface>(&self, target: &mut S, program: &Program, draw_params: &DrawParameters,
+                              proj_view: &Matrix4<f32>, center: &Point, scale: f32) {
+        let model = Matrix4::from_translation(Vector3::new(center.pos[0], center.pos[1], center.pos[2]))
+            * Matrix4::from_scale(scale);
+        let proj_view: [[f32; 4]; 4] = (proj_view * model).into();
+        for (i, c) in self.axis_colors.iter().enumerate() {
+            let uniforms = uniform! {
+                proj_view: proj_view,
+                pcolor: *c,
+            };
+            let axis = self.axes_vbo.slice(2 * i..2 * i + 2).unwrap();
+            target.draw(axis, &NoIndices(PrimitiveType::LinesList),
+                        &program, &uniforms, &draw_params).unwrap();
+        }
+    }
+    /// Find the axis handle of the gizmo at `center` hit by the ray, if any. Returns the
+    /// picked axis and the position along it where it was picked.
+    pub fn pick(&self, ray_origin: &Point, ray_dir: &Point, center: &Point, scale: f32)
+        -> Option<(usize, f32)>
+    {
+        let threshold = 0.08 * scale;
+        let mut picked = None;
+        let mut nearest = f32::MAX;
+        for i in 0..3 {
+            let s = match closest_on_axis(ray_origin, ray_dir, center, i) {
+                Some(s) => s,
+                None => continue,
+            };
+            if s < 0.0 || s > scale {
+                continue;
+            }
+            // Distance from the closest point on the handle to the ray
+            let p = *center + axis(i) * s;
+            let t = (p - *ray_origin).dot(ray_dir);
+            let dist = (*ray_origin + *ray_dir * t - p).length();
+            if dist < threshold && dist < nearest {
+                nearest = dist;
+                picked = Some((i, s));
+            }
+        }
+        picked
+    }
+}
+
+/// Get the unit vector along the axis
+pub fn axis(i: usize) -> Point {
+    match i {
+        0 => Point::new(1.0, 0.0, 0.0),
+        1 => Point::new(0.0, 1.0, 0.0),
+        _ => Point::new(0.0, 0.0, 1.0),
+    }
+}
+
+/// Find the position along the axis line through `center` closest to the ray, `ray_dir`
+/// should be normalized. Returns None if the ray is parallel to the axis.
+pub fn closest_on_axis(ray_origin: &Point, ray_dir: &Point, center: &Point, i: usize) -> Option<f32> {
+    let a = axis(i);
+    let w = *center - *ray_origin;
+    let b = a.dot(ray_dir);
+    let denom = 1.0 - b * b;
+    if denom.abs() < 1e-6 {
+        return None;
+    }
+    Some((b * w.dot(ray_dir) - w.dot(&a)) / denom)
+}
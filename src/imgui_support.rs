@@ -1,3 +1,6 @@
+use std::cmp;
+use std::os::raw::c_char;
+use std::ptr;
 use std::time::Instant;
 
 use glium;
@@ -20,6 +23,26 @@ pub fn push_id_int(id: i32) {
 pub fn pop_id() {
     unsafe { imgui_sys::igPopId(); }
 }
+pub fn set_scroll_here() {
+    unsafe { imgui_sys::igSetScrollHere(1.0); }
+}
+/// Edit `text` in a text input field which holds up to `max_len` bytes, returns true
+/// if the text was changed.
+pub fn input_text(label: ImStr, text: &mut String, max_len: usize) -> bool {
+    // ImGui edits a nul terminated string in place, so give it a buffer with room to grow
+    let mut buf = text.clone().into_bytes();
+    buf.resize(cmp::max(text.len(), max_len) + 1, 0);
+    let changed = unsafe {
+        imgui_sys::igInputText(label.as_ptr(), buf.as_mut_ptr() as *mut c_char, buf.len(),
+                               imgui_sys::ImGuiInputTextFlags::empty(), None, ptr::null_mut())
+    };
+    if changed {
+        let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+        buf.truncate(len);
+        *text = String::from_utf8_lossy(&buf).into_owned();
+    }
+    changed
+}
 
 /// Manages giving ImGui key presses, mouse motion and so on
 pub struct ImGuiSupport {
@@ -28,6 +51,8 @@ pub struct ImGuiSupport {
     pub mouse_pressed: (bool, bool, bool),
     pub mouse_wheel: f32,
     pub last_frame: Instant,
+    /// Time elapsed in seconds between the last two frames
+    pub delta_s: f32,
 }
 
 impl ImGuiSupport {
@@ -59,6 +84,7 @@ impl ImGuiSupport {
             mouse_pressed: (false, false, false),
             mouse_wheel: 0.0,
             last_frame: Instant::now(),
+            delta_s: 0.0,
         }
     }
     pub fn render_ui(&mut self, display: &glium::backend::glutin_backend::GlutinFacade) -> imgui::Ui {
@@ -66,6 +92,7 @@ impl ImGuiSupport {
         let delta = now - self.last_frame;
         let delta_s = delta.as_secs() as f32 + delta.subsec_nanos() as f32 / 1_000_000_000.0;
         self.last_frame = now;
+        self.delta_s = delta_s;
 
         let window = display.get_window().unwrap();
         let size_pts = window.get_inner_size_points().unwrap();
@@ -0,0 +1,12 @@
+//! The B-spline and Bezier curve and surface math used by the viewer, which can also
+//! be used on its own without any of the rendering or UI.
+
+#[macro_use]
+extern crate glium;
+extern crate rulinalg;
+
+pub mod bezier;
+pub mod bspline;
+pub mod bspline_basis;
+pub mod bspline_surf;
+pub mod point;
@@ -0,0 +1,84 @@
+//! A registry of the functions reading curve and surface files, picked by the file's
+//! extension, so new file formats can be added without changing how files are loaded.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+use scene_object::Geometry;
+
+/// Reads the curves and surfaces in a file, or returns a message describing why it couldn't
+pub type LoadFn = Box<dyn Fn(&Path) -> Result<Vec<Geometry>, String>>;
+
+thread_local!(static LOADERS: RefCell<HashMap<String, LoadFn>> = RefCell::new(HashMap::new()));
+
+/// Get the lowercase extension of `path` used to look up its loader
+fn extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase())
+}
+
+/// Register `f` to read files with `extension`, given without the leading '.'. Registering
+/// an extension again replaces its loader, so the built-in formats can be overridden.
+pub fn register(extension: &str, f: LoadFn) {
+    LOADERS.with(|l| {
+        l.borrow_mut().insert(extension.to_lowercase(), f);
+    });
+}
+
+/// Check if there's a loader registered for the extension of `path`
+pub fn is_registered(path: &Path) -> bool {
+    extension(path).map_or(false, |e| LOADERS.with(|l| l.borrow().contains_key(&e)))
+}
+
+/// Read the file at `path` with the loader registered for its extension, returns None if
+/// there's no loader for it.
+pub fn load(path: &Path) -> Option<Result<Vec<Geometry>, String>> {
+    let ext = match extension(path) {
+        Some(e) => e,
+        None => return None,
+    };
+    LOADERS.with(|l| l.borrow().get(&ext).map(|f| f(path)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+
+    use bspline::BSpline;
+    use point::Point;
+
+    #[test]
+    fn load_registered_extension() {
+        // A fake format with one "x y" point per line, read as a polyline
+        register("xy", Box::new(|path| {
+            let mut text = String::new();
+            File::open(path).and_then(|mut f| f.read_to_string(&mut text)).map_err(|e| e.to_string())?;
+            let points = text.lines().map(|l| {
+                let v: Vec<f32> = l.split_whitespace().map(|x| x.parse().unwrap()).collect();
+                Point::new(v[0], v[1], 0.0)
+            }).collect();
+            Ok(vec![Geometry::Curve(BSpline::new(1, points, vec![]), false)])
+        }));
+        let path = env::temp_dir().join("spline_viewer_loader_test.XY");
+        File::create(&path).unwrap().write_all(b"0 0\n1 2\n3 1\n").unwrap();
+        assert!(is_registered(&path));
+        match load(&path) {
+            Some(Ok(ref g)) if g.len() == 1 => match g[0] {
+                Geometry::Curve(ref c, false) => {
+                    assert_eq!(c.control_points().map(|p| p.pos).collect::<Vec<_>>(),
+                               vec![[0.0, 0.0, 0.0], [1.0, 2.0, 0.0], [3.0, 1.0, 0.0]]);
+                },
+                _ => panic!("expected a 2D curve"),
+            },
+            _ => panic!("expected the file to load through the registered loader"),
+        }
+        fs::remove_file(&path).unwrap();
+
+        assert!(!is_registered(Path::new("curve.unknown")));
+        assert!(load(Path::new("curve.unknown")).is_none());
+        assert!(load(Path::new("no_extension")).is_none());
+    }
+}
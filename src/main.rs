@@ -9,47 +9,114 @@ extern crate docopt;
 extern crate num_traits;
 extern crate rulinalg;
 extern crate serde;
+#[macro_use]
 extern crate serde_json;
-extern crate arcball;
+extern crate spline_viewer;
+extern crate png;
 
 mod imgui_support;
-mod bezier;
-mod bspline;
-mod point;
 mod camera2d;
+mod arcball_camera;
 mod display_curve;
-mod display_curve3d;
 mod polyline;
-mod bspline_surf;
 mod display_surf;
 mod display_surf_interp;
-mod bspline_basis;
+mod scene_object;
+mod gizmo;
+mod message_log;
+mod palette;
+mod mesh_export;
+mod loader;
 
-use std::fs::File;
-use std::io::BufReader;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::f32;
+use std::time::Instant;
 
-use glium::{DisplayBuild, Surface, DrawParameters};
+use glium::{DisplayBuild, Surface, DrawParameters, Program, Rect};
+use glium::program::ProgramCreationInput;
+use glium::draw_parameters::Smooth;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::backend::Facade;
+use glium::backend::glutin_backend::GlutinFacade;
 use glium::glutin::{self, ElementState, Event, VirtualKeyCode, MouseButton};
-use cgmath::{SquareMatrix, Transform, Vector2, Matrix4};
+use cgmath::{SquareMatrix, Transform, Vector2, Matrix3, Matrix4};
 use docopt::Docopt;
 use imgui_glium_renderer::Renderer;
-use arcball::ArcballCamera;
 
+use spline_viewer::{bspline, bspline_basis, bspline_surf, point};
 use imgui_support::ImGuiSupport;
 use bspline::BSpline;
 use bspline_surf::BSplineSurf;
-use point::Point;
+use point::{Point, HomogeneousPoint};
 use camera2d::Camera2d;
+use arcball_camera::{ArcballCamera, AxisView};
 use display_curve::DisplayCurve;
-use display_curve3d::DisplayCurve3D;
 use display_surf::DisplaySurf;
 use display_surf_interp::DisplaySurfInterpolation;
+use scene_object::{SceneObject, Geometry};
+use gizmo::TranslateGizmo;
+use message_log::log_message;
+use palette::Palette;
+
+/// Options for how the curve and surface files are imported, by default files are read as is
+#[derive(Clone, Copy, Default)]
+struct ImportOptions {
+    /// Mirror the geometry to convert it from the other handedness
+    flip_handedness: bool,
+    /// Skip objects with out of order knots instead of sorting their knots
+    reject_unsorted_knots: bool,
+}
+
+/// Describe the out of order knots of the `what` in `file`, if it has any
+fn unsorted_knots_message(knots: &[f32], what: &str, file: &str) -> Option<String> {
+    let unsorted = bspline_basis::unsorted_knots(knots);
+    if unsorted.is_empty() {
+        None
+    } else {
+        Some(format!("{} in {} has knots out of order at indices {:?}", what, file, unsorted))
+    }
+}
+
+/// Check that the knots of the `what` in `file` are in non-decreasing order. Out of order
+/// knots are reported since sorting them could hide a typo in the file. Returns false if
+/// the object should be skipped, otherwise its knots will be sorted.
+fn check_knot_order(knots: &[f32], what: &str, file: &str, options: &ImportOptions) -> bool {
+    match unsorted_knots_message(knots, what, file) {
+        Some(msg) => {
+            if options.reject_unsorted_knots {
+                log_message(format!("Error: {}, skipping it", msg));
+                false
+            } else {
+                log_message(format!("Warning: {}, sorting them", msg));
+                true
+            }
+        },
+        None => true,
+    }
+}
+
+/// Check that the knot vector read from `file` has the right number of knots for the curve.
+/// If not a warning is printed and an empty knot vector is returned, so the curve will
+/// generate a clamped uniform knot vector instead.
+fn validate_knots(knots: Vec<f32>, degree: usize, num_points: usize, file: &str) -> Vec<f32> {
+    if !knots.is_empty() && knots.len() != num_points + degree + 1 {
+        log_message(format!("Warning: {} has {} knots but {} are needed for {} points of degree {}, \
+                             generating a clamped uniform knot vector instead",
+                            file, knots.len(), num_points + degree + 1, num_points, degree));
+        Vec::new()
+    } else {
+        knots
+    }
+}
 
-/// Import a 2D BSpline curve from the file
-fn import_bspline(json: &serde_json::Value) -> BSpline<Point> {
+/// Import a BSpline curve from the file, the z coordinate of the points is optional and
+/// defaults to 0. Any z given for 2D curves is kept so they aren't flattened in the 3D view.
+/// Returns None if the curve is skipped for having out of order knots.
+fn import_bspline(json: &serde_json::Value, file: &str, options: &ImportOptions) -> Option<BSpline<Point>> {
     let degree = json["degree"].as_u64().expect("A curve degree must be specified") as usize;
-    let points = json["points"].as_array().expect("A list of points must be specified").iter()
+    let points: Vec<_> = json["points"].as_array().expect("A list of points must be specified").iter()
         .map(|p| Point::new(p["x"].as_f64().expect("Invalid x coord") as f32,
                             p["y"].as_f64().expect("Invalid y coord") as f32,
                             p["z"].as_f64().unwrap_or(0.0) as f32)).collect();
@@ -57,21 +124,64 @@ fn import_bspline(json: &serde_json::Value) -> BSpline<Point> {
     if let Some(k) = json["knots"].as_array() {
         knots = k.iter().map(|x| x.as_f64().expect("Invalid knot value") as f32).collect();
     }
-    BSpline::new(degree, points, knots)
+    if !check_knot_order(&knots[..], "Curve", file, options) {
+        return None;
+    }
+    let knots = validate_knots(knots, degree, points.len(), file);
+    Some(BSpline::new(degree, points, knots))
 }
 
-/// Import a B-spline surface file
-fn import_surf(json: &serde_json::Value) -> BSplineSurf<Point> {
+/// Export a B-spline curve to the JSON format read by `import_bspline`
+fn export_bspline(curve: &BSpline<Point>, is_3d: bool) -> serde_json::Value {
+    let points: Vec<_> = curve.control_points()
+        .map(|p| json!({"x": p.pos[0], "y": p.pos[1], "z": p.pos[2]})).collect();
+    let knots: Vec<_> = curve.knots().cloned().collect();
+    json!({
+        "type": if is_3d { "bspline3d" } else { "bspline2d" },
+        "degree": curve.degree(),
+        "points": points,
+        "knots": knots,
+    })
+}
+
+/// Write the curve to `file` in the JSON format read by `import_bspline`
+fn write_curve_file(curve: &BSpline<Point>, is_3d: bool, file: &str) -> Result<(), String> {
+    let f = File::create(file).map_err(|e| format!("failed to create {}: {}", file, e))?;
+    // Flush explicitly since errors flushing the buffer when it's dropped are ignored
+    let mut writer = BufWriter::new(f);
+    serde_json::to_writer_pretty(&mut writer, &export_bspline(curve, is_3d))
+        .map_err(|e| format!("failed to write {}: {}", file, e))?;
+    writer.flush().map_err(|e| format!("failed to write {}: {}", file, e))
+}
+
+/// Write the curve to `file`, logging whether it was exported
+fn export_curve_file(curve: &BSpline<Point>, is_3d: bool, file: &str) {
+    match write_curve_file(curve, is_3d, file) {
+        Ok(_) => log_message(format!("Exported curve to {}", file)),
+        Err(e) => log_message(format!("Error: {}", e)),
+    }
+}
+
+/// Import a B-spline surface file, returns None if the surface is skipped for having out
+/// of order knots.
+fn import_surf(json: &serde_json::Value, file: &str, options: &ImportOptions) -> Option<BSplineSurf<Point>> {
     let u_data = json["u"].as_object().expect("Surface u component is required");
     let v_data = json["v"].as_object().expect("Surface v component is required");
 
     let degree_u = u_data["degree"].as_u64().expect("Surface u degree is required") as usize;
     let degree_v = v_data["degree"].as_u64().expect("Surface v degree is required") as usize;
 
-    let knots_u = u_data["knots"].as_array().expect("Surface u knots are required").iter()
+    let mut knots_u: Vec<f32> = u_data["knots"].as_array().expect("Surface u knots are required").iter()
         .map(|x| x.as_f64().expect("Invalid knot value") as f32).collect();
-    let knots_v = v_data["knots"].as_array().expect("Surface v knots are required").iter()
+    let mut knots_v: Vec<f32> = v_data["knots"].as_array().expect("Surface v knots are required").iter()
         .map(|x| x.as_f64().expect("Invalid knot value") as f32).collect();
+    if !check_knot_order(&knots_u[..], "Surface u", file, options)
+        || !check_knot_order(&knots_v[..], "Surface v", file, options) {
+        return None;
+    }
+    // Unlike the curves the surface doesn't sort its knots
+    knots_u.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    knots_v.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
     let mut mesh = Vec::new();
     for r in json["mesh"].as_array().expect("Surface control mesh is required") {
@@ -81,100 +191,605 @@ fn import_surf(json: &serde_json::Value) -> BSplineSurf<Point> {
                                 p["z"].as_f64().expect("Invalid z coord") as f32)).collect();
         mesh.push(points);
     }
-    BSplineSurf::new((degree_u, degree_v), (knots_u, knots_v), mesh)
+    Some(BSplineSurf::new((degree_u, degree_v), (knots_u, knots_v), mesh))
 }
 
 /// Import a B-spline nodal interpolation data file
 /// Note: for the assignment we only did interpolation on one axis, so it assumes
-/// the passed control points are the curve along v's control points
-fn import_surf_interpolation(json: &serde_json::Value) -> Vec<BSpline<Point>> {
+/// the passed control points are the curve along v's control points. Returns None if the
+/// curves are skipped for having out of order knots.
+fn import_surf_interpolation(json: &serde_json::Value, file: &str, options: &ImportOptions)
+    -> Option<Vec<BSpline<Point>>>
+{
     let u_data = json["u"].as_object().expect("Surface u component is required");
     let degree_u = u_data["degree"].as_u64().expect("Surface u degree is required") as usize;
     let knots_u: Vec<f32> = u_data["knots"].as_array().expect("Surface u knots are required").iter()
         .map(|x| x.as_f64().expect("Invalid knot value") as f32).collect();
+    if !check_knot_order(&knots_u[..], "Surface interpolation u", file, options) {
+        return None;
+    }
 
     let mut splines = Vec::new();
     for r in json["mesh"].as_array().expect("Surface control mesh is required") {
-        let points = r.as_array().expect("A list of points must be specified").iter()
+        let points: Vec<_> = r.as_array().expect("A list of points must be specified").iter()
             .map(|p| Point::new(p["x"].as_f64().expect("Invalid x coord") as f32,
                                 p["y"].as_f64().expect("Invalid y coord") as f32,
                                 p["z"].as_f64().expect("Invalid z coord") as f32)).collect();
-        splines.push(BSpline::new(degree_u, points, knots_u.clone()));
+        let knots = validate_knots(knots_u.clone(), degree_u, points.len(), file);
+        splines.push(BSpline::new(degree_u, points, knots));
     }
-    splines
+    Some(splines)
 }
 
-const USAGE: &'static str = "
-Usage:
-    spline-viewer [<file>...]
-    spline-viewer (-h | --help)
+/// Check if the surface interpolation input has an optional `"weights"` list for each row of
+/// its mesh with any weight other than 1, making its curves rational.
+fn has_rational_weights(json: &serde_json::Value) -> bool {
+    json["weights"].as_array().map_or(false, |rows| {
+        rows.iter().filter_map(|r| r.as_array()).flat_map(|r| r.iter()).any(|w| w.as_f64() != Some(1.0))
+    })
+}
 
-Options:
-    -h, --help      Show this message.
-";
+/// The resolution of the polynomial surface displayed for a rational interpolated surface
+const RATIONAL_DISPLAY_RES: usize = 32;
 
-fn main() {
-    let args = Docopt::new(USAGE).and_then(|d| d.parse()).unwrap_or_else(|e| e.exit());
+/// Import a B-spline nodal interpolation data file with a `"weights"` list for each row of its
+/// mesh. The weighted curves are interpolated in homogeneous coordinates, and since only
+/// polynomial surfaces can be displayed the rational surface is approximated by one. Returns
+/// None if the curves are skipped or the weights don't match the mesh.
+fn import_rational_surf_interpolation(json: &serde_json::Value, file: &str, options: &ImportOptions)
+    -> Option<BSplineSurf<Point>>
+{
+    let curves = import_surf_interpolation(json, file, options)?;
+    let weights: Vec<Vec<f32>> = json["weights"].as_array().expect("Weights must be a list of rows").iter()
+        .map(|r| r.as_array().expect("A list of weights must be specified").iter()
+             .map(|w| w.as_f64().expect("Invalid weight") as f32).collect())
+        .collect();
+    if weights.len() != curves.len()
+        || weights.iter().zip(curves.iter()).any(|(w, c)| w.len() != c.control_points.len())
+    {
+        log_message(format!("Error: {} doesn't have a weight for each point of its mesh", file));
+        return None;
+    }
+    let rational_curves: Vec<_> = curves.iter().zip(weights.iter()).map(|(c, w)| {
+        let points = c.control_points().zip(w.iter()).map(|(p, w)| HomogeneousPoint::new(*p, *w)).collect();
+        BSpline::new(c.degree(), points, c.knots().cloned().collect())
+    }).collect();
+    // Linear interpolation across u, the default for unweighted interpolation files
+    let surf = BSplineSurf::interpolate_rational_curves(&rational_curves[..], 1);
+    Some(surf.polynomial_approximation(RATIONAL_DISPLAY_RES))
+}
+
+/// Import the curve or surface described by the JSON file, returns None if the file type
+/// is not recognized or the object was skipped.
+fn import_geometry(json: &serde_json::Value, file: &str, options: &ImportOptions) -> Option<Geometry> {
+    let ty = json["type"].as_str().expect("A curve type must be specified");
+    if ty == "bspline2d" {
+        import_bspline(json, file, options).map(|c| Geometry::Curve(c, false))
+    } else if ty == "bspline3d" {
+        import_bspline(json, file, options).map(|c| Geometry::Curve(c, true))
+    } else if ty == "surface" {
+        import_surf(json, file, options).map(Geometry::Surface)
+    } else if ty == "interpolation_u" && has_rational_weights(json) {
+        import_rational_surf_interpolation(json, file, options).map(Geometry::Surface)
+    } else if ty == "interpolation_u" {
+        import_surf_interpolation(json, file, options).map(Geometry::Interpolation)
+    } else {
+        log_message(format!("Error: {} has unrecognized file type header {}", file, ty));
+        None
+    }
+}
+
+/// Import all the curves and surfaces in the JSON file. Scene files list the objects in a
+/// top-level `"objects"` array, otherwise the file is a single object with a `"type"`.
+fn import_scene(json: &serde_json::Value, file: &str, options: &ImportOptions) -> Vec<Geometry> {
+    if let Some(objs) = json["objects"].as_array() {
+        objs.iter().filter_map(|o| import_geometry(o, file, options)).collect()
+    } else {
+        import_geometry(json, file, options).into_iter().collect()
+    }
+}
+
+/// Make the scene object to display the curve or surface
+fn make_scene_object<'a, F: 'a + Facade>(geometry: Geometry, display: &'a F) -> Box<dyn SceneObject + 'a> {
+    match geometry {
+        Geometry::Curve(curve, is_3d) => Box::new(DisplayCurve::new(curve, is_3d, display)),
+        Geometry::Surface(surf) => Box::new(DisplaySurf::new(surf, display)),
+        Geometry::Interpolation(curves) => Box::new(DisplaySurfInterpolation::new(curves, display)),
+    }
+}
+
+/// Extensions of the text curve and surface formats used by the older viewer. These are
+/// rejected on purpose: their parsers and sample files aren't part of this tree, so there's
+/// nothing to port them against, and files with them are refused with an error instead.
+const LEGACY_EXTENSIONS: [&'static str; 4] = ["curve", "txt", "dat", "sdat"];
+
+/// Read the curves and surfaces from the JSON file at `path`
+fn read_json_scene(path: &Path, options: &ImportOptions) -> Result<Vec<Geometry>, String> {
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+    let json: serde_json::Value = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    Ok(import_scene(&json, &path.to_string_lossy(), options))
+}
+
+/// Register the loaders for the built-in file formats, which read JSON files with `options`
+fn register_loaders(options: ImportOptions) {
+    loader::register("json", Box::new(move |path| read_json_scene(path, &options)));
+    // The text formats read by the older viewer aren't supported, so reject them and point
+    // the user at the JSON format instead of reporting a confusing JSON parse error
+    for ext in &LEGACY_EXTENSIONS {
+        loader::register(ext, Box::new(move |path| {
+            Err(format!("{} is a legacy .{} file, only JSON curve and surface files are supported, \
+                         see examples/ for the format", path.display(), ext))
+        }));
+    }
+}
+
+/// Read the curves and surfaces from the file at `path` with the loader registered for its
+/// extension, files with other extensions are read as JSON. Failing to read the file is
+/// reported in the log and nothing is read.
+fn read_scene_file(path: &str, options: &ImportOptions) -> Vec<Geometry> {
+    let p = Path::new(path);
+    let mut geometry = match loader::load(p).unwrap_or_else(|| read_json_scene(p, options)) {
+        Ok(g) => g,
+        Err(e) => {
+            log_message(format!("Error: {}", e));
+            return Vec::new();
+        },
+    };
+    if options.flip_handedness {
+        for g in geometry.iter_mut() {
+            g.flip_handedness();
+        }
+    }
+    geometry
+}
+
+/// Load the curves and surfaces from the JSON file at `path` into new scene objects, which
+/// remember the file so they can be reloaded from it.
+fn load_scene_file<'a, F: 'a + Facade>(path: &str, options: &ImportOptions, display: &'a F)
+    -> Vec<Box<dyn SceneObject + 'a>>
+{
+    let geometry = read_scene_file(path, options);
+    let count = geometry.len();
+    let objects: Vec<_> = geometry.into_iter().enumerate().map(|(i, g)| {
+        let mut o = make_scene_object(g, display);
+        o.set_name(object_name(path, i, count));
+        o.set_source(path.to_owned(), i);
+        o
+    }).collect();
+    log_message(format!("Loaded {} object(s) from {}", objects.len(), path));
+    objects
+}
+
+/// Group the objects loaded from files by the file, given the `(path, index)` each object
+/// was loaded from. Returns each file with the `(object, index in file)` pairs to reload
+/// from it, so each file is read once.
+fn reload_plan(sources: &[Option<(String, usize)>]) -> Vec<(String, Vec<(usize, usize)>)> {
+    let mut plan: Vec<(String, Vec<(usize, usize)>)> = Vec::new();
+    for (i, s) in sources.iter().enumerate() {
+        if let Some((ref path, index)) = *s {
+            match plan.iter().position(|p| p.0 == *path) {
+                Some(p) => plan[p].1.push((i, index)),
+                None => plan.push((path.clone(), vec![(i, index)])),
+            }
+        }
+    }
+    plan
+}
+
+/// Reload the objects loaded from files with the curves and surfaces now in the files. The
+/// objects are kept with their display settings and only their geometry is replaced.
+fn reload_scene_files<'a>(objects: &mut [Box<dyn SceneObject + 'a>], options: &ImportOptions) {
+    let sources: Vec<_> = objects.iter().map(|o| o.source().map(|(p, i)| (p.to_owned(), i))).collect();
+    for (path, targets) in reload_plan(&sources[..]) {
+        let mut geometry: Vec<_> = read_scene_file(&path, options).into_iter().map(Some).collect();
+        let mut reloaded = 0;
+        for (o, index) in targets {
+            match geometry.get_mut(index).and_then(|g| g.take()) {
+                Some(g) => {
+                    if objects[o].set_geometry(g) {
+                        reloaded += 1;
+                    } else {
+                        log_message(format!("Warning: object {} of {} changed type or is empty, it wasn't \
+                                             reloaded", index + 1, path));
+                    }
+                },
+                None => log_message(format!("Warning: object {} of {} is no longer in the file", index + 1, path)),
+            }
+        }
+        log_message(format!("Reloaded {} object(s) from {}", reloaded, path));
+    }
+}
+
+/// Get the name for object `index` of the `count` objects loaded from the file at `path`,
+/// which is the file name, numbered if the file has multiple objects.
+fn object_name(path: &str, index: usize, count: usize) -> String {
+    let file_name = Path::new(path).file_name().map_or(path.into(), |f| f.to_string_lossy());
+    if count > 1 {
+        format!("{} ({})", file_name, index + 1)
+    } else {
+        file_name.into_owned()
+    }
+}
+
+/// Give each of the newly added `objects` the next color from the palette, so objects
+/// in the scene are easy to tell apart.
+fn assign_colors<'a>(objects: &mut [Box<dyn SceneObject + 'a>], next_color: &mut usize) {
+    for o in objects.iter_mut() {
+        o.set_color(scene_object::palette_color(*next_color));
+        *next_color += 1;
+    }
+}
+
+/// Color the objects with the palette and switch to its background color
+fn apply_palette<'a>(objects: &mut [Box<dyn SceneObject + 'a>], palette: &Palette, background: &mut [f32; 3]) {
+    for (i, o) in objects.iter_mut().enumerate() {
+        o.set_color(palette.color(i));
+    }
+    *background = palette.background;
+}
+
+/// Unproject a mouse position in pixels back into world space for the 2D view, `inv_proj_view`
+/// should be the inverse of the 2D camera's projection * view matrix.
+fn unproject_2d(inv_proj_view: &Matrix4<f32>, mouse_pos: (i32, i32), width: u32, height: u32) -> Point {
+    let screen_pos = cgmath::Point3::<f32>::new(2.0 * mouse_pos.0 as f32 / width as f32 - 1.0,
+                                                -2.0 * mouse_pos.1 as f32 / height as f32 + 1.0,
+                                                0.0);
+    let pos = inv_proj_view.transform_point(screen_pos);
+    Point::new(pos.x, pos.y, 0.0)
+}
+
+/// Compute the ray through the mouse position for the 3D view, `inv_proj_view` should be the
+/// inverse of the perspective projection * arcball camera matrix. Returns `(origin, dir)`.
+fn mouse_ray(inv_proj_view: &Matrix4<f32>, mouse_pos: (i32, i32), width: u32, height: u32) -> (Point, Point) {
+    let x = 2.0 * mouse_pos.0 as f32 / width as f32 - 1.0;
+    let y = -2.0 * mouse_pos.1 as f32 / height as f32 + 1.0;
+    let near = inv_proj_view.transform_point(cgmath::Point3::<f32>::new(x, y, -1.0));
+    let far = inv_proj_view.transform_point(cgmath::Point3::<f32>::new(x, y, 1.0));
+    let origin = Point::new(near.x, near.y, near.z);
+    let dir = Point::new(far.x, far.y, far.z) - origin;
+    (origin, dir.normalized())
+}
+
+/// Get the rotation applied to the 3D scene for the up axis convention of the data. Y up data
+/// is shown as is, while Z up data is rotated so its z axis points up on screen.
+fn up_axis_matrix(z_up: bool) -> Matrix4<f32> {
+    if z_up {
+        Matrix4::from_angle_x(cgmath::Deg(-90.0))
+    } else {
+        Matrix4::identity()
+    }
+}
+
+/// Get the projection flattening the scene onto the ground plane, y = 0 or z = 0 for Z up
+/// data, used to draw the shadows of 3D curves
+fn ground_projection(z_up: bool) -> Matrix4<f32> {
+    if z_up {
+        Matrix4::from_nonuniform_scale(1.0, 1.0, 0.0)
+    } else {
+        Matrix4::from_nonuniform_scale(1.0, 0.0, 1.0)
+    }
+}
+
+/// Get the look at matrix for the 3D view's camera in its initial position
+fn default_look_at() -> Matrix4<f32> {
+    use cgmath::{Point3, Vector3};
+    Matrix4::look_at(Point3::new(0.0, 0.0, 6.0), Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0))
+}
+
+/// Create the 3D view's camera in its initial position for a framebuffer of `width` x `height`
+fn default_arcball_camera(width: u32, height: u32) -> ArcballCamera {
+    ArcballCamera::new(&default_look_at(), 1.0, 5.0, [width as f32, height as f32])
+}
+
+/// Compute the projection * view matrix for the 3D view
+fn proj_view_3d(persp_proj: &Matrix4<f32>, camera: &ArcballCamera, z_up: bool) -> Matrix4<f32> {
+    persp_proj * camera.get_mat4() * up_axis_matrix(z_up)
+}
+
+/// Compute the projection * view matrix for the axis triad, which turns with the camera's
+/// rotation but ignores its pan and zoom
+fn triad_proj_view(camera: &ArcballCamera, z_up: bool) -> Matrix4<f32> {
+    let m = camera.get_mat4();
+    let rotation = Matrix3::new(m.x.x, m.x.y, m.x.z, m.y.x, m.y.y, m.y.z, m.z.x, m.z.y, m.z.z);
+    cgmath::ortho(-1.2, 1.2, -1.2, 1.2, -2.0, 2.0) * Matrix4::from(rotation) * up_axis_matrix(z_up)
+}
+
+/// Get the center and handle length for the translation gizmo of an object with `bounds`
+fn gizmo_frame(bounds: &(Point, Point)) -> (Point, f32) {
+    let center = (bounds.0 + bounds.1) * 0.5;
+    let scale = f32::max(0.5 * (bounds.1 - bounds.0).length(), 0.5);
+    (center, scale)
+}
+
+/// Compute the 2D view's orthographic projection for a framebuffer of `width` x `height`
+/// pixels, the view is sized by the window size in points so it's the same on HiDPI displays.
+fn ortho_projection(width: u32, height: u32, hidpi_factor: f32) -> Matrix4<f32> {
+    let half_width = width as f32 / (200.0 * hidpi_factor);
+    let half_height = height as f32 / (200.0 * hidpi_factor);
+    // The depth range is large enough that 2D curves with some z aren't clipped
+    cgmath::ortho(-half_width, half_width, -half_height, half_height, -1000.0, 1000.0)
+}
+
+/// Compute the 3D view's perspective projection with a vertical field of view of `fov` degrees
+fn perspective_projection(fov: f32, width: u32, height: u32) -> Matrix4<f32> {
+    cgmath::perspective(cgmath::Deg(fov), width as f32 / height as f32, 0.01, 1000.0)
+}
+
+/// Get the time elapsed since `start` in milliseconds
+fn elapsed_ms(start: Instant) -> f32 {
+    let d = start.elapsed();
+    d.as_secs() as f32 * 1000.0 + d.subsec_nanos() as f32 / 1_000_000.0
+}
+
+/// Get the number of MSAA samples to use for the level picked in the UI
+fn msaa_samples(level: i32) -> u16 {
+    if level == 0 { 0 } else { 1 << level }
+}
+
+/// Setup the window builder for the viewer's window, requesting `samples` MSAA samples
+/// if it's non-zero.
+fn window_builder(width: u32, height: u32, samples: u16) -> glutin::WindowBuilder<'static> {
     let target_gl_versions = glutin::GlRequest::GlThenGles {
         opengl_version: (3, 3),
         opengles_version: (3, 2),
     };
-    let mut width = 1280;
-    let mut height = 720;
-    let display = glutin::WindowBuilder::new()
+    let builder = glutin::WindowBuilder::new()
         .with_dimensions(width, height)
         .with_gl(target_gl_versions)
         .with_gl_profile(glutin::GlProfile::Core)
         .with_title("Spline Viewer")
-        .with_vsync()
-        .build_glium().unwrap();
-
-    let mut curves = Vec::new();
-    let mut curves3d = Vec::new();
-    let mut surfaces = Vec::new();
-    let mut surface_interpolations = Vec::new();
-    for f in args.get_vec("<file>") {
-        let file = match File::open(&f) {
-            Ok(f) => f,
-            Err(e) => panic!("Failed to open file: {}", e),
-        };
-        let reader = BufReader::new(file);
-        let json: serde_json::Value  = serde_json::from_reader(reader).expect("Failed to read input file");
-        let ty = json["type"].as_str().expect("A curve type must be specified");
-        if ty == "bspline2d" {
-            curves.push(DisplayCurve::new(import_bspline(&json), &display));
-        } else if ty == "bspline3d" {
-            curves3d.push(DisplayCurve3D::new(import_bspline(&json), &display));
-        } else if ty == "surface" {
-            surfaces.push(DisplaySurf::new(import_surf(&json), &display));
-        } else if ty == "interpolation_u" {
-            surface_interpolations.push(DisplaySurfInterpolation::new(
-                    import_surf_interpolation(&json), &display));
+        .with_vsync();
+    if samples > 0 {
+        builder.with_multisampling(samples)
+    } else {
+        builder
+    }
+}
+
+/// Parse an image size given as `WIDTHxHEIGHT`
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let mut dims = size.split('x').map(|x| x.trim().parse::<u32>());
+    match (dims.next(), dims.next(), dims.next()) {
+        (Some(Ok(w)), Some(Ok(h)), None) if w > 0 && h > 0 => Some((w, h)),
+        _ => None,
+    }
+}
+
+/// Compute a projection * view matrix framing `bounds` in a `width` x `height` image. 2D scenes
+/// are viewed straight on with an orthographic camera, 3D ones in perspective from above and
+/// to the side, so every file is rendered from a comparable view.
+fn framing_proj_view(bounds: &(Point, Point), is_3d: bool, width: u32, height: u32) -> Matrix4<f32> {
+    use cgmath::{Point3, Vector3};
+    let aspect = width as f32 / height as f32;
+    let center = (bounds.0 + bounds.1) * 0.5;
+    if is_3d {
+        let radius = f32::max(0.5 * (bounds.1 - bounds.0).length(), 0.1);
+        // Back the camera up so the bounding sphere fits in the narrower field of view
+        let half_fov = (f32::min(1.0, aspect) * 32.5f32.to_radians().tan()).atan();
+        let dist = 1.1 * radius / half_fov.sin();
+        let eye = center + Point::new(1.0, 1.0, 2.0).normalized() * dist;
+        let view = Matrix4::look_at(Point3::new(eye.pos[0], eye.pos[1], eye.pos[2]),
+                                    Point3::new(center.pos[0], center.pos[1], center.pos[2]),
+                                    Vector3::new(0.0, 1.0, 0.0));
+        cgmath::perspective(cgmath::Deg(65.0), aspect, 0.01 * dist, dist + 2.0 * radius) * view
+    } else {
+        let mut half_width = f32::max(0.55 * (bounds.1.pos[0] - bounds.0.pos[0]), 0.1);
+        let mut half_height = f32::max(0.55 * (bounds.1.pos[1] - bounds.0.pos[1]), 0.1);
+        if half_width / half_height < aspect {
+            half_width = half_height * aspect;
+        } else {
+            half_height = half_width / aspect;
+        }
+        cgmath::ortho(center.pos[0] - half_width, center.pos[0] + half_width,
+                      center.pos[1] - half_height, center.pos[1] + half_height, -1.0, 1.0)
+    }
+}
+
+/// Find all the files in `dir` and its subdirectories, in sorted order
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = Vec::new();
+    for e in fs::read_dir(dir)? {
+        entries.push(e?.path());
+    }
+    entries.sort();
+    for p in entries {
+        if p.is_dir() {
+            collect_files(&p, files)?;
+        } else {
+            files.push(p);
+        }
+    }
+    Ok(())
+}
+
+/// Write the front buffer of the display out to a PNG file at `path`
+fn write_front_buffer(display: &GlutinFacade, path: &Path) -> io::Result<()> {
+    // The rows are read bottom to top
+    let pixels: Vec<Vec<(u8, u8, u8, u8)>> = display.read_front_buffer();
+    let height = pixels.len() as u32;
+    let width = pixels.first().map_or(0, |r| r.len()) as u32;
+    let mut data = Vec::with_capacity((4 * width * height) as usize);
+    for row in pixels.iter().rev() {
+        for p in row.iter() {
+            data.extend_from_slice(&[p.0, p.1, p.2, p.3]);
+        }
+    }
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    {
+        use png::HasParameters;
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data[..])?;
+    Ok(())
+}
+
+/// Render every curve file in `dir` and its subdirectories to a PNG in `out_dir`, keeping their
+/// paths relative to `dir`. Files which aren't JSON or have nothing to render are skipped.
+fn render_directory(display: &GlutinFacade, dir: &Path, out_dir: &Path, options: &ImportOptions,
+                    program: &Program, color_program: &Program, draw_params: &DrawParameters) {
+    let mut files = Vec::new();
+    if let Err(e) = collect_files(dir, &mut files) {
+        log_message(format!("Error: failed to read directory {}: {}", dir.display(), e));
+        return;
+    }
+    let (width, height) = display.get_framebuffer_dimensions();
+    for f in files {
+        if !loader::is_registered(&f) {
+            log_message(format!("Warning: skipping {}, not a curve file", f.display()));
+            continue;
+        }
+        let mut objects = load_scene_file(&f.to_string_lossy(), options, display);
+        let bounds: Vec<_> = objects.iter().filter_map(|o| o.bounds()).flat_map(|b| vec![b.0, b.1]).collect();
+        if bounds.is_empty() {
+            log_message(format!("Warning: skipping {}, nothing to render", f.display()));
+            continue;
+        }
+        assign_colors(&mut objects[..], &mut 0);
+        let is_3d = objects.iter_mut().any(|o| o.as_editable().map_or(true, |c| c.is_3d()));
+        let proj_view: [[f32; 4]; 4] =
+            framing_proj_view(&point::bounding_box(&bounds[..]), is_3d, width, height).into();
+
+        let mut target = display.draw();
+        target.clear_color(0.05, 0.05, 0.05, 1.0);
+        for i in scene_object::draw_order(&objects[..]) {
+            let params = scene_object::alpha_draw_params(draw_params, objects[i].alpha());
+            objects[i].render(&mut target, program, color_program, &params, &proj_view, true, 1.0);
+        }
+        target.finish().unwrap();
+
+        let out_file = out_dir.join(f.strip_prefix(dir).unwrap_or(&f)).with_extension("png");
+        let written = out_file.parent().map_or(Ok(()), |p| fs::create_dir_all(p))
+            .and_then(|_| write_front_buffer(display, &out_file));
+        match written {
+            Ok(_) => log_message(format!("Rendered {} to {}", f.display(), out_file.display())),
+            Err(e) => log_message(format!("Error: failed to write {}: {}", out_file.display(), e)),
+        }
+    }
+}
+
+/// Check a file passed on the command line can be loaded, returning a message saying
+/// what's wrong with it if not.
+fn check_file_arg(path: &str) -> Result<(), String> {
+    let p = Path::new(path);
+    if !p.exists() {
+        return Err(format!("{}: file not found", path));
+    }
+    if !p.is_file() {
+        return Err(format!("{}: not a file", path));
+    }
+    File::open(p).map(|_| ()).map_err(|e| format!("{}: unreadable: {}", path, e))
+}
+
+const USAGE: &'static str = "
+Usage:
+    spline-viewer [--flip-handedness] [--reject-unsorted-knots] [<file>...]
+    spline-viewer --render-dir <dir> [--out <out>] [--size <size>] [--flip-handedness] [--reject-unsorted-knots]
+    spline-viewer (-h | --help)
+
+Options:
+    -h, --help               Show this message.
+    --render-dir <dir>       Render every curve file in the directory to a PNG and exit.
+    --out <out>              Directory to write the rendered images to [default: renders].
+    --size <size>            Size of the rendered images as WIDTHxHEIGHT [default: 800x600].
+    --flip-handedness        Mirror the loaded files through the xy plane to convert them
+                             between left and right handed coordinates.
+    --reject-unsorted-knots  Skip objects with out of order knots instead of sorting them.
+";
+
+/// The mouse and keyboard controls listed in the Help menu
+const CONTROLS: [&'static str; 11] = [
+    "Left click: add a control point to the selected 2D curve",
+    "Left click and drag: move a control point",
+    "Shift + Left click: remove a control point",
+    "Left drag in 3D: rotate the camera",
+    "Right drag, or middle drag in 3D: pan the camera",
+    "Scroll: zoom, in 2D towards the cursor",
+    "Tab / Shift + Tab: select the next or previous object",
+    "Numpad 1, 3, 7: snap the 3D view to the front, right or top, Ctrl for the opposite side",
+    "Home: reset the cameras",
+    "F5: reload the objects loaded from files, keeping their display settings",
+    "Escape: quit",
+];
+
+fn main() {
+    let args = Docopt::new(USAGE).and_then(|d| d.parse()).unwrap_or_else(|e| e.exit());
+    // Bad paths are reported before the window opens and skipped, the viewer still
+    // launches with the files that can be loaded
+    let files: Vec<_> = args.get_vec("<file>").into_iter().filter(|f| {
+        match check_file_arg(f) {
+            Ok(_) => true,
+            Err(e) => {
+                log_message(format!("Error: {}", e));
+                false
+            },
+        }
+    }).collect();
+    // In batch mode the files are rendered in a hidden window of the output image size
+    let render_dir = args.get_str("--render-dir");
+    let import_options = ImportOptions { flip_handedness: args.get_bool("--flip-handedness"),
+                                         reject_unsorted_knots: args.get_bool("--reject-unsorted-knots") };
+    register_loaders(import_options);
+    let batch_render = !render_dir.is_empty();
+    let window_size =
+        if batch_render {
+            match parse_size(args.get_str("--size")) {
+                Some(s) => s,
+                None => {
+                    log_message(format!("Error: invalid image size '{}', expected WIDTHxHEIGHT",
+                                        args.get_str("--size")));
+                    return;
+                }
+            }
         } else {
-            println!("Unrecognized file type header {}", ty);
+            (1280, 720)
+        };
+    // The MSAA sample count is picked as a power of two, 2^msaa_level, with 0 disabling it.
+    // If the driver doesn't support multisampling we fall back to a window without it
+    let mut msaa_level = 2;
+    let display = match window_builder(window_size.0, window_size.1, msaa_samples(msaa_level))
+        .with_visibility(!batch_render).build_glium()
+    {
+        Ok(d) => d,
+        Err(_) => {
+            msaa_level = 0;
+            window_builder(window_size.0, window_size.1, 0).with_visibility(!batch_render)
+                .build_glium().unwrap()
         }
+    };
+    // Mouse positions are in physical pixels so the framebuffer size in pixels is used for
+    // picking and the cameras, not the window size in points
+    let (mut width, mut height) = display.get_framebuffer_dimensions();
+    let mut hidpi_factor = display.get_window().unwrap().hidpi_factor();
+
+    let mut objects = Vec::new();
+    // The palette index of the next color to give an object added to the scene
+    let mut next_color = 0;
+    let palettes = palette::builtin_palettes();
+    let mut palette_index = 0;
+    let mut background = palettes[0].background;
+    for f in files {
+        objects.extend(load_scene_file(f, &import_options, &display));
     }
+    assign_colors(&mut objects[..], &mut next_color);
 
-    println!("Got OpenGL: {:?}", display.get_opengl_version());
-    println!("Got GLSL: {:?}", display.get_supported_glsl_version());
+    log_message(format!("Got OpenGL: {:?}", display.get_opengl_version()));
+    log_message(format!("Got GLSL: {:?}", display.get_supported_glsl_version()));
 
     let mut imgui = ImGuiSupport::init();
     let mut imgui_renderer = Renderer::init(&mut imgui.imgui, &display).unwrap();
 
     let mut camera_2d = Camera2d::new();
-    let mut arcball_camera = {
-        use cgmath::{Point3, Vector3};
-        let look_at = Matrix4::<f32>::look_at(Point3::new(0.0, 0.0, 6.0),
-                                              Point3::new(0.0, 0.0, 0.0),
-                                              Vector3::new(0.0, 1.0, 0.0));
-        ArcballCamera::new(&look_at, 1.0, 5.0, [width as f32, height as f32])
-    };
+    let mut arcball_camera = default_arcball_camera(width, height);
 
-    let mut ortho_proj = cgmath::ortho(width as f32 / -200.0, width as f32 / 200.0, height as f32 / -200.0,
-                                       height as f32 / 200.0, -0.01, -100.0);
-    let mut persp_proj = cgmath::perspective(cgmath::Deg(65.0), width as f32 / height as f32, 0.01, 100.0);
+    let mut ortho_proj = ortho_projection(width, height, hidpi_factor);
+    let mut fov = 65.0;
+    let mut persp_proj = perspective_projection(fov, width, height);
     let draw_params = DrawParameters {
         point_size: Some(6.0),
+        // Only lines and polygons are smoothed, which is what we want for the curves
+        smooth: Some(Smooth::Nicest),
         .. Default::default()
     };
     let shader_program = program!(&display,
@@ -197,12 +812,96 @@ fn main() {
             "
         },
     ).unwrap();
+    // Shader for drawing geometry with per-vertex colors, e.g. curves colored by a gradient
+    let vertex_color_program = program!(&display,
+        330 => {
+            vertex: "
+                #version 330 core
+                uniform mat4 proj_view;
+                in vec3 pos;
+                in vec3 color;
+                out vec3 vcolor;
+                void main(void) {
+                    vcolor = color;
+                    gl_Position = proj_view * vec4(pos, 1.0);
+                }
+                ",
+            fragment: "
+                #version 330 core
+                uniform float attenuation;
+                in vec3 vcolor;
+                out vec4 color;
+                void main(void) {
+                    color = vec4(attenuation * vcolor, 1);
+                }
+            "
+        },
+    ).unwrap();
+    // Flat shader which also sizes points by their depth, so distant points in the 3D
+    // view are smaller. Points are 6px at the camera's initial distance of 6 from the origin,
+    // this is used in place of the flat shader so any object's points can be sized this way.
+    let perspective_point_program = Program::new(&display, ProgramCreationInput::SourceCode {
+        vertex_shader: "
+            #version 330 core
+            uniform mat4 proj_view;
+            in vec3 pos;
+            void main(void) {
+                gl_Position = proj_view * vec4(pos, 1.0);
+                gl_PointSize = clamp(36.0 / gl_Position.w, 1.0, 32.0);
+            }
+            ",
+        fragment_shader: "
+            #version 330 core
+            uniform vec3 pcolor;
+            out vec4 color;
+            void main(void) {
+                color = vec4(pcolor, 1);
+            }
+        ",
+        tessellation_control_shader: None,
+        tessellation_evaluation_shader: None,
+        geometry_shader: None,
+        transform_feedback_varyings: None,
+        outputs_srgb: false,
+        uses_point_size: true,
+    }).unwrap();
+
+    if batch_render {
+        render_directory(&display, Path::new(render_dir), Path::new(args.get_str("--out")), &import_options,
+                         &shader_program, &vertex_color_program, &draw_params);
+        return;
+    }
 
     let mut shift_down = false;
+    let mut ctrl_down = false;
     let mut selected_curve: i32 = 0;
     let mut ui_interaction = false;
     let mut color_attenuation = true;
     let mut render_3d = true;
+    let translate_gizmo = TranslateGizmo::new(&display);
+    let mut show_gizmo = true;
+    let mut show_triad = true;
+    let mut perspective_points = false;
+    let mut z_up = false;
+    let mut ground_shadows = false;
+    let mut pin_markers = false;
+    let mut pick_mesh_lines = false;
+    // Set when a click pinned a marker or picked a mesh line, so releasing it doesn't rotate the camera
+    let mut pinned_marker = false;
+    let mut show_log = true;
+    let mut show_controls = false;
+    // Set when the user asked to clear the scene and we're waiting for them to confirm
+    let mut confirm_clear = false;
+    // The number of log messages seen by the log window, to scroll it down when new ones come in
+    let mut log_seen = 0;
+    // CPU time in ms spent submitting the UI draws in the last frame, since the UI is
+    // rendered after the panel showing the timings is built
+    let mut ui_ms = 0.0;
+    // The world space cursor position in the 2D view and the parameter on the selected
+    // curve under it, if the cursor is near the curve
+    let mut cursor_info: (Point, Option<f32>) = (Point::new(0.0, 0.0, 0.0), None);
+    // The axis being dragged and the position along it the drag started at
+    let mut gizmo_drag: Option<(usize, f32)> = None;
     'outer: loop {
         let fbscale = imgui.imgui.display_framebuffer_scale();
         for e in display.poll_events() {
@@ -214,61 +913,122 @@ fn main() {
                         Some(VirtualKeyCode::Escape) if pressed => break 'outer,
                         Some(VirtualKeyCode::RShift) => shift_down = pressed,
                         Some(VirtualKeyCode::LShift) => shift_down = pressed,
+                        Some(VirtualKeyCode::RControl) => ctrl_down = pressed,
+                        Some(VirtualKeyCode::LControl) => ctrl_down = pressed,
+                        // Numpad 1, 3 and 7 snap to the front, right and top views like in Blender,
+                        // holding control snaps to the opposite side
+                        Some(VirtualKeyCode::Numpad1) if pressed && render_3d => {
+                            arcball_camera.snap_to(if ctrl_down { AxisView::Back } else { AxisView::Front });
+                        },
+                        Some(VirtualKeyCode::Numpad3) if pressed && render_3d => {
+                            arcball_camera.snap_to(if ctrl_down { AxisView::Left } else { AxisView::Right });
+                        },
+                        Some(VirtualKeyCode::Numpad7) if pressed && render_3d => {
+                            arcball_camera.snap_to(if ctrl_down { AxisView::Bottom } else { AxisView::Top });
+                        },
+                        // Tab is left to imgui while a UI field is being edited
+                        Some(VirtualKeyCode::Tab) if pressed && !imgui_support::is_any_item_active() => {
+                            selected_curve = scene_object::cycle_selection(selected_curve, objects.len(),
+                                                                           !shift_down);
+                        },
+                        Some(VirtualKeyCode::Home) if pressed => {
+                            camera_2d.reset();
+                            arcball_camera.reset(&default_look_at());
+                        },
+                        Some(VirtualKeyCode::F5) if pressed => {
+                            reload_scene_files(&mut objects[..], &import_options)
+                        },
                         _ => {}
                     }
                 },
                 Event::MouseMoved(x, y) if imgui.mouse_pressed.1 && !ui_interaction && !render_3d => {
-                    let delta = ((x - imgui.mouse_pos.0) as f32 / (fbscale.0 * 100.0),
-                    -(y - imgui.mouse_pos.1) as f32 / (fbscale.1 * 100.0));
-                    camera_2d.translate(delta.0, delta.1);
+                    // Move the camera by the world space distance the mouse moved so the
+                    // scene tracks the cursor exactly at any zoom level
+                    let unproj = (ortho_proj * camera_2d.get_mat4()).invert()
+                        .expect("Uninvertable proj * view!?");
+                    let prev = unproject_2d(&unproj, imgui.mouse_pos, width, height);
+                    let cur = unproject_2d(&unproj, (x, y), width, height);
+                    camera_2d.translate(cur.pos[0] - prev.pos[0], cur.pos[1] - prev.pos[1]);
                 },
                 Event::MouseMoved(x, y) if !ui_interaction && render_3d => {
-                    if imgui.mouse_pressed.0 {
+                    if let Some((i, grab_s)) = gizmo_drag {
+                        // Move the object along the axis by how far the closest point to the mouse ray moved
+                        let unproj = proj_view_3d(&persp_proj, &arcball_camera, z_up).invert()
+                            .expect("Uninvertable proj * view!?");
+                        let (origin, dir) = mouse_ray(&unproj, (x, y), width, height);
+                        if let Some(o) = objects.get_mut(selected_curve as usize) {
+                            if let Some(b) = o.bounds() {
+                                let (center, _) = gizmo_frame(&b);
+                                if let Some(s) = gizmo::closest_on_axis(&origin, &dir, &center, i) {
+                                    o.translate(gizmo::axis(i) * (s - grab_s));
+                                }
+                            }
+                        }
+                    } else if imgui.mouse_pressed.0 && !pinned_marker {
                         arcball_camera.rotate(Vector2::new(imgui.mouse_pos.0 as f32, imgui.mouse_pos.1 as f32),
                                               Vector2::new(x as f32, y as f32));
-                    } else if imgui.mouse_pressed.1 {
+                    } else if imgui.mouse_pressed.1 || imgui.mouse_pressed.2 {
+                        // Both right and middle drag pan, middle drag matching other 3D tools
                         let mouse_delta = Vector2::new((x - imgui.mouse_pos.0) as f32,
                                                        -(y - imgui.mouse_pos.1) as f32);
                         arcball_camera.pan(mouse_delta, 0.16);
                     }
                 },
                 Event::MouseInput(state, button) => {
-                    if !render_3d && state == ElementState::Released
-                        && button == MouseButton::Left && selected_curve < curves.len() as i32
+                    if render_3d && button == MouseButton::Left {
+                        gizmo_drag = None;
+                        pinned_marker = false;
+                        if state == ElementState::Pressed && show_gizmo && !ui_interaction {
+                            let unproj = proj_view_3d(&persp_proj, &arcball_camera, z_up).invert()
+                                .expect("Uninvertable proj * view!?");
+                            let (origin, dir) = mouse_ray(&unproj, imgui.mouse_pos, width, height);
+                            if let Some(b) = objects.get(selected_curve as usize).and_then(|o| o.bounds()) {
+                                let (center, scale) = gizmo_frame(&b);
+                                gizmo_drag = translate_gizmo.pick(&origin, &dir, &center, scale);
+                            }
+                        }
+                        // Dragging the gizmo takes priority over placing a marker under it
+                        if state == ElementState::Pressed && pin_markers && gizmo_drag.is_none()
+                            && !ui_interaction
+                        {
+                            let unproj = proj_view_3d(&persp_proj, &arcball_camera, z_up).invert()
+                                .expect("Uninvertable proj * view!?");
+                            let (origin, dir) = mouse_ray(&unproj, imgui.mouse_pos, width, height);
+                            if let Some(o) = objects.get_mut(selected_curve as usize) {
+                                pinned_marker = o.pin_marker(&origin, &dir);
+                            }
+                        }
+                        if state == ElementState::Pressed && pick_mesh_lines && gizmo_drag.is_none()
+                            && !pinned_marker && !ui_interaction
                         {
-                            curves[selected_curve as usize].release_point();
+                            let unproj = proj_view_3d(&persp_proj, &arcball_camera, z_up).invert()
+                                .expect("Uninvertable proj * view!?");
+                            let (origin, dir) = mouse_ray(&unproj, imgui.mouse_pos, width, height);
+                            if let Some(o) = objects.get_mut(selected_curve as usize) {
+                                pinned_marker = o.pick_mesh_line(&origin, &dir);
+                            }
+                        }
+                    }
+                    if !render_3d && state == ElementState::Released && button == MouseButton::Left {
+                        if let Some(c) = objects.get_mut(selected_curve as usize).and_then(|o| o.as_editable()) {
+                            c.release_point();
                         }
+                    }
                 },
-                Event::Resized(w, h) => {
-                    width = w;
-                    height = h;
-                    ortho_proj = cgmath::ortho(width as f32 / -200.0, width as f32 / 200.0,
-                                               height as f32 / -200.0, height as f32 / 200.0, -1.0, -1000.0);
-                    persp_proj = cgmath::perspective(cgmath::Deg(65.0), width as f32 / height as f32,
-                                                     1.0, 1000.0);
+                Event::Resized(_, _) => {
+                    // The resize event gives the size in points, but we want the framebuffer size in pixels
+                    let fb_dims = display.get_framebuffer_dimensions();
+                    width = fb_dims.0;
+                    height = fb_dims.1;
+                    hidpi_factor = display.get_window().unwrap().hidpi_factor();
+                    ortho_proj = ortho_projection(width, height, hidpi_factor);
+                    persp_proj = perspective_projection(fov, width, height);
                     arcball_camera.update_screen(width as f32, height as f32);
                 },
                 Event::DroppedFile(ref p) => {
-                    let file = match File::open(p) {
-                        Ok(f) => f,
-                        Err(e) => panic!("Failed to open file: {}", e),
-                    };
-                    let reader = BufReader::new(file);
-                    let json: serde_json::Value  = serde_json::from_reader(reader)
-                        .expect("Failed to read input file");
-                    let ty = json["type"].as_str().expect("A curve type must be specified");
-                    if ty == "bspline2d" {
-                        curves.push(DisplayCurve::new(import_bspline(&json), &display));
-                    } else if ty == "bspline3d" {
-                        curves3d.push(DisplayCurve3D::new(import_bspline(&json), &display));
-                    } else if ty == "surface" {
-                        surfaces.push(DisplaySurf::new(import_surf(&json), &display));
-                    } else if ty == "interpolation_u" {
-                        surface_interpolations.push(DisplaySurfInterpolation::new(
-                                import_surf_interpolation(&json), &display));
-                    } else {
-                        println!("Unrecognized file type header {}", ty);
-                    }
+                    let first_new = objects.len();
+                    objects.extend(load_scene_file(&p.to_string_lossy(), &import_options, &display));
+                    assign_colors(&mut objects[first_new..], &mut next_color);
                 },
                 _ => {}
             }
@@ -281,18 +1041,36 @@ fn main() {
                 }
             } else {
                 if imgui.mouse_wheel != 0.0 {
-                    camera_2d.zoom(imgui.mouse_wheel / (fbscale.1 * 10.0));
+                    // Zoom towards the cursor, keeping the point under it fixed
+                    let unproj = (ortho_proj * camera_2d.get_mat4()).invert()
+                        .expect("Uninvertable proj * view!?");
+                    let pos = unproject_2d(&unproj, imgui.mouse_pos, width, height);
+                    camera_2d.zoom_about(imgui.mouse_wheel / (fbscale.1 * 10.0), (pos.pos[0], pos.pos[1]));
                 }
-                if imgui.mouse_pressed.0 && selected_curve < curves.len() as i32 {
+                {
                     let unproj = (ortho_proj * camera_2d.get_mat4()).invert()
                         .expect("Uninvertable proj * view!?");
-                    let click_pos =
-                        cgmath::Point3::<f32>::new(2.0 * imgui.mouse_pos.0 as f32 / width as f32 - 1.0,
-                                                   -2.0 * imgui.mouse_pos.1 as f32 / height as f32 + 1.0,
-                                                   0.0);
-                    let pos = unproj.transform_point(click_pos);
-                    let pos = Point::new(pos.x, pos.y, 0.0);
-                    curves[selected_curve as usize].handle_click(pos, shift_down, camera_2d.zoom);
+                    let pos = unproject_2d(&unproj, imgui.mouse_pos, width, height);
+                    let near_dist = 20.0 / (100.0 * camera_2d.zoom);
+                    let t = objects.get_mut(selected_curve as usize).and_then(|o| o.as_editable())
+                        .and_then(|c| {
+                            if c.is_3d() || c.curve().control_points.is_empty() || c.curve().validate().is_err() {
+                                return None;
+                            }
+                            let (t, p) = c.curve().closest_point(&pos);
+                            if (p - pos).length() < near_dist { Some(t) } else { None }
+                        });
+                    cursor_info = (pos, t);
+                }
+                if imgui.mouse_pressed.0 {
+                    let unproj = (ortho_proj * camera_2d.get_mat4()).invert()
+                        .expect("Uninvertable proj * view!?");
+                    let pos = unproject_2d(&unproj, imgui.mouse_pos, width, height);
+                    if let Some(c) = objects.get_mut(selected_curve as usize).and_then(|o| o.as_editable()) {
+                        if !c.is_3d() {
+                            c.handle_click(pos, shift_down, camera_2d.zoom);
+                        }
+                    }
                 }
             }
         }
@@ -301,37 +1079,205 @@ fn main() {
         ui_interaction = imgui_support::is_mouse_hovering_any_window() || imgui_support::is_any_item_active();
 
         let mut target = display.draw();
-        target.clear_color(0.05, 0.05, 0.05, 1.0);
+        target.clear_color(background[0], background[1], background[2], 1.0);
 
         let proj_view: [[f32; 4]; 4] =
             if !render_3d {
                 (ortho_proj * camera_2d.get_mat4()).into()
             } else {
-                (persp_proj * arcball_camera.get_mat4()).into()
+                proj_view_3d(&persp_proj, &arcball_camera, z_up).into()
             };
         let attenuation = if color_attenuation { 0.4 } else { 1.0 };
 
-        for (i, c) in curves.iter().enumerate() {
-            c.render(&mut target, &shader_program, &draw_params, &proj_view, i as i32 == selected_curve,
-                     attenuation);
+        for o in objects.iter_mut() {
+            o.update(imgui.delta_s);
         }
-        for (i, c) in curves3d.iter().enumerate() {
-            let sel_curve = selected_curve - curves.len() as i32;
-            c.render(&mut target, &shader_program, &draw_params, &proj_view, i as i32 == sel_curve,
-                     attenuation);
+        let flat_program =
+            if render_3d && perspective_points {
+                &perspective_point_program
+            } else {
+                &shader_program
+            };
+        // GL calls are asynchronous so this measures the cost of issuing the draws, not the GPU time
+        let geometry_start = Instant::now();
+        for i in scene_object::draw_order(&objects[..]) {
+            let params = scene_object::alpha_draw_params(&draw_params, objects[i].alpha());
+            objects[i].render(&mut target, flat_program, &vertex_color_program, &params, &proj_view,
+                              i as i32 == selected_curve, attenuation);
         }
-        for (i, s) in surfaces.iter().enumerate() {
-            let sel_curve = selected_curve - curves.len() as i32 - curves3d.len() as i32;
-            s.render(&mut target, &shader_program, &draw_params, &proj_view, i as i32 == sel_curve,
-                     attenuation);
+        if render_3d && ground_shadows {
+            // The shadows are faint gray copies of the curves flattened onto the ground
+            let shadow_params = scene_object::alpha_draw_params(&draw_params, 0.3);
+            let shadow_proj_view: [[f32; 4]; 4] =
+                (proj_view_3d(&persp_proj, &arcball_camera, z_up) * ground_projection(z_up)).into();
+            let uniforms = uniform! {
+                proj_view: shadow_proj_view,
+                pcolor: [0.5f32, 0.5, 0.5],
+            };
+            for shadow in objects.iter().filter_map(|o| o.ground_shadow()) {
+                target.draw(shadow, &NoIndices(PrimitiveType::LineStrip), &shader_program, &uniforms,
+                            &shadow_params).unwrap();
+            }
         }
-        for (i, s) in surface_interpolations.iter().enumerate() {
-            let sel_curve = selected_curve - curves.len() as i32 - curves3d.len() as i32 - surfaces.len() as i32;
-            s.render(&mut target, &shader_program, &draw_params, &proj_view, i as i32 == sel_curve,
-                     attenuation);
+        if render_3d && show_gizmo {
+            if let Some(b) = objects.get(selected_curve as usize).and_then(|o| o.bounds()) {
+                let (center, scale) = gizmo_frame(&b);
+                translate_gizmo.render(&mut target, &shader_program, &draw_params,
+                                       &proj_view_3d(&persp_proj, &arcball_camera, z_up),
+                                       &center, scale);
+            }
+        }
+        if render_3d && show_triad {
+            // The triad is drawn in its own small viewport in the bottom left corner
+            let size = (80.0 * hidpi_factor) as u32;
+            let triad_params = DrawParameters {
+                viewport: Some(Rect { left: 10, bottom: 10, width: size, height: size }),
+                .. draw_params.clone()
+            };
+            translate_gizmo.render(&mut target, &shader_program, &triad_params,
+                                   &triad_proj_view(&arcball_camera, z_up), &Point::new(0.0, 0.0, 0.0), 1.0);
         }
+        let geometry_ms = elapsed_ms(geometry_start);
 
+        let mut msaa_change = None;
+        let mut quit = false;
         let ui = imgui.render_ui(&display);
+        ui.main_menu_bar(|| {
+            ui.menu(im_str!("File")).build(|| {
+                if ui.menu_item(im_str!("New Curve")).build() {
+                    let mut curve = DisplayCurve::new(BSpline::empty(), false, &display);
+                    curve.set_name(format!("Curve {}", objects.len() + 1));
+                    objects.push(Box::new(curve));
+                    let first_new = objects.len() - 1;
+                    assign_colors(&mut objects[first_new..], &mut next_color);
+                    selected_curve = (objects.len() - 1) as i32;
+                }
+                if ui.menu_item(im_str!("Clear Scene")).enabled(!objects.is_empty()).build() {
+                    confirm_clear = true;
+                }
+                let reloadable = objects.iter().any(|o| o.source().is_some());
+                if ui.menu_item(im_str!("Reload Files")).shortcut(im_str!("F5")).enabled(reloadable).build() {
+                    reload_scene_files(&mut objects[..], &import_options);
+                }
+                let exportable = objects.get_mut(selected_curve as usize).and_then(|o| o.as_editable())
+                    .map_or(false, |c| !c.curve().control_points.is_empty());
+                if ui.menu_item(im_str!("Export Selected Curve")).enabled(exportable).build() {
+                    if let Some(c) = objects.get_mut(selected_curve as usize).and_then(|o| o.as_editable()) {
+                        export_curve_file(c.curve(), c.is_3d(), &format!("curve_{}.json", selected_curve));
+                    }
+                }
+                ui.separator();
+                if ui.menu_item(im_str!("Save Palette")).build() {
+                    let saved = Palette { name: String::from("Saved"),
+                                          background: background,
+                                          colors: objects.iter().map(|o| o.color()).collect() };
+                    if saved.colors.is_empty() {
+                        log_message(String::from("Warning: there are no object colors to save in a palette"));
+                    } else {
+                        match palette::save(&saved, "palette.json") {
+                            Ok(_) => log_message(String::from("Saved palette to palette.json")),
+                            Err(e) => log_message(format!("Error: {}", e)),
+                        }
+                    }
+                }
+                if ui.menu_item(im_str!("Load Palette")).build() {
+                    match palette::load("palette.json") {
+                        Ok(p) => {
+                            apply_palette(&mut objects[..], &p, &mut background);
+                            log_message(String::from("Loaded palette from palette.json"));
+                        },
+                        Err(e) => log_message(format!("Error: {}", e)),
+                    }
+                }
+                ui.separator();
+                quit = ui.menu_item(im_str!("Quit")).shortcut(im_str!("Esc")).build();
+            });
+            ui.menu(im_str!("View")).build(|| {
+                if ui.menu_item(im_str!("Reset Camera")).shortcut(im_str!("Home")).build() {
+                    camera_2d.reset();
+                    arcball_camera.reset(&default_look_at());
+                }
+                if ui.menu_item(im_str!("Focus Selected")).enabled(!render_3d).build() {
+                    if let Some(b) = objects.get(selected_curve as usize).and_then(|o| o.bounds()) {
+                        camera_2d.fit((b.0.pos[0], b.0.pos[1]), (b.1.pos[0], b.1.pos[1]),
+                                      (width as f32 / (100.0 * hidpi_factor),
+                                       height as f32 / (100.0 * hidpi_factor)));
+                    }
+                }
+                ui.menu(im_str!("Snap View")).enabled(render_3d).build(|| {
+                    let views = [(im_str!("Front"), im_str!("Numpad 1"), AxisView::Front),
+                                 (im_str!("Back"), im_str!("Ctrl+Numpad 1"), AxisView::Back),
+                                 (im_str!("Right"), im_str!("Numpad 3"), AxisView::Right),
+                                 (im_str!("Left"), im_str!("Ctrl+Numpad 3"), AxisView::Left),
+                                 (im_str!("Top"), im_str!("Numpad 7"), AxisView::Top),
+                                 (im_str!("Bottom"), im_str!("Ctrl+Numpad 7"), AxisView::Bottom)];
+                    for &(ref label, ref shortcut, view) in views.iter() {
+                        if ui.menu_item(label.clone()).shortcut(shortcut.clone()).build() {
+                            arcball_camera.snap_to(view);
+                        }
+                    }
+                });
+                ui.separator();
+                ui.menu_item(im_str!("Render 3D")).selected(&mut render_3d).build();
+                ui.menu_item(im_str!("Fade Unselected Curves")).selected(&mut color_attenuation).build();
+                ui.menu_item(im_str!("Show Log")).selected(&mut show_log).build();
+                ui.separator();
+                let in_3d = render_3d;
+                ui.menu_item(im_str!("Show Translate Gizmo")).selected(&mut show_gizmo).enabled(in_3d).build();
+                ui.menu_item(im_str!("Show Axis Triad")).selected(&mut show_triad).enabled(in_3d).build();
+                ui.menu_item(im_str!("Perspective Sized Points")).selected(&mut perspective_points)
+                    .enabled(in_3d).build();
+                ui.menu_item(im_str!("Z Axis Up")).selected(&mut z_up).enabled(in_3d).build();
+                ui.menu_item(im_str!("Ground Shadows")).selected(&mut ground_shadows).enabled(in_3d).build();
+            });
+            ui.menu(im_str!("Help")).build(|| {
+                ui.menu_item(im_str!("Controls")).selected(&mut show_controls).build();
+                ui.menu(im_str!("About")).build(|| {
+                    ui.text(im_str!("Spline Viewer {}", env!("CARGO_PKG_VERSION")));
+                    ui.text(im_str!("View and edit B-spline curves and surfaces"));
+                });
+            });
+        });
+        if show_controls {
+            ui.window(im_str!("Controls"))
+                .always_auto_resize(true)
+                .opened(&mut show_controls)
+                .build(|| {
+                    for c in &CONTROLS {
+                        ui.bullet_text(im_str!("{}", c));
+                    }
+                    ui.text(im_str!("Drop curve or surface files on the window to load them"));
+                });
+        }
+        if !render_3d {
+            ui.window(im_str!("Cursor"))
+                .position((10.0, height as f32 / hidpi_factor - 90.0), imgui::ImGuiSetCond_Always)
+                .title_bar(false)
+                .resizable(false)
+                .movable(false)
+                .always_auto_resize(true)
+                .save_settings(false)
+                .build(|| {
+                    ui.text(im_str!("Cursor: ({:.3}, {:.3})", cursor_info.0.pos[0], cursor_info.0.pos[1]));
+                    if let Some(c) = objects.get_mut(selected_curve as usize).and_then(|o| o.as_editable()) {
+                        if !c.curve().control_points.is_empty() {
+                            let domain = c.curve().knot_domain();
+                            ui.text(im_str!("Knot Domain: [{:.3}, {:.3}]", domain.0, domain.1));
+                        }
+                    }
+                    if let Some(t) = cursor_info.1 {
+                        ui.text(im_str!("t: {:.4}", t));
+                    }
+                });
+        }
+        if show_log {
+            ui.window(im_str!("Log"))
+                .size((400.0, 200.0), imgui::ImGuiSetCond_FirstUseEver)
+                .opened(&mut show_log)
+                .build(|| {
+                    message_log::draw_ui(&ui, &mut log_seen);
+                });
+        }
         ui.window(im_str!("Curve Control Panel"))
             .size((300.0, 100.0), imgui::ImGuiSetCond_FirstUseEver)
             .build(|| {
@@ -340,78 +1286,479 @@ fn main() {
                 let gl_version = display.get_opengl_version();
                 let glsl_version = display.get_supported_glsl_version();
                 ui.text(im_str!("Framerate: {:.3} FPS ({:.3} ms)", fps, frame_time));
+                ui.text(im_str!("Geometry: {:.3} ms, UI: {:.3} ms", geometry_ms, ui_ms));
                 ui.text(im_str!("OpenGL Version: {}.{}", gl_version.1, gl_version.2));
                 ui.text(im_str!("GLSL Version: {}.{}", glsl_version.1, glsl_version.2));
-                ui.checkbox(im_str!("Fade Unselected Curves"), &mut color_attenuation);
-                ui.checkbox(im_str!("Render 3D"), &mut render_3d);
+                let mut level = msaa_level;
+                if ui.slider_int(im_str!("MSAA Level"), &mut level, 0, 3).build() {
+                    msaa_change = Some(level);
+                }
+                ui.text(im_str!("MSAA Samples: {}", msaa_samples(msaa_level)));
+                let palette_names: Vec<_> = palettes.iter().map(|p| im_str!("{}", p.name)).collect();
+                if ui.combo(im_str!("Palette"), &mut palette_index, &palette_names[..], 5) {
+                    apply_palette(&mut objects[..], &palettes[palette_index as usize], &mut background);
+                }
+                if render_3d {
+                    ui.checkbox(im_str!("Pin Surface Markers"), &mut pin_markers);
+                    ui.checkbox(im_str!("Pick Surface Mesh Lines"), &mut pick_mesh_lines);
+                    if ui.slider_float(im_str!("Field of View"), &mut fov, 20.0, 100.0).build() {
+                        // Values outside the slider's range can still be typed in with ctrl+click
+                        fov = f32::min(f32::max(fov, 20.0), 100.0);
+                        persp_proj = perspective_projection(fov, width, height);
+                    }
+                }
+                if ui.small_button(im_str!("Normalize Degrees")) {
+                    // Elevate all curves up to the max degree in the scene, surfaces are left as is
+                    let max_degree = objects.iter_mut().filter_map(|o| o.as_editable())
+                        .map(|c| c.curve().degree()).max().unwrap_or(0);
+                    for (i, o) in objects.iter_mut().enumerate() {
+                        let c = match o.as_editable() {
+                            Some(c) => c,
+                            None => continue,
+                        };
+                        let degree = c.curve().degree();
+                        if degree == 0 || degree == max_degree {
+                            continue;
+                        }
+                        while c.curve().degree() < max_degree {
+                            c.elevate_degree();
+                        }
+                        log_message(format!("Elevated curve {} from degree {} to {}", i, degree, max_degree));
+                    }
+                }
 
                 let mut removing = None;
-                for (i, c) in curves.iter_mut().enumerate() {
+                for (i, o) in objects.iter_mut().enumerate() {
                     ui.separator();
                     imgui_support::push_id_int(i as i32);
-                    imgui_support::radio_button(im_str!("Select Curve"), &mut selected_curve, i as i32);
-                    c.draw_ui(&ui);
-                    if ui.small_button(im_str!("Remove Curve")) {
+                    imgui_support::radio_button(im_str!("{}", o.name()), &mut selected_curve, i as i32);
+                    o.draw_ui(&ui);
+                    if let Some(c) = o.as_editable() {
+                        if !c.curve().control_points.is_empty() && ui.small_button(im_str!("Export")) {
+                            export_curve_file(c.curve(), c.is_3d(), &format!("curve_{}.json", i));
+                        }
+                    }
+                    if ui.small_button(im_str!("Remove")) {
                         removing = Some(i);
                     }
                     imgui_support::pop_id();
                 }
-                for (i, c) in curves3d.iter_mut().enumerate() {
-                    let id = i + curves.len();
-                    ui.separator();
-                    imgui_support::push_id_int(id as i32);
-                    imgui_support::radio_button(im_str!("Select Curve"), &mut selected_curve, id as i32);
-                    c.draw_ui(&ui);
-                    if ui.small_button(im_str!("Remove Curve")) {
-                        removing = Some(id);
-                    }
-                    imgui_support::pop_id();
+
+                let extracted: Vec<_> = objects.iter_mut().filter_map(|o| o.take_extracted_curve()).collect();
+                let first_new = objects.len();
+                for c in extracted {
+                    let mut curve = DisplayCurve::new(c, true, &display);
+                    curve.set_name(format!("Curve {}", objects.len() + 1));
+                    objects.push(Box::new(curve));
                 }
-                for (i, c) in surfaces.iter_mut().enumerate() {
-                    let id = i + curves.len() + curves3d.len();
-                    ui.separator();
-                    imgui_support::push_id_int(id as i32);
-                    imgui_support::radio_button(im_str!("Select Surface"), &mut selected_curve, id as i32);
-                    c.draw_ui(&ui);
-                    if ui.small_button(im_str!("Remove Surface")) {
-                        removing = Some(id);
-                    }
-                    imgui_support::pop_id();
+                assign_colors(&mut objects[first_new..], &mut next_color);
+                if let Some(i) = removing {
+                    selected_curve = scene_object::selection_after_removal(selected_curve, i);
+                    objects.remove(i);
                 }
-                for (i, c) in surface_interpolations.iter_mut().enumerate() {
-                    let id = i + curves.len() + curves3d.len() + surfaces.len();
+                if !objects.is_empty() {
                     ui.separator();
-                    imgui_support::push_id_int(id as i32);
-                    imgui_support::radio_button(im_str!("Select Surface"), &mut selected_curve, id as i32);
-                    c.draw_ui(&ui);
-                    if ui.small_button(im_str!("Remove Surface")) {
-                        removing = Some(id);
+                    if ui.small_button(im_str!("Clear All")) {
+                        confirm_clear = true;
                     }
-                    imgui_support::pop_id();
                 }
-
-                if let Some(i) = removing {
-                    if selected_curve as usize >= i && selected_curve != 0 {
-                        selected_curve -= 1;
+            });
+        if confirm_clear {
+            ui.window(im_str!("Clear Scene"))
+                .always_auto_resize(true)
+                .collapsible(false)
+                .build(|| {
+                    ui.text(im_str!("Remove all {} objects from the scene?", objects.len()));
+                    if ui.small_button(im_str!("Clear")) {
+                        objects.clear();
+                        selected_curve = 0;
+                        next_color = 0;
+                        gizmo_drag = None;
+                        cursor_info.1 = None;
+                        confirm_clear = false;
+                        log_message(String::from("Cleared the scene"));
                     }
-                    if i >= curves.len() + curves3d.len() + surfaces.len() {
-                        surface_interpolations.remove(i - curves.len() - curves3d.len() - surfaces.len());
-                    } else if i >= curves.len() + curves3d.len() {
-                        surfaces.remove(i - curves.len() - curves3d.len());
-                    } else if i >= curves.len() {
-                        curves3d.remove(i - curves.len());
-                    } else {
-                        curves.remove(i);
+                    ui.same_line(0.0);
+                    if ui.small_button(im_str!("Cancel")) {
+                        confirm_clear = false;
                     }
-                }
-                if ui.small_button(im_str!("Add Curve")) {
-                    curves.push(DisplayCurve::new(BSpline::empty(), &display));
-                    selected_curve = (curves.len() - 1) as i32;
-                }
-            });
+                });
+        }
+        let ui_start = Instant::now();
         imgui_renderer.render(&mut target, ui).unwrap();
+        ui_ms = elapsed_ms(ui_start);
 
         target.finish().unwrap();
+        if quit {
+            break;
+        }
+
+        // If the driver rejects the sample count the existing window is kept as is
+        if let Some(level) = msaa_change {
+            let size = display.get_window().unwrap().get_inner_size_points().unwrap_or(window_size);
+            if window_builder(size.0, size.1, msaa_samples(level)).rebuild_glium(&display).is_ok() {
+                msaa_level = level;
+            }
+        }
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn check_file_args() {
+        let dir = std::env::temp_dir();
+        let file = dir.join("spline_viewer_check_file_arg.json");
+        File::create(&file).unwrap();
+        let missing = dir.join("spline_viewer_no_such_file.json");
+        assert_eq!(check_file_arg(&file.to_string_lossy()), Ok(()));
+        assert!(check_file_arg(&missing.to_string_lossy()).unwrap_err().ends_with("file not found"));
+        assert!(check_file_arg(&dir.to_string_lossy()).unwrap_err().ends_with("not a file"));
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn object_names_from_path() {
+        assert_eq!(object_name("examples/curve.json", 0, 1), "curve.json");
+        assert_eq!(object_name("scene.json", 0, 2), "scene.json (1)");
+        assert_eq!(object_name("examples/scene.json", 1, 2), "scene.json (2)");
+    }
+
+    #[test]
+    fn reload_plan_groups_by_file() {
+        let sources = vec![Some(("a.json".to_owned(), 0)), None, Some(("b.json".to_owned(), 0)),
+                           Some(("a.json".to_owned(), 1))];
+        let plan = reload_plan(&sources[..]);
+        assert_eq!(plan, vec![("a.json".to_owned(), vec![(0, 0), (3, 1)]), ("b.json".to_owned(), vec![(2, 0)])]);
+        assert!(reload_plan(&[None, None]).is_empty());
+    }
+
+    /// The geometry and display settings of a `ReloadTarget`, shared so the test can look at them
+    struct ReloadState {
+        geometry: Geometry,
+        color: [f32; 3],
+        sample_step_size: f32,
+    }
+
+    /// A scene object which just holds its geometry and settings, to test reloading without a display
+    struct ReloadTarget {
+        source: Option<(String, usize)>,
+        state: Rc<RefCell<ReloadState>>,
+    }
+
+    impl SceneObject for ReloadTarget {
+        fn render(&self, _: &mut glium::Frame, _: &Program, _: &Program, _: &DrawParameters, _: &[[f32; 4]; 4],
+                  _: bool, _: f32) {}
+        fn draw_ui(&mut self, _: &imgui::Ui) {}
+        fn name(&self) -> &str {
+            "reload target"
+        }
+        fn set_name(&mut self, _: String) {}
+        fn source(&self) -> Option<(&str, usize)> {
+            self.source.as_ref().map(|s| (&s.0[..], s.1))
+        }
+        fn set_source(&mut self, path: String, index: usize) {
+            self.source = Some((path, index));
+        }
+        fn set_geometry(&mut self, geometry: Geometry) -> bool {
+            let mut state = self.state.borrow_mut();
+            match (&state.geometry, &geometry) {
+                (&Geometry::Curve(..), &Geometry::Curve(..))
+                    | (&Geometry::Surface(_), &Geometry::Surface(_)) => {},
+                _ => return false,
+            }
+            state.geometry = geometry;
+            true
+        }
+        fn bounds(&self) -> Option<(Point, Point)> {
+            None
+        }
+        fn translate(&mut self, _: Point) {}
+        fn color(&self) -> [f32; 3] {
+            self.state.borrow().color
+        }
+        fn set_color(&mut self, color: [f32; 3]) {
+            self.state.borrow_mut().color = color;
+        }
+    }
+
+    #[test]
+    fn reload_keeps_settings() {
+        let point = |x: f64, y: f64| json!({"x": x, "y": y, "z": 0.0});
+        let scene = |y: f64| json!({
+            "objects": [
+                {"type": "bspline2d", "degree": 1, "points": [point(0.0, 0.0), point(1.0, y)]},
+                {"type": "surface",
+                 "u": {"degree": 1, "knots": [0.0, 0.0, 1.0, 1.0]},
+                 "v": {"degree": 1, "knots": [0.0, 0.0, 1.0, 1.0]},
+                 "mesh": [[point(0.0, 0.0), point(0.0, 1.0)], [point(1.0, 0.0), point(1.0, y)]]},
+            ],
+        });
+        let path = std::env::temp_dir().join("spline_viewer_reload_test.json");
+        let path_str = path.to_string_lossy().into_owned();
+        let options = ImportOptions::default();
+        serde_json::to_writer(File::create(&path).unwrap(), &scene(1.0)).unwrap();
+
+        let states: Vec<_> = read_scene_file(&path_str, &options).into_iter().map(|g| {
+            Rc::new(RefCell::new(ReloadState { geometry: g, color: [0.0; 3], sample_step_size: 0.05 }))
+        }).collect();
+        assert_eq!(states.len(), 2);
+        let mut objects: Vec<Box<dyn SceneObject>> = states.iter().enumerate().map(|(i, s)| {
+            let target = ReloadTarget { source: Some((path_str.clone(), i)), state: s.clone() };
+            Box::new(target) as Box<dyn SceneObject>
+        }).collect();
+        objects[0].set_color([1.0, 0.5, 0.0]);
+        objects[1].set_color([0.0, 0.5, 1.0]);
+        states[1].borrow_mut().sample_step_size = 0.2;
+
+        // Edit the file and reload it, the new geometry replaces the old but the settings are kept
+        serde_json::to_writer(File::create(&path).unwrap(), &scene(3.0)).unwrap();
+        reload_scene_files(&mut objects[..], &options);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(objects[0].color(), [1.0, 0.5, 0.0]);
+        assert_eq!(objects[1].color(), [0.0, 0.5, 1.0]);
+        assert_eq!(objects[0].source(), Some((&path_str[..], 0)));
+        assert_eq!(states[0].borrow().sample_step_size, 0.05);
+        assert_eq!(states[1].borrow().sample_step_size, 0.2);
+        let (curve, surf) = (states[0].borrow(), states[1].borrow());
+        match curve.geometry {
+            Geometry::Curve(ref c, false) => {
+                assert_eq!(c.control_points().last().unwrap().pos, [1.0, 3.0, 0.0]);
+            },
+            _ => panic!("expected the first object to still be a curve"),
+        }
+        match surf.geometry {
+            Geometry::Surface(ref s) => assert_eq!(s.point(1.0, 1.0).pos, [1.0, 3.0, 0.0]),
+            _ => panic!("expected the second object to still be a surface"),
+        }
+    }
+
+    #[test]
+    fn legacy_files_rejected() {
+        register_loaders(ImportOptions::default());
+        for ext in &LEGACY_EXTENSIONS {
+            let file = format!("old_curve.{}", ext.to_uppercase());
+            match loader::load(Path::new(&file)) {
+                Some(Err(e)) => {
+                    assert!(e.contains("legacy") && e.contains("only JSON"), "unclear error: {}", e);
+                },
+                _ => panic!("expected {} to be rejected", file),
+            }
+        }
+        assert!(loader::is_registered(Path::new("scene.json")));
+    }
+
+    #[test]
+    fn import_weighted_interpolation() {
+        // Quarter circle arcs of the unit cylinder at z = 0 and z = 2
+        let w = std::f64::consts::FRAC_1_SQRT_2;
+        let row = |z: f64| json!([{"x": 1.0, "y": 0.0, "z": z}, {"x": 1.0, "y": 1.0, "z": z},
+                                  {"x": 0.0, "y": 1.0, "z": z}]);
+        let json = json!({
+            "type": "interpolation_u",
+            "u": {"degree": 2, "knots": [0.0, 0.0, 0.0, 1.0, 1.0, 1.0]},
+            "mesh": [row(0.0), row(2.0)],
+            "weights": [[1.0, w, 1.0], [1.0, w, 1.0]],
+        });
+        let options = ImportOptions::default();
+        let surf = match import_geometry(&json, "test.json", &options) {
+            Some(Geometry::Surface(s)) => s,
+            _ => panic!("expected the weighted interpolation to import as a surface"),
+        };
+        let curves: Vec<_> = [0.0, 2.0].iter().map(|z| {
+            let points = vec![HomogeneousPoint::new(Point::new(1.0, 0.0, *z), 1.0),
+                              HomogeneousPoint::new(Point::new(1.0, 1.0, *z), w as f32),
+                              HomogeneousPoint::new(Point::new(0.0, 1.0, *z), 1.0)];
+            BSpline::new(2, points, vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0])
+        }).collect();
+        let rational = BSplineSurf::interpolate_rational_curves(&curves[..], 1);
+        for i in 0..5 {
+            for j in 0..5 {
+                let (u, v) = (i as f32 / 4.0, j as f32 / 4.0);
+                let (p, r) = (surf.point(u, v), rational.rational_point(u, v));
+                assert!((p - r).length() < 1e-3, "{:?} != {:?} at ({}, {})", p, r, u, v);
+                // The rational surface is on the cylinder, which a polynomial surface can't be exactly
+                assert!((p.pos[0] * p.pos[0] + p.pos[1] * p.pos[1] - 1.0).abs() < 1e-3);
+            }
+        }
+        // Weights which don't match the mesh are rejected
+        let mut bad = json.clone();
+        bad["weights"] = json!([[1.0, w, 1.0]]);
+        assert!(import_geometry(&bad, "test.json", &options).is_none());
+    }
+
+    #[test]
+    fn rational_weights_detected() {
+        assert!(!has_rational_weights(&json!({"type": "interpolation_u"})));
+        assert!(!has_rational_weights(&json!({"weights": [[1.0, 1.0], [1.0, 1.0]]})));
+        assert!(has_rational_weights(&json!({"weights": [[1.0, 0.5], [1.0, 1.0]]})));
+    }
+
+    #[test]
+    fn import_bspline_keeps_z() {
+        let json = json!({
+            "type": "bspline2d",
+            "degree": 1,
+            "points": [{"x": 0.0, "y": 0.0, "z": 0.5}, {"x": 1.0, "y": 1.0}],
+        });
+        let options = ImportOptions::default();
+        let curve = import_bspline(&json, "test.json", &options).unwrap();
+        let z: Vec<_> = curve.control_points().map(|p| p.pos[2]).collect();
+        assert_eq!(z, vec![0.5, 0.0]);
+    }
+
+    #[test]
+    fn import_scene_objects() {
+        let point = |x: f64, y: f64, z: f64| json!({"x": x, "y": y, "z": z});
+        let json = json!({
+            "objects": [
+                {"type": "bspline2d", "degree": 1, "points": [point(0.0, 0.0, 0.0), point(1.0, 1.0, 0.0)]},
+                {"type": "bspline3d", "degree": 2,
+                 "points": [point(0.0, 0.0, 0.0), point(1.0, 1.0, 1.0), point(2.0, 0.0, 2.0)]},
+                {"type": "surface",
+                 "u": {"degree": 1, "knots": [0.0, 0.0, 1.0, 1.0]},
+                 "v": {"degree": 1, "knots": [0.0, 0.0, 1.0, 1.0]},
+                 "mesh": [[point(0.0, 0.0, 0.0), point(0.0, 1.0, 0.0)],
+                          [point(1.0, 0.0, 0.0), point(1.0, 1.0, 1.0)]]},
+            ],
+        });
+        let options = ImportOptions::default();
+        let geometry = import_scene(&json, "scene.json", &options);
+        assert_eq!(geometry.len(), 3);
+        match geometry[0] {
+            Geometry::Curve(ref c, false) => assert_eq!(c.degree(), 1),
+            _ => panic!("expected the first object to be a 2D curve"),
+        }
+        match geometry[1] {
+            Geometry::Curve(ref c, true) => {
+                assert_eq!(c.degree(), 2);
+                assert_eq!(c.control_points().count(), 3);
+            },
+            _ => panic!("expected the second object to be a 3D curve"),
+        }
+        match geometry[2] {
+            Geometry::Surface(ref s) => {
+                assert_eq!((s.degree_u(), s.degree_v()), (1, 1));
+                assert_eq!(s.point(1.0, 1.0).pos, [1.0, 1.0, 1.0]);
+            },
+            _ => panic!("expected the third object to be a surface"),
+        }
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let points = vec![Point::new(0.0, 0.0, 0.25), Point::new(1.5, 2.0, -1.0), Point::new(2.0, -0.5, 0.0),
+                          Point::new(3.25, 1.0, 2.0), Point::new(4.0, 0.0, 0.5)];
+        let curve = BSpline::new(2, points, vec![0.0, 0.0, 0.0, 0.3, 1.7, 2.0, 2.0, 2.0]);
+        let options = ImportOptions::default();
+        for &is_3d in &[false, true] {
+            // Go through the text written to the file to check the values survive printing
+            let text = export_bspline(&curve, is_3d).to_string();
+            let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+            let imported = match import_geometry(&json, "export.json", &options) {
+                Some(Geometry::Curve(c, imported_3d)) => {
+                    assert_eq!(imported_3d, is_3d);
+                    c
+                },
+                _ => panic!("expected the exported curve to import as a curve"),
+            };
+            assert_eq!(imported.degree(), curve.degree());
+            assert_eq!(imported.knots().collect::<Vec<_>>(), curve.knots().collect::<Vec<_>>());
+            assert_eq!(imported.control_points().map(|p| p.pos).collect::<Vec<_>>(),
+                       curve.control_points().map(|p| p.pos).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn write_curve_file_errors() {
+        let curve = BSpline::new(1, vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0)], vec![]);
+        let dir = std::env::temp_dir();
+        let err = write_curve_file(&curve, false, &dir.to_string_lossy()).unwrap_err();
+        assert!(err.starts_with("failed to create"), "{}", err);
+        // Writing to a full disk is reported instead of crashing
+        if Path::new("/dev/full").exists() {
+            let err = write_curve_file(&curve, false, "/dev/full").unwrap_err();
+            assert!(err.starts_with("failed to write"), "{}", err);
+        }
+        let file = dir.join("spline_viewer_write_curve_test.json");
+        write_curve_file(&curve, true, &file.to_string_lossy()).unwrap();
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn mismatched_knots_fall_back_to_uniform() {
+        let json = json!({
+            "type": "bspline2d",
+            "degree": 2,
+            "points": [{"x": 0.0, "y": 0.0}, {"x": 1.0, "y": 1.0}, {"x": 2.0, "y": 0.0}, {"x": 3.0, "y": 1.0}],
+            "knots": [0.0, 0.0, 1.0, 1.0],
+        });
+        let options = ImportOptions::default();
+        let curve = import_bspline(&json, "test.json", &options).expect("the curve should still load");
+        let knots: Vec<_> = curve.knots().cloned().collect();
+        assert_eq!(knots, vec![0.0, 0.0, 0.0, 1.0, 2.0, 2.0, 2.0]);
+        assert_eq!(curve.control_points().count(), 4);
+        assert_eq!(curve.validate(), Ok(()));
+    }
+
+    #[test]
+    fn view_transforms_compose() {
+        use cgmath::{Vector4, InnerSpace};
+        let near = |a: Vector4<f32>, b: Vector4<f32>| (a - b).magnitude() < 1e-4;
+        // Z up data has its z axis turned to point up the screen's y axis
+        assert_eq!(up_axis_matrix(false), Matrix4::identity());
+        let z_up = up_axis_matrix(true);
+        assert!(near(z_up * Vector4::new(0.0, 0.0, 1.0, 0.0), Vector4::new(0.0, 1.0, 0.0, 0.0)));
+        assert!(near(z_up * Vector4::new(0.0, 1.0, 0.0, 0.0), Vector4::new(0.0, 0.0, -1.0, 0.0)));
+
+        let mut camera = default_arcball_camera(800, 600);
+        camera.rotate(Vector2::new(300.0, 200.0), Vector2::new(420.0, 260.0));
+        camera.pan(Vector2::new(15.0, -10.0), 0.016);
+        let persp_proj = perspective_projection(65.0, 800, 600);
+        let triad_proj = cgmath::ortho(-1.2, 1.2, -1.2, 1.2, -2.0, 2.0);
+        for &z_up in &[false, true] {
+            let proj_view = proj_view_3d(&persp_proj, &camera, z_up);
+            let triad = triad_proj_view(&camera, z_up);
+            for p in &[Vector4::new(1.0, 2.0, -0.5, 1.0), Vector4::new(-3.0, 0.25, 2.0, 1.0)] {
+                let stepwise = persp_proj * (camera.get_mat4() * (up_axis_matrix(z_up) * *p));
+                assert!(near(proj_view * *p, stepwise), "{:?} != {:?}", proj_view * *p, stepwise);
+                // The triad turns directions like the scene but ignores the camera's pan
+                let dir = Vector4::new(p.x, p.y, p.z, 0.0);
+                let expected = triad_proj * (camera.get_mat4() * (up_axis_matrix(z_up) * dir));
+                assert!(near(triad * dir, expected), "{:?} != {:?}", triad * dir, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn ground_projection_flattens_up_axis() {
+        use cgmath::Vector4;
+        let p = Vector4::new(1.0, 2.0, 3.0, 1.0);
+        assert_eq!(ground_projection(false) * p, Vector4::new(1.0, 0.0, 3.0, 1.0));
+        assert_eq!(ground_projection(true) * p, Vector4::new(1.0, 2.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn unsorted_knots_reported() {
+        let json = json!({
+            "type": "bspline2d",
+            "degree": 1,
+            "points": [{"x": 0.0, "y": 0.0}, {"x": 1.0, "y": 1.0}, {"x": 2.0, "y": 0.0}],
+            "knots": [0.0, 0.0, 2.0, 1.0, 3.0],
+        });
+        assert_eq!(unsorted_knots_message(&[0.0, 0.0, 2.0, 1.0, 3.0], "Curve", "test.json"),
+                   Some(String::from("Curve in test.json has knots out of order at indices [3]")));
+        assert_eq!(unsorted_knots_message(&[0.0, 0.0, 1.0, 2.0, 3.0], "Curve", "test.json"), None);
+        // The knots are sorted unless out of order knots are rejected
+        let mut options = ImportOptions::default();
+        let curve = import_bspline(&json, "test.json", &options).unwrap();
+        assert_eq!(curve.knots().cloned().collect::<Vec<_>>(), vec![0.0, 0.0, 1.0, 2.0, 3.0]);
+        options.reject_unsorted_knots = true;
+        assert!(import_bspline(&json, "test.json", &options).is_none());
+    }
+}
@@ -0,0 +1,146 @@
+//! Export tessellated surfaces as Wavefront OBJ or glTF 2.0 meshes, the format is
+//! picked by the extension of the file being written.
+
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+
+use serde_json;
+
+use bspline_surf::BSplineSurf;
+use point::Point;
+
+/// Tessellate the surface into a grid of `res` by `res` vertices and write it to `path`,
+/// which must be an `.obj` or `.gltf` file. OBJ files can keep the grid's quad faces, the
+/// glTF mesh is always triangulated and written with its vertex normals.
+pub fn export_surface(surf: &BSplineSurf<Point>, path: &str, res: usize, quad_faces: bool) -> io::Result<()> {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("obj") => {
+            let (points, quads) = surf.tessellate_quads(res, res);
+            let f = File::create(path)?;
+            write_obj(&mut BufWriter::new(f), &points[..], &quads[..], quad_faces)
+        },
+        Some("gltf") => {
+            let (points, normals, triangles) = surf.tessellate(res, res);
+            write_gltf(Path::new(path), &points[..], &normals[..], &triangles[..])
+        },
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                format!("unsupported mesh format for {}, expected .obj or .gltf", path))),
+    }
+}
+
+/// Write the mesh out as a Wavefront OBJ file, either with the quad faces or splitting
+/// each quad into two triangles.
+fn write_obj<W: Write>(w: &mut W, points: &[Point], quads: &[[u32; 4]], quad_faces: bool) -> io::Result<()> {
+    for p in points {
+        writeln!(w, "v {} {} {}", p.pos[0], p.pos[1], p.pos[2])?;
+    }
+    // OBJ indices start at 1
+    for q in quads {
+        if quad_faces {
+            writeln!(w, "f {} {} {} {}", q[0] + 1, q[1] + 1, q[2] + 1, q[3] + 1)?;
+        } else {
+            writeln!(w, "f {} {} {}", q[0] + 1, q[1] + 1, q[2] + 1)?;
+            writeln!(w, "f {} {} {}", q[0] + 1, q[2] + 1, q[3] + 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the triangle mesh as a glTF 2.0 file at `path`, the vertex and index data is
+/// written to a `.bin` file next to it.
+fn write_gltf(path: &Path, points: &[Point], normals: &[Point], triangles: &[[u32; 3]]) -> io::Result<()> {
+    let bin_path = path.with_extension("bin");
+    let bin_name = bin_path.file_name().unwrap().to_string_lossy().into_owned();
+    let mut bin = BufWriter::new(File::create(&bin_path)?);
+    for p in points.iter().chain(normals.iter()) {
+        for x in &p.pos {
+            write_u32_le(&mut bin, x.to_bits())?;
+        }
+    }
+    for t in triangles {
+        for i in t {
+            write_u32_le(&mut bin, *i)?;
+        }
+    }
+    bin.flush()?;
+
+    let vertex_bytes = 12 * points.len();
+    let index_bytes = 12 * triangles.len();
+    // The position accessor is required to have the bounds of the positions
+    let (min, max) = points.iter().fold(([f32::MAX; 3], [f32::MIN; 3]), |(mut min, mut max), p| {
+        for c in 0..3 {
+            min[c] = f32::min(min[c], p.pos[c]);
+            max[c] = f32::max(max[c], p.pos[c]);
+        }
+        (min, max)
+    });
+    // Constants from the glTF spec for the buffer targets, component types and triangle mode
+    let (array_buffer, element_array_buffer, float, unsigned_int, triangles_mode) = (34962, 34963, 5126, 5125, 4);
+    let gltf = json!({
+        "asset": { "version": "2.0", "generator": "spline-viewer" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{
+            "primitives": [{
+                "attributes": { "POSITION": 0, "NORMAL": 1 },
+                "indices": 2,
+                "mode": triangles_mode,
+            }],
+        }],
+        "buffers": [{ "uri": bin_name, "byteLength": 2 * vertex_bytes + index_bytes }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": vertex_bytes, "target": array_buffer },
+            { "buffer": 0, "byteOffset": vertex_bytes, "byteLength": vertex_bytes, "target": array_buffer },
+            { "buffer": 0, "byteOffset": 2 * vertex_bytes, "byteLength": index_bytes,
+              "target": element_array_buffer },
+        ],
+        "accessors": [
+            { "bufferView": 0, "componentType": float, "count": points.len(), "type": "VEC3",
+              "min": min, "max": max },
+            { "bufferView": 1, "componentType": float, "count": normals.len(), "type": "VEC3" },
+            { "bufferView": 2, "componentType": unsigned_int, "count": 3 * triangles.len(), "type": "SCALAR" },
+        ],
+    });
+    let f = File::create(path)?;
+    serde_json::to_writer_pretty(BufWriter::new(f), &gltf)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Write `x` as 4 little endian bytes, which glTF uses for all its binary data
+fn write_u32_le<W: Write>(w: &mut W, x: u32) -> io::Result<()> {
+    w.write_all(&[x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Read;
+
+    #[test]
+    fn gltf_export_counts() {
+        let mesh = (0..3).map(|i| (0..3).map(|j| Point::new(i as f32, (i * j) as f32, j as f32)).collect())
+            .collect();
+        let surf = BSplineSurf::new((2, 1), (vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0], vec![0.0, 0.0, 1.0, 2.0, 2.0]),
+                                    mesh);
+        let path = env::temp_dir().join("spline_viewer_export_test.gltf");
+        export_surface(&surf, &path.to_string_lossy(), 5, false).unwrap();
+
+        let gltf: serde_json::Value = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(gltf["asset"]["version"], "2.0");
+        assert_eq!(gltf["accessors"][0]["count"], 25);
+        assert_eq!(gltf["accessors"][1]["count"], 25);
+        assert_eq!(gltf["accessors"][2]["count"], 3 * 2 * 4 * 4);
+        let mut bin = Vec::new();
+        File::open(path.with_extension("bin")).unwrap().read_to_end(&mut bin).unwrap();
+        assert_eq!(gltf["buffers"][0]["byteLength"], bin.len());
+        assert_eq!(bin.len(), 2 * 25 * 12 + 3 * 2 * 4 * 4 * 4);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("bin")).unwrap();
+
+        assert!(export_surface(&surf, "surface.stl", 5, false).is_err());
+    }
+}
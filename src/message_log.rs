@@ -0,0 +1,61 @@
+//! A log of the import messages, warnings and errors which is shown in the UI, since
+//! stdout isn't visible when the viewer isn't launched from a terminal.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use imgui::Ui;
+
+use imgui_support;
+
+/// The max number of messages kept in the log, older messages are dropped
+const MAX_MESSAGES: usize = 256;
+
+struct MessageLog {
+    messages: VecDeque<String>,
+    /// The total number of messages logged so far, used to tell when new ones come in
+    count: usize,
+}
+
+thread_local!(static LOG: RefCell<MessageLog> = RefCell::new(MessageLog {
+                                                                  messages: VecDeque::new(),
+                                                                  count: 0
+                                                              }));
+
+/// Add a message to the log, it's also printed to stdout
+pub fn log_message(msg: String) {
+    println!("{}", msg);
+    LOG.with(|log| {
+        let mut log = log.borrow_mut();
+        if log.messages.len() == MAX_MESSAGES {
+            log.messages.pop_front();
+        }
+        log.messages.push_back(msg);
+        log.count += 1;
+    });
+}
+
+/// Draw the log messages in the current window, `last_seen` tracks the message count
+/// when the log was last drawn so the window can scroll down to new messages.
+pub fn draw_ui(ui: &Ui, last_seen: &mut usize) {
+    LOG.with(|log| {
+        if ui.small_button(im_str!("Clear")) {
+            log.borrow_mut().messages.clear();
+        }
+        ui.separator();
+        let log = log.borrow();
+        for m in log.messages.iter() {
+            if m.starts_with("Error") {
+                ui.text_colored((1.0, 0.4, 0.4, 1.0), im_str!("{}", m));
+            } else if m.starts_with("Warning") {
+                ui.text_colored((1.0, 0.8, 0.3, 1.0), im_str!("{}", m));
+            } else {
+                ui.text_wrapped(im_str!("{}", m));
+            }
+        }
+        if *last_seen != log.count {
+            imgui_support::set_scroll_here();
+            *last_seen = log.count;
+        }
+    });
+}
@@ -0,0 +1,111 @@
+//! Color palettes which can be applied to the objects in the scene, saved to and
+//! loaded from JSON files so a color scheme can be reused across sessions.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use serde_json;
+
+use scene_object;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub background: [f32; 3],
+    /// Colors given to the objects in order, repeating if there are more objects
+    pub colors: Vec<[f32; 3]>,
+}
+
+impl Palette {
+    /// Get the color for the `i`th object in the scene
+    pub fn color(&self, i: usize) -> [f32; 3] {
+        self.colors[i % self.colors.len()]
+    }
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "type": "palette",
+            "name": self.name,
+            "background": self.background,
+            "colors": self.colors,
+        })
+    }
+    /// Read a palette saved by `to_json`, returns a message describing the problem if it's invalid
+    pub fn from_json(json: &serde_json::Value) -> Result<Palette, String> {
+        let name = json["name"].as_str().unwrap_or("Unnamed").to_owned();
+        let background = parse_color(&json["background"]).ok_or("Invalid background color")?;
+        let colors = json["colors"].as_array().ok_or("A list of colors must be specified")?
+            .iter().map(parse_color).collect::<Option<Vec<_>>>().ok_or("Invalid palette color")?;
+        if colors.is_empty() {
+            return Err(String::from("A palette needs at least one color"));
+        }
+        Ok(Palette { name: name, background: background, colors: colors })
+    }
+}
+
+/// Save the palette to the JSON file at `path`
+pub fn save(palette: &Palette, path: &str) -> Result<(), String> {
+    let f = File::create(path).map_err(|e| format!("failed to create {}: {}", path, e))?;
+    serde_json::to_writer_pretty(BufWriter::new(f), &palette.to_json())
+        .map_err(|e| format!("failed to write {}: {}", path, e))
+}
+
+/// Load a palette from the JSON file at `path`
+pub fn load(path: &str) -> Result<Palette, String> {
+    let f = File::open(path).map_err(|e| format!("failed to open {}: {}", path, e))?;
+    let json: serde_json::Value = serde_json::from_reader(BufReader::new(f))
+        .map_err(|e| format!("failed to parse {}: {}", path, e))?;
+    Palette::from_json(&json).map_err(|e| format!("{} is not a valid palette: {}", path, e))
+}
+
+/// Parse an `[r, g, b]` color
+fn parse_color(json: &serde_json::Value) -> Option<[f32; 3]> {
+    let c = json.as_array()?;
+    if c.len() != 3 {
+        return None;
+    }
+    Some([c[0].as_f64()? as f32, c[1].as_f64()? as f32, c[2].as_f64()? as f32])
+}
+
+/// Get the palettes built into the viewer, the first is the default color scheme
+pub fn builtin_palettes() -> Vec<Palette> {
+    vec![Palette { name: String::from("Default"),
+                   background: [0.05, 0.05, 0.05],
+                   colors: (0..8).map(scene_object::palette_color).collect() },
+         // The Okabe-Ito colors, which stay distinguishable with color vision deficiencies
+         Palette { name: String::from("Colorblind Safe"),
+                   background: [0.05, 0.05, 0.05],
+                   colors: vec![[0.902, 0.624, 0.0], [0.337, 0.706, 0.914], [0.0, 0.620, 0.451],
+                                [0.941, 0.894, 0.259], [0.0, 0.447, 0.698], [0.835, 0.369, 0.0],
+                                [0.800, 0.475, 0.655]] },
+         // Dark colors on a white background for figures that will be printed
+         Palette { name: String::from("Print"),
+                   background: [1.0, 1.0, 1.0],
+                   colors: vec![[0.1, 0.1, 0.1], [0.12, 0.25, 0.6], [0.65, 0.1, 0.1],
+                                [0.1, 0.45, 0.2], [0.4, 0.15, 0.5]] },
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let saved = Palette { name: String::from("Saved"),
+                              background: [0.25, 0.5, 0.75],
+                              colors: vec![[1.0, 0.0, 0.0], [0.0, 0.5, 1.0]] };
+        let loaded = Palette::from_json(&saved.to_json()).unwrap();
+        assert_eq!(loaded, saved);
+        assert_eq!(loaded.color(3), [0.0, 0.5, 1.0]);
+        for p in builtin_palettes() {
+            assert_eq!(Palette::from_json(&p.to_json()).unwrap(), p);
+        }
+    }
+
+    #[test]
+    fn invalid_palettes() {
+        assert!(Palette::from_json(&json!({"background": [0.0, 0.0], "colors": [[1.0, 1.0, 1.0]]})).is_err());
+        assert!(Palette::from_json(&json!({"background": [0.0, 0.0, 0.0], "colors": []})).is_err());
+        assert!(Palette::from_json(&json!({"background": [0.0, 0.0, 0.0]})).is_err());
+    }
+}
@@ -13,6 +13,17 @@ pub fn clamp(x: f32, min: f32, max: f32) -> f32 {
     }
 }
 
+/// Compute the axis-aligned bounding box of the points, returned as `(min, max)`
+pub fn bounding_box(points: &[Point]) -> (Point, Point) {
+    points.iter().fold((Point::new(f32::MAX, f32::MAX, f32::MAX), Point::new(f32::MIN, f32::MIN, f32::MIN)),
+        |acc, p| {
+            (Point::new(f32::min(acc.0.pos[0], p.pos[0]), f32::min(acc.0.pos[1], p.pos[1]),
+                        f32::min(acc.0.pos[2], p.pos[2])),
+             Point::new(f32::max(acc.1.pos[0], p.pos[0]), f32::max(acc.1.pos[1], p.pos[1]),
+                        f32::max(acc.1.pos[2], p.pos[2])))
+        })
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Point {
     pub pos: [f32; 3],
@@ -27,9 +38,37 @@ impl Point {
     pub fn length(&self) -> f32 {
         f32::sqrt(self.dot(&self))
     }
+    /// Get the unit vector in the same direction, points with ~0 length give the zero vector
+    pub fn normalized(&self) -> Point {
+        let len = self.length();
+        if len < 1e-12 {
+            Point::new(0.0, 0.0, 0.0)
+        } else {
+            *self / len
+        }
+    }
+    pub fn cross(&self, a: &Point) -> Point {
+        Point::new(self.pos[1] * a.pos[2] - self.pos[2] * a.pos[1],
+                   self.pos[2] * a.pos[0] - self.pos[0] * a.pos[2],
+                   self.pos[0] * a.pos[1] - self.pos[1] * a.pos[0])
+    }
 }
 implement_vertex!(Point, pos);
 
+/// A point with a per-vertex color, used for drawing curves shaded
+/// with colors that vary along their length.
+#[derive(Copy, Clone, Debug)]
+pub struct ColorPoint {
+    pub pos: [f32; 3],
+    pub color: [f32; 3],
+}
+impl ColorPoint {
+    pub fn new(p: Point, color: [f32; 3]) -> ColorPoint {
+        ColorPoint { pos: p.pos, color: color }
+    }
+}
+implement_vertex!(ColorPoint, pos, color);
+
 impl Mul<f32> for Point {
     type Output = Point;
     fn mul(self, rhs: f32) -> Point {
@@ -58,10 +97,84 @@ impl ProjectToSegment for Point {
     fn project(&self, a: &Point, b: &Point) -> (f32, f32) {
         let v = *b - *a;
         let dir = *self - *a;
-        let t = clamp(dir.dot(&v) / v.dot(&v), 0.0, 1.0);
+        // A zero length segment is just the point, there's no direction to project along
+        let len_sq = v.dot(&v);
+        if len_sq < 1e-12 {
+            return (dir.length(), 0.0);
+        }
+        let t = clamp(dir.dot(&v) / len_sq, 0.0, 1.0);
         let p = *a + v * t;
         let d = (p - *self).length();
         (d, t)
     }
 }
 
+
+/// A point in homogeneous coordinates, used to evaluate rational (NURBS) curves.
+/// The position is stored pre-multiplied by the weight so linearly interpolating
+/// homogeneous points and projecting the result gives the rational curve, so a NURBS
+/// curve is just a `BSpline<HomogeneousPoint>` whose points are projected when sampled.
+#[derive(Copy, Clone, Debug)]
+pub struct HomogeneousPoint {
+    pub pos: [f32; 3],
+    pub w: f32,
+}
+impl HomogeneousPoint {
+    /// Make a homogeneous point for the point `p` with weight `w`
+    pub fn new(p: Point, w: f32) -> HomogeneousPoint {
+        HomogeneousPoint { pos: [p.pos[0] * w, p.pos[1] * w, p.pos[2] * w], w: w }
+    }
+    /// Project the point back to 3D by dividing through by the weight
+    pub fn project(&self) -> Point {
+        Point::new(self.pos[0] / self.w, self.pos[1] / self.w, self.pos[2] / self.w)
+    }
+}
+implement_vertex!(HomogeneousPoint, pos, w);
+
+impl ProjectToSegment for HomogeneousPoint {
+    /// Homogeneous points are projected on to the segment by their 3D positions
+    fn project(&self, a: &HomogeneousPoint, b: &HomogeneousPoint) -> (f32, f32) {
+        HomogeneousPoint::project(self).project(&HomogeneousPoint::project(a), &HomogeneousPoint::project(b))
+    }
+}
+
+impl Mul<f32> for HomogeneousPoint {
+    type Output = HomogeneousPoint;
+    fn mul(self, rhs: f32) -> HomogeneousPoint {
+        HomogeneousPoint { pos: [self.pos[0] * rhs, self.pos[1] * rhs, self.pos[2] * rhs], w: self.w * rhs }
+    }
+}
+impl Add for HomogeneousPoint {
+    type Output = HomogeneousPoint;
+    fn add(self, rhs: HomogeneousPoint) -> HomogeneousPoint {
+        HomogeneousPoint { pos: [self.pos[0] + rhs.pos[0], self.pos[1] + rhs.pos[1], self.pos[2] + rhs.pos[2]],
+                           w: self.w + rhs.w }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cross_basis_vectors() {
+        let x = Point::new(1.0, 0.0, 0.0);
+        let y = Point::new(0.0, 1.0, 0.0);
+        let z = Point::new(0.0, 0.0, 1.0);
+        assert_eq!(x.cross(&y).pos, z.pos);
+        assert_eq!(y.cross(&z).pos, x.pos);
+        assert_eq!(z.cross(&x).pos, y.pos);
+        assert_eq!(y.cross(&x).pos, (z * -1.0).pos);
+        assert_eq!(x.cross(&x).pos, [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn normalize() {
+        let n = Point::new(3.0, 0.0, 4.0).normalized();
+        assert!((n.length() - 1.0).abs() < 1e-6);
+        assert!((n.pos[0] - 0.6).abs() < 1e-6 && n.pos[1] == 0.0 && (n.pos[2] - 0.8).abs() < 1e-6);
+        // Zero length vectors stay at zero instead of becoming NaN
+        assert_eq!(Point::new(0.0, 0.0, 0.0).normalized().pos, [0.0, 0.0, 0.0]);
+        assert_eq!(Point::new(1e-20, 0.0, 0.0).normalized().pos, [0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,291 @@
+//! Provides the common interface for the curves and surfaces displayed in the scene,
+//! so the viewer can keep them all in a single list.
+
+use glium::{Frame, Program, DrawParameters, Blend, BlendingFunction, LinearBlendingFactor, VertexBuffer};
+use imgui::Ui;
+
+use bspline::BSpline;
+use bspline_surf::BSplineSurf;
+use point::{self, Point};
+
+/// The curve or surface displayed by a scene object, as read from a file
+pub enum Geometry {
+    /// A curve and whether it's a 3D curve
+    Curve(BSpline<Point>, bool),
+    Surface(BSplineSurf<Point>),
+    /// The curves interpolated by a surface
+    Interpolation(Vec<BSpline<Point>>),
+}
+
+impl Geometry {
+    /// Mirror the geometry through the xy plane to convert it between left and right
+    /// handed coordinates, surfaces keep their faces wound to match their normals.
+    pub fn flip_handedness(&mut self) {
+        match *self {
+            Geometry::Curve(ref mut c, _) => c.flip_handedness(),
+            Geometry::Surface(ref mut s) => s.flip_handedness(),
+            // Reversing the input curves reverses the interpolated surface along them
+            Geometry::Interpolation(ref mut curves) => {
+                for c in curves.iter_mut() {
+                    c.flip_handedness();
+                    c.reverse();
+                }
+            },
+        }
+    }
+}
+
+/// An object which can be rendered in the scene and has its own UI panel
+pub trait SceneObject {
+    /// Render the object, `color_program` is used for geometry with per-vertex colors.
+    /// Unselected objects have their colors scaled by `attenuation`.
+    fn render(&self, target: &mut Frame, program: &Program, color_program: &Program,
+              draw_params: &DrawParameters, proj_view: &[[f32; 4]; 4], selected: bool, attenuation: f32);
+    /// Draw the UI controls for the object
+    fn draw_ui(&mut self, ui: &Ui);
+    /// Get the name the object is listed under in the UI
+    fn name(&self) -> &str;
+    /// Set the name the object is listed under in the UI
+    fn set_name(&mut self, name: String);
+    /// Get the file the object was loaded from and its index among the file's objects,
+    /// if it was loaded from a file
+    fn source(&self) -> Option<(&str, usize)>;
+    /// Set the file the object was loaded from and its index among the file's objects
+    fn set_source(&mut self, path: String, index: usize);
+    /// Replace the displayed curve or surface, e.g. with the one reloaded from its file,
+    /// keeping the display settings. Returns false if it's a different kind of geometry
+    /// than the object displays or it's empty.
+    fn set_geometry(&mut self, geometry: Geometry) -> bool;
+    /// Get the axis-aligned bounds of the object as `(min, max)`, returns None if it's empty
+    fn bounds(&self) -> Option<(Point, Point)>;
+    /// Move the object by `offset`
+    fn translate(&mut self, offset: Point);
+    /// Get the color used to draw the object's curve or surface
+    fn color(&self) -> [f32; 3];
+    /// Set the color used to draw the object's curve or surface
+    fn set_color(&mut self, color: [f32; 3]);
+    /// Get the opacity the object is drawn with, objects below 1 are blended with the
+    /// objects behind them
+    fn alpha(&self) -> f32 {
+        1.0
+    }
+    /// Pin a marker where the ray from `origin` along `dir` hits the object, returns
+    /// true if a marker was placed. Only surfaces support markers.
+    fn pin_marker(&mut self, _origin: &Point, _dir: &Point) -> bool {
+        false
+    }
+    /// Select the row or column of the control mesh with a control point under the ray from
+    /// `origin` along `dir`, returns true if one was picked. Only surfaces can be picked.
+    fn pick_mesh_line(&mut self, _origin: &Point, _dir: &Point) -> bool {
+        false
+    }
+    /// Advance any animations by the time elapsed since the last frame
+    fn update(&mut self, _delta_s: f32) {}
+    /// Take the curve the user asked to extract from the object through its UI, if any,
+    /// so it can be added to the scene as its own object.
+    fn take_extracted_curve(&mut self) -> Option<BSpline<Point>> {
+        None
+    }
+    /// Get the sampled points of a 3D curve to project onto the ground plane as its shadow,
+    /// if it's drawing one
+    fn ground_shadow(&self) -> Option<&VertexBuffer<Point>> {
+        None
+    }
+    /// Get the object as an editable curve, if it is one
+    fn as_editable(&mut self) -> Option<&mut dyn EditableCurve> {
+        None
+    }
+}
+
+/// A curve in the scene which can be edited
+pub trait EditableCurve {
+    /// Get the curve being displayed
+    fn curve(&self) -> &BSpline<Point>;
+    /// Check if this is a 3D curve, which can't be edited by clicking in the 2D view
+    fn is_3d(&self) -> bool;
+    /// Handle a click at `pos` in the 2D view, either moving, adding or removing a point
+    fn handle_click(&mut self, pos: Point, shift_down: bool, zoom_factor: f32);
+    /// Release any held point that was being dragged
+    fn release_point(&mut self);
+    /// Elevate the degree of the curve by one without changing its shape
+    fn elevate_degree(&mut self);
+}
+
+/// Get the order to draw the objects in, the opaque objects are drawn first so the
+/// translucent ones are blended over them.
+pub fn draw_order<'a>(objects: &[Box<dyn SceneObject + 'a>]) -> Vec<usize> {
+    let (mut order, translucent): (Vec<_>, Vec<_>) = (0..objects.len())
+        .partition(|i| objects[*i].alpha() >= 1.0);
+    order.extend(translucent);
+    order
+}
+
+/// Get the draw parameters for an object drawn with opacity `alpha`. Translucent objects
+/// are blended using `alpha` as a constant blend factor, so the shaders don't need to know
+/// about it.
+pub fn alpha_draw_params<'b>(draw_params: &DrawParameters<'b>, alpha: f32) -> DrawParameters<'b> {
+    let mut params = draw_params.clone();
+    if alpha < 1.0 {
+        let blend_fn = BlendingFunction::Addition {
+            source: LinearBlendingFactor::ConstantAlpha,
+            destination: LinearBlendingFactor::OneMinusConstantAlpha,
+        };
+        params.blend = Blend { color: blend_fn, alpha: blend_fn, constant_value: (0.0, 0.0, 0.0, alpha) };
+    }
+    params
+}
+
+/// Compute the selected object index after removing the object at `removed`, the
+/// selection stays on the same object or moves to the previous one if it was removed.
+pub fn selection_after_removal(selected: i32, removed: usize) -> i32 {
+    if selected as usize >= removed && selected != 0 {
+        selected - 1
+    } else {
+        selected
+    }
+}
+
+/// Step the selected object index forward or back through `count` objects, wrapping
+/// around at either end.
+pub fn cycle_selection(selected: i32, count: usize, forward: bool) -> i32 {
+    if count == 0 {
+        return 0;
+    }
+    let count = count as i32;
+    // An out of range selection, e.g. after the scene was cleared, starts over from the ends
+    let selected = if selected < 0 || selected >= count { if forward { -1 } else { count } } else { selected };
+    let step = if forward { 1 } else { -1 };
+    (selected + step + count) % count
+}
+
+/// Get the default color for the `index`'th object added to the scene. The hue steps by
+/// the golden angle so successive colors are well separated, starting from yellow.
+pub fn palette_color(index: usize) -> [f32; 3] {
+    hsv_to_rgb((60.0 + index as f32 * 137.508) % 360.0, 0.875, 0.8)
+}
+
+/// Convert a color in HSV to RGB, the hue is in degrees and saturation and value in [0, 1]
+pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [f32; 3] {
+    let c = v * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = v - c;
+    let (r, g, b) =
+        if h < 60.0 {
+            (c, x, 0.0)
+        } else if h < 120.0 {
+            (x, c, 0.0)
+        } else if h < 180.0 {
+            (0.0, c, x)
+        } else if h < 240.0 {
+            (0.0, x, c)
+        } else if h < 300.0 {
+            (x, 0.0, c)
+        } else {
+            (c, 0.0, x)
+        };
+    [r + m, g + m, b + m]
+}
+
+/// Samples of the viridis colormap at evenly spaced steps from 0 to 1
+const VIRIDIS: [[f32; 3]; 9] = [[0.267004, 0.004874, 0.329415], [0.278826, 0.175490, 0.483397],
+                                [0.229739, 0.322361, 0.545706], [0.172719, 0.448791, 0.557885],
+                                [0.127568, 0.566949, 0.550556], [0.157851, 0.683765, 0.501686],
+                                [0.369214, 0.788888, 0.382914], [0.678489, 0.863742, 0.189503],
+                                [0.993248, 0.906157, 0.143936]];
+
+/// Map `value` to a color in the viridis colormap over the `(min, max)` range, values
+/// outside the range are clamped to its ends.
+pub fn colormap(value: f32, range: (f32, f32)) -> [f32; 3] {
+    let x = if range.1 > range.0 {
+        point::clamp((value - range.0) / (range.1 - range.0), 0.0, 1.0)
+    } else {
+        0.0
+    };
+    let s = x * (VIRIDIS.len() - 1) as f32;
+    let i = usize::min(s as usize, VIRIDIS.len() - 2);
+    let f = s - i as f32;
+    let (a, b) = (VIRIDIS[i], VIRIDIS[i + 1]);
+    [a[0] * (1.0 - f) + b[0] * f, a[1] * (1.0 - f) + b[1] * f, a[2] * (1.0 - f) + b[2] * f]
+}
+
+/// Find the range of `values` to map onto a colormap, the lowest and highest 2% are
+/// left out so a few outliers, e.g. curvature spikes at a singular point, don't wash
+/// out the colors of the rest.
+pub fn scalar_range(values: &[f32]) -> (f32, f32) {
+    let mut sorted: Vec<_> = values.iter().cloned().filter(|x| x.is_finite()).collect();
+    if sorted.is_empty() {
+        return (0.0, 0.0);
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let cut = sorted.len() / 50;
+    (sorted[cut], sorted[sorted.len() - 1 - cut])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn colormap_ends() {
+        assert_eq!(colormap(-2.0, (-2.0, 3.0)), VIRIDIS[0]);
+        assert_eq!(colormap(3.0, (-2.0, 3.0)), VIRIDIS[VIRIDIS.len() - 1]);
+        // Values outside the range are clamped
+        assert_eq!(colormap(-5.0, (-2.0, 3.0)), VIRIDIS[0]);
+        assert_eq!(colormap(10.0, (-2.0, 3.0)), VIRIDIS[VIRIDIS.len() - 1]);
+        assert_eq!(colormap(0.5, (-2.0, 3.0)), VIRIDIS[VIRIDIS.len() / 2]);
+        // A single outlier is left out of the range
+        let mut values: Vec<_> = (0..100).map(|i| i as f32 / 99.0).collect();
+        values[50] = 1000.0;
+        let range = scalar_range(&values[..]);
+        assert!(range.1 <= 1.0, "range {:?} includes the outlier", range);
+    }
+
+    #[test]
+    fn cycle_selection_wraps() {
+        assert_eq!(cycle_selection(0, 3, true), 1);
+        assert_eq!(cycle_selection(2, 3, true), 0);
+        assert_eq!(cycle_selection(0, 3, false), 2);
+        assert_eq!(cycle_selection(1, 3, false), 0);
+        assert_eq!(cycle_selection(0, 1, true), 0);
+        assert_eq!(cycle_selection(0, 0, true), 0);
+        // Out of range selections start from the first or last object
+        assert_eq!(cycle_selection(5, 3, true), 0);
+        assert_eq!(cycle_selection(5, 3, false), 2);
+    }
+
+    #[test]
+    fn selection_after_removal_follows_object() {
+        // Removing an object before the selection shifts it down to stay on the same object
+        assert_eq!(selection_after_removal(2, 0), 1);
+        // Removing the selected object moves to the previous one, or stays on the first
+        assert_eq!(selection_after_removal(1, 1), 0);
+        assert_eq!(selection_after_removal(0, 0), 0);
+        // Removing an object after the selection leaves it unchanged
+        assert_eq!(selection_after_removal(1, 2), 1);
+        // Removing the selected last object selects the new last one
+        assert_eq!(selection_after_removal(2, 2), 1);
+    }
+
+    #[test]
+    fn palette_colors_distinct() {
+        let dist = |a: [f32; 3], b: [f32; 3]| (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f32>().sqrt();
+        // The first color is yellow
+        let first = palette_color(0);
+        assert!((first[0] - first[1]).abs() < 1e-5 && first[2] < first[0]);
+        // The hue wraps around past 360 degrees back to valid colors without repeating
+        let colors: Vec<_> = (0..16).map(palette_color).collect();
+        for (i, a) in colors.iter().enumerate() {
+            for b in &colors[i + 1..] {
+                assert!(dist(*a, *b) > 1e-2, "color {} repeats: {:?} and {:?}", i, a, b);
+            }
+        }
+        for i in (0..16).chain(vec![1000, 100000]) {
+            let c = palette_color(i);
+            assert!(c.iter().all(|&x| x >= 0.0 && x <= 1.0), "color {} out of range: {:?}", i, c);
+        }
+        // Successive colors are well separated
+        for w in colors.windows(2) {
+            assert!(dist(w[0], w[1]) > 0.3, "{:?} and {:?} are too similar", w[0], w[1]);
+        }
+    }
+}